@@ -185,7 +185,7 @@ pub(crate) fn duplicate(
 
         let mut ret = ptr::null_mut();
         cvt(unsafe {
-            let cur_proc = c::GetCurrentProcess();
+            let cur_proc = crate::sys::process::current_process();
             c::DuplicateHandle(
                 cur_proc,
                 handle,
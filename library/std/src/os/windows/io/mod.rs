@@ -48,6 +48,8 @@
 mod raw;
 mod socket;
 
+#[unstable(feature = "windows_stdio", issue = "none")]
+pub use crate::sys::stdio::{is_console, StdStream};
 #[unstable(feature = "io_safety", issue = "87074")]
 pub use handle::*;
 #[stable(feature = "rust1", since = "1.0.0")]
@@ -83,7 +83,7 @@ impl OwnedSocket {
     pub fn try_clone(&self) -> io::Result<Self> {
         let mut info = unsafe { mem::zeroed::<c::WSAPROTOCOL_INFOA>() };
         let result = unsafe {
-            c::WSADuplicateSocketA(self.as_raw_socket(), c::GetCurrentProcessId(), &mut info)
+            c::WSADuplicateSocketA(self.as_raw_socket(), sys::process::current_pid(), &mut info)
         };
         sys::net::cvt(result)?;
         let socket = unsafe {
@@ -116,6 +116,13 @@ pub trait ExitStatusExt: Sealed {
     /// a process.
     #[stable(feature = "exit_status_from", since = "1.12.0")]
     fn from_raw(raw: u32) -> Self;
+
+    /// Returns the full `u32` exit code of the process.
+    ///
+    /// Unlike [`process::ExitStatus::code`], this does not reinterpret the value as signed, which
+    /// makes it easier to inspect exit codes that are really `HRESULT`s or `NTSTATUS`es.
+    #[unstable(feature = "windows_exit_status_raw", issue = "none")]
+    fn into_raw(self) -> u32;
 }
 
 #[stable(feature = "exit_status_from", since = "1.12.0")]
@@ -123,6 +130,10 @@ impl ExitStatusExt for process::ExitStatus {
     fn from_raw(raw: u32) -> Self {
         process::ExitStatus::from_inner(From::from(raw))
     }
+
+    fn into_raw(self) -> u32 {
+        self.as_inner().raw()
+    }
 }
 
 /// Windows-specific extensions to the [`process::Command`] builder.
@@ -125,6 +125,10 @@ fn lang_start_internal(
     // user code from `main` or, more nefariously, as described in e.g. issue #86030.
     // SAFETY: Only called once during runtime initialization.
     panic::catch_unwind(move || unsafe { init(argc, argv) }).map_err(rt_abort)?;
+    // Without vectored exception handlers (before XP), stack overflows are reported from an
+    // exception frame around `main` instead.
+    #[cfg(all(windows, target_arch = "x86", not(target_vendor = "uwp")))]
+    let main = &move || crate::sys::exception::with_exception_frame(main);
     let ret_code = panic::catch_unwind(move || panic::catch_unwind(main).unwrap_or(101) as isize)
         .map_err(move |e| {
             mem::forget(e);
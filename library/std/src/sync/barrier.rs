@@ -2,6 +2,7 @@
 mod tests;
 
 use crate::fmt;
+#[cfg(not(windows))]
 use crate::sync::{Condvar, Mutex};
 
 /// A barrier enables multiple threads to synchronize the beginning
@@ -32,12 +33,19 @@
 /// ```
 #[stable(feature = "rust1", since = "1.0.0")]
 pub struct Barrier {
+    #[cfg(not(windows))]
     lock: Mutex<BarrierState>,
+    #[cfg(not(windows))]
     cvar: Condvar,
+    #[cfg(not(windows))]
     num_threads: usize,
+    // Doesn't rely on the condvar on systems without SRW locks, see the sys barrier.
+    #[cfg(windows)]
+    inner: crate::sys::locks::Barrier,
 }
 
 // The inner state of a double barrier
+#[cfg(not(windows))]
 struct BarrierState {
     count: usize,
     generation_id: usize,
@@ -83,9 +91,14 @@ impl Barrier {
     #[must_use]
     pub fn new(n: usize) -> Barrier {
         Barrier {
+            #[cfg(not(windows))]
             lock: Mutex::new(BarrierState { count: 0, generation_id: 0 }),
+            #[cfg(not(windows))]
             cvar: Condvar::new(),
+            #[cfg(not(windows))]
             num_threads: n,
+            #[cfg(windows)]
+            inner: crate::sys::locks::Barrier::new(n),
         }
     }
 
@@ -124,6 +137,16 @@ pub fn new(n: usize) -> Barrier {
     /// ```
     #[stable(feature = "rust1", since = "1.0.0")]
     pub fn wait(&self) -> BarrierWaitResult {
+        BarrierWaitResult(self.wait_inner())
+    }
+
+    #[cfg(windows)]
+    fn wait_inner(&self) -> bool {
+        self.inner.wait()
+    }
+
+    #[cfg(not(windows))]
+    fn wait_inner(&self) -> bool {
         let mut lock = self.lock.lock().unwrap();
         let local_gen = lock.generation_id;
         lock.count += 1;
@@ -133,12 +156,12 @@ pub fn wait(&self) -> BarrierWaitResult {
             while local_gen == lock.generation_id {
                 lock = self.cvar.wait(lock).unwrap();
             }
-            BarrierWaitResult(false)
+            false
         } else {
             lock.count = 0;
             lock.generation_id = lock.generation_id.wrapping_add(1);
             self.cvar.notify_all();
-            BarrierWaitResult(true)
+            true
         }
     }
 }
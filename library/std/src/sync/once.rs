@@ -160,7 +160,8 @@ pub struct OnceState {
 pub const ONCE_INIT: Once = Once::new();
 
 // Four states that a Once can be in, encoded into the lower bits of
-// `state_and_queue` in the Once structure.
+// `state_and_queue` in the Once structure. The Windows fallback for systems
+// where the thread parker can't block (`sys::windows::once`) uses them too.
 const INCOMPLETE: usize = 0x0;
 const POISONED: usize = 0x1;
 const RUNNING: usize = 0x2;
@@ -382,7 +383,15 @@ pub fn is_completed(&self) -> bool {
         // operations visible to us, and, this being a fast path, weaker
         // ordering helps with performance. This `Acquire` synchronizes with
         // `Release` operations on the slow path.
-        self.state_and_queue.load(Ordering::Acquire).addr() == COMPLETE
+        if self.state_and_queue.load(Ordering::Acquire).addr() == COMPLETE {
+            return true;
+        }
+        // The fallback only stores `COMPLETE` once the last thread left it.
+        #[cfg(windows)]
+        if crate::sys::once::is_needed() {
+            return crate::sys::once::is_completed(&self.state_and_queue);
+        }
+        false
     }
 
     // This is a non-generic function to reduce the monomorphization cost of
@@ -399,6 +408,19 @@ pub fn is_completed(&self) -> bool {
     #[cold]
     #[track_caller]
     fn call_inner(&self, ignore_poisoning: bool, init: &mut dyn FnMut(&OnceState)) {
+        #[cfg(windows)]
+        if crate::sys::once::is_needed() {
+            let mut run = |poisoned| {
+                let init_state = OnceState {
+                    poisoned,
+                    set_state_on_drop_to: Cell::new(ptr::invalid_mut(COMPLETE)),
+                };
+                init(&init_state);
+                init_state.set_state_on_drop_to.get().addr() == COMPLETE
+            };
+            return crate::sys::once::call(&self.state_and_queue, ignore_poisoning, &mut run);
+        }
+
         let mut state_and_queue = self.state_and_queue.load(Ordering::Acquire);
         loop {
             match state_and_queue.addr() {
@@ -0,0 +1,167 @@
+//! 64-bit atomics for CPUs without `cmpxchg8b`.
+//!
+//! The 386 and 486 can't compare-and-swap 8 bytes at once, so these atomics guard their value
+//! with one of a fixed table of spinlocks instead, picked by the address of the value. The
+//! spinlocks only need 32-bit `xchg`, which every x86 has. Every operation holds the lock for a
+//! few instructions at most, and all of them are sequentially consistent.
+//!
+//! Only `compare_exchange` (and `load`/`store`) take the lock directly, the read-modify-write
+//! operations are CAS loops on top of it, like LLVM lowers them on CPUs that do have `cmpxchg8b`.
+//!
+//! `interlocked_cmpxchg64` is a drop-in for `InterlockedCompareExchange64` (Vista+), which picks
+//! `cmpxchg8b` or the lock table depending on the CPU.
+
+#[cfg(test)]
+mod tests;
+
+use crate::cell::UnsafeCell;
+use crate::hint;
+use crate::sync::atomic::{AtomicBool, Ordering};
+use crate::sys::thread::Thread;
+
+const LOCKS: usize = 64;
+
+static LOCK_TABLE: [AtomicBool; LOCKS] = {
+    const UNLOCKED: AtomicBool = AtomicBool::new(false);
+    [UNLOCKED; LOCKS]
+};
+
+fn lock_for(addr: *const u64) -> &'static AtomicBool {
+    // The values are 8-byte aligned, so the lowest 3 bits are always 0.
+    &LOCK_TABLE[(addr as usize >> 3) % LOCKS]
+}
+
+fn with_lock<R>(addr: *const u64, f: impl FnOnce() -> R) -> R {
+    let lock = lock_for(addr);
+    let mut spins = 0;
+    while lock.swap(true, Ordering::Acquire) {
+        // The holder might be a lower priority thread, which needs to get the CPU to finish.
+        if spins < 100 {
+            spins += 1;
+            hint::spin_loop();
+        } else {
+            Thread::yield_now();
+        }
+    }
+    let ret = f();
+    lock.store(false, Ordering::Release);
+    ret
+}
+
+macro_rules! atomic64 {
+    ($name:ident, $int:ty) => {
+        #[repr(C, align(8))]
+        pub struct $name {
+            v: UnsafeCell<$int>,
+        }
+
+        unsafe impl Sync for $name {}
+
+        impl $name {
+            pub const fn new(v: $int) -> $name {
+                $name { v: UnsafeCell::new(v) }
+            }
+
+            fn with_lock<R>(&self, f: impl FnOnce(&mut $int) -> R) -> R {
+                with_lock(self.v.get() as *const u64, || f(unsafe { &mut *self.v.get() }))
+            }
+
+            pub fn load(&self) -> $int {
+                self.with_lock(|v| *v)
+            }
+
+            pub fn store(&self, new: $int) {
+                self.with_lock(|v| *v = new)
+            }
+
+            pub fn compare_exchange(&self, current: $int, new: $int) -> Result<$int, $int> {
+                self.with_lock(|v| {
+                    if *v == current {
+                        *v = new;
+                        Ok(current)
+                    } else {
+                        Err(*v)
+                    }
+                })
+            }
+
+            /// Stores the minimum of the current value and `val`, and returns the previous value.
+            pub fn fetch_min(&self, val: $int) -> $int {
+                self.fetch_update(|v| if val < v { Some(val) } else { None })
+            }
+
+            /// Stores the maximum of the current value and `val`, and returns the previous value.
+            pub fn fetch_max(&self, val: $int) -> $int {
+                self.fetch_update(|v| if val > v { Some(val) } else { None })
+            }
+
+            /// Applies `f` in a CAS loop until it returns `None` or the exchange succeeds, and
+            /// returns the previous value.
+            fn fetch_update(&self, mut f: impl FnMut($int) -> Option<$int>) -> $int {
+                let mut current = self.load();
+                while let Some(new) = f(current) {
+                    match self.compare_exchange(current, new) {
+                        Ok(_) => break,
+                        Err(actual) => current = actual,
+                    }
+                }
+                current
+            }
+        }
+    };
+}
+
+atomic64!(AtomicU64, u64);
+atomic64!(AtomicI64, i64);
+
+/// Compares `*destination` with `comparand`, and replaces it with `exchange` if they are equal.
+/// Returns the initial value of `*destination`, like `InterlockedCompareExchange64`.
+///
+/// # Safety
+///
+/// `destination` must be valid and 8-byte aligned. All concurrent accesses to it must go through
+/// this function, as it might use either `cmpxchg8b` or the lock table, which don't synchronize
+/// with each other (it picks the same one for the lifetime of the process, though).
+pub unsafe fn interlocked_cmpxchg64(destination: *mut i64, exchange: i64, comparand: i64) -> i64 {
+    #[cfg(target_has_atomic = "64")]
+    if has_cmpxchg8b() {
+        use crate::sync::atomic::{AtomicI64, Ordering::SeqCst};
+
+        let atomic = &*(destination as *const AtomicI64);
+        return match atomic.compare_exchange(comparand, exchange, SeqCst, SeqCst) {
+            Ok(initial) | Err(initial) => initial,
+        };
+    }
+    cmpxchg64_locked(destination, exchange, comparand)
+}
+
+unsafe fn cmpxchg64_locked(destination: *mut i64, exchange: i64, comparand: i64) -> i64 {
+    with_lock(destination as *const u64, || {
+        let initial = *destination;
+        if initial == comparand {
+            *destination = exchange;
+        }
+        initial
+    })
+}
+
+/// `cpu::has_cmpxchg8b`, cached as it's called for every exchange.
+#[cfg(target_has_atomic = "64")]
+fn has_cmpxchg8b() -> bool {
+    use crate::sync::atomic::AtomicU8;
+    use crate::sys::cpu;
+
+    const UNKNOWN: u8 = 0;
+    const NO: u8 = 1;
+    const YES: u8 = 2;
+    static HAS_CMPXCHG8B: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+    match HAS_CMPXCHG8B.load(Ordering::Relaxed) {
+        UNKNOWN => {
+            let has = cpu::has_cmpxchg8b();
+            HAS_CMPXCHG8B.store(if has { YES } else { NO }, Ordering::Relaxed);
+            has
+        }
+        cached => cached == YES,
+    }
+}
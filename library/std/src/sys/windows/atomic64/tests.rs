@@ -0,0 +1,95 @@
+use super::{cmpxchg64_locked, interlocked_cmpxchg64, AtomicI64, AtomicU64};
+use crate::cell::UnsafeCell;
+use crate::sync::Arc;
+use crate::thread;
+
+#[test]
+fn min_max_compare_signedness() {
+    let unsigned = AtomicU64::new(1);
+    assert_eq!(unsigned.fetch_max(u64::MAX), 1);
+    assert_eq!(unsigned.fetch_min(2), u64::MAX);
+    assert_eq!(unsigned.load(), 2);
+
+    // -1 would be the maximum if compared as unsigned
+    let signed = AtomicI64::new(1);
+    assert_eq!(signed.fetch_max(-1), 1);
+    assert_eq!(signed.load(), 1);
+    assert_eq!(signed.fetch_min(i64::MIN), 1);
+    assert_eq!(signed.load(), i64::MIN);
+}
+
+#[test]
+fn min_max_across_threads() {
+    const THREADS: u64 = 8;
+    const VALUES: u64 = 1000;
+    // spreads the values over the whole range, so half of them are negative as `i64`
+    const STEP: u64 = u64::MAX / (THREADS * VALUES);
+
+    let (umin, umax) = (Arc::new(AtomicU64::new(u64::MAX)), Arc::new(AtomicU64::new(0)));
+    let (imin, imax) = (Arc::new(AtomicI64::new(i64::MAX)), Arc::new(AtomicI64::new(i64::MIN)));
+
+    let threads: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let (umin, umax, imin, imax) = (umin.clone(), umax.clone(), imin.clone(), imax.clone());
+            thread::spawn(move || {
+                for i in 0..VALUES {
+                    let v = (t * VALUES + i) * STEP;
+                    umin.fetch_min(v);
+                    umax.fetch_max(v);
+                    imin.fetch_min(v as i64);
+                    imax.fetch_max(v as i64);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    let values = || (0..THREADS * VALUES).map(|k| k * STEP);
+    assert_eq!(umin.load(), values().min().unwrap());
+    assert_eq!(umax.load(), values().max().unwrap());
+    assert_eq!(imin.load(), values().map(|v| v as i64).min().unwrap());
+    assert_eq!(imax.load(), values().map(|v| v as i64).max().unwrap());
+    assert!(imin.load() < 0 && imax.load() > 0);
+}
+
+#[test]
+fn contended_cmpxchg64() {
+    const THREADS: i64 = 8;
+    const INCREMENTS: i64 = 10_000;
+
+    // Something that crosses the 32-bit boundary, so a torn exchange would show.
+    const START: i64 = u32::MAX as i64 - THREADS * INCREMENTS / 2;
+
+    for cmpxchg in [interlocked_cmpxchg64, cmpxchg64_locked] {
+        #[repr(align(8))]
+        struct Counter(UnsafeCell<i64>);
+        unsafe impl Sync for Counter {}
+        let counter = Arc::new(Counter(UnsafeCell::new(START)));
+
+        let threads: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let counter = counter.clone();
+                thread::spawn(move || {
+                    let ptr = counter.0.get();
+                    for _ in 0..INCREMENTS {
+                        let mut current = unsafe { cmpxchg(ptr, 0, 0) };
+                        loop {
+                            let initial = unsafe { cmpxchg(ptr, current + 1, current) };
+                            if initial == current {
+                                break;
+                            }
+                            current = initial;
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        assert_eq!(unsafe { *counter.0.get() }, START + THREADS * INCREMENTS);
+    }
+}
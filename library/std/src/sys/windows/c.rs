@@ -5,7 +5,7 @@
 #![unstable(issue = "none", feature = "windows_c")]
 
 use crate::mem;
-use crate::os::raw::{c_char, c_int, c_long, c_longlong, c_uint, c_ulong, c_ushort};
+use crate::os::raw::{c_char, c_int, c_long, c_longlong, c_short, c_uint, c_ulong, c_ushort};
 use crate::os::windows::io::{BorrowedHandle, HandleOrInvalid, HandleOrNull};
 use crate::ptr;
 use core::ffi::NonZero_c_ulong;
@@ -18,8 +18,14 @@
 
 mod wspiapi;
 
+#[cfg(test)]
+pub mod mock;
+#[cfg(test)]
+pub use mock::{GetModuleHandleA, GetProcAddress, GetVersion};
+
 pub use self::EXCEPTION_DISPOSITION::*;
 pub use self::FILE_INFO_BY_HANDLE_CLASS::*;
+pub use self::GET_FILEEX_INFO_LEVELS::*;
 
 pub type DWORD_PTR = ULONG_PTR;
 pub type DWORD = c_ulong;
@@ -36,6 +42,7 @@
 pub type LONG = c_long;
 pub type UINT = c_uint;
 pub type WCHAR = u16;
+pub type SHORT = c_short;
 pub type USHORT = c_ushort;
 pub type SIZE_T = usize;
 pub type WORD = u16;
@@ -45,10 +52,14 @@
 pub type ULONG = c_ulong;
 pub type NTSTATUS = LONG;
 pub type ACCESS_MASK = DWORD;
+pub type HWND = HANDLE;
+pub type WPARAM = usize;
+pub type LPARAM = isize;
 
 pub type LPBOOL = *mut BOOL;
 pub type LPBYTE = *mut BYTE;
 pub type LPCSTR = *const CHAR;
+pub type LPSTR = *mut CHAR;
 pub type LPCWSTR = *const WCHAR;
 pub type LPDWORD = *mut DWORD;
 pub type LPHANDLE = *mut HANDLE;
@@ -56,6 +67,7 @@
 pub type LPPROCESS_INFORMATION = *mut PROCESS_INFORMATION;
 pub type LPSECURITY_ATTRIBUTES = *mut SECURITY_ATTRIBUTES;
 pub type LPSTARTUPINFO = *mut STARTUPINFO;
+pub type LPCH = *mut CHAR;
 pub type LPVOID = *mut c_void;
 pub type LPWCH = *mut WCHAR;
 pub type LPWIN32_FIND_DATAW = *mut WIN32_FIND_DATAW;
@@ -79,6 +91,8 @@
 pub const TRUE: BOOL = 1;
 pub const FALSE: BOOL = 0;
 
+pub const CP_ACP: UINT = 0;
+
 pub const CSTR_LESS_THAN: c_int = 1;
 pub const CSTR_EQUAL: c_int = 2;
 pub const CSTR_GREATER_THAN: c_int = 3;
@@ -88,6 +102,9 @@
 pub const FILE_ATTRIBUTE_REPARSE_POINT: DWORD = 0x400;
 pub const INVALID_FILE_ATTRIBUTES: DWORD = DWORD::MAX;
 
+pub const FILE_SUPPORTS_REPARSE_POINTS: DWORD = 0x00000080;
+pub const FILE_SUPPORTS_HARD_LINKS: DWORD = 0x00400000;
+
 pub const FILE_SHARE_DELETE: DWORD = 0x4;
 pub const FILE_SHARE_READ: DWORD = 0x1;
 pub const FILE_SHARE_WRITE: DWORD = 0x2;
@@ -125,6 +142,7 @@
 pub const SECURITY_SQOS_PRESENT: DWORD = 0x00100000;
 
 pub const FIONBIO: c_ulong = 0x8004667e;
+pub const FIONREAD: c_ulong = 0x4004667f;
 
 #[repr(C)]
 #[derive(Copy)]
@@ -157,6 +175,42 @@ fn clone(&self) -> Self {
 pub const MAX_PROTOCOL_CHAIN: DWORD = 7;
 
 pub const MAXIMUM_REPARSE_DATA_BUFFER_SIZE: usize = 16 * 1024;
+pub const MAX_PATH: usize = 260;
+pub const MAX_MODULE_NAME32: usize = 255;
+
+pub const GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT: DWORD = 0x2;
+pub const GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS: DWORD = 0x4;
+
+pub const TH32CS_SNAPPROCESS: DWORD = 0x2;
+pub const TH32CS_SNAPMODULE: DWORD = 0x8;
+
+#[repr(C)]
+pub struct PROCESSENTRY32 {
+    pub dwSize: DWORD,
+    pub cntUsage: DWORD,
+    pub th32ProcessID: DWORD,
+    pub th32DefaultHeapID: ULONG_PTR,
+    pub th32ModuleID: DWORD,
+    pub cntThreads: DWORD,
+    pub th32ParentProcessID: DWORD,
+    pub pcPriClassBase: LONG,
+    pub dwFlags: DWORD,
+    pub szExeFile: [CHAR; MAX_PATH],
+}
+
+#[repr(C)]
+pub struct MODULEENTRY32 {
+    pub dwSize: DWORD,
+    pub th32ModuleID: DWORD,
+    pub th32ProcessID: DWORD,
+    pub GlblcntUsage: DWORD,
+    pub ProccntUsage: DWORD,
+    pub modBaseAddr: *mut BYTE,
+    pub modBaseSize: DWORD,
+    pub hModule: HMODULE,
+    pub szModule: [CHAR; MAX_MODULE_NAME32 + 1],
+    pub szExePath: [CHAR; MAX_PATH],
+}
 pub const FSCTL_GET_REPARSE_POINT: DWORD = 0x900a8;
 pub const IO_REPARSE_TAG_SYMLINK: DWORD = 0xa000000c;
 pub const IO_REPARSE_TAG_MOUNT_POINT: DWORD = 0xa0000003;
@@ -171,6 +225,11 @@ fn clone(&self) -> Self {
 pub const STD_OUTPUT_HANDLE: DWORD = -11i32 as DWORD;
 pub const STD_ERROR_HANDLE: DWORD = -12i32 as DWORD;
 
+pub const FILE_TYPE_UNKNOWN: DWORD = 0x0000;
+pub const FILE_TYPE_DISK: DWORD = 0x0001;
+pub const FILE_TYPE_CHAR: DWORD = 0x0002;
+pub const FILE_TYPE_PIPE: DWORD = 0x0003;
+
 pub const PROGRESS_CONTINUE: DWORD = 0;
 
 pub const E_NOTIMPL: HRESULT = 0x80004001u32 as HRESULT;
@@ -211,6 +270,7 @@ fn clone(&self) -> Self {
 pub const SO_LINGER: c_int = 0x0080;
 pub const SO_RCVTIMEO: c_int = 0x1006;
 pub const SO_SNDTIMEO: c_int = 0x1005;
+pub const SO_RCVBUF: c_int = 0x1002;
 pub const IPPROTO_IP: c_int = 0;
 pub const IPPROTO_TCP: c_int = 6;
 pub const IPPROTO_IPV6: c_int = 41;
@@ -218,6 +278,8 @@ fn clone(&self) -> Self {
 pub const IP_TTL: c_int = 4;
 pub const IPV6_V6ONLY: c_int = 27;
 pub const SO_ERROR: c_int = 0x1007;
+pub const SO_TYPE: c_int = 0x1008;
+pub const SOMAXCONN: c_int = 0x7fffffff;
 pub const SO_BROADCAST: c_int = 0x0020;
 pub const IP_MULTICAST_LOOP: c_int = 11;
 pub const IPV6_MULTICAST_LOOP: c_int = 11;
@@ -256,8 +318,26 @@ pub struct ipv6_mreq {
 
 pub const WAIT_OBJECT_0: DWORD = 0x00000000;
 pub const WAIT_TIMEOUT: DWORD = 258;
+pub const WAIT_ABANDONED: DWORD = 0x00000080;
+pub const WAIT_IO_COMPLETION: DWORD = 0x000000C0;
 pub const WAIT_FAILED: DWORD = 0xFFFFFFFF;
 
+pub const SEM_FAILCRITICALERRORS: UINT = 0x0001;
+pub const SEM_NOGPFAULTERRORBOX: UINT = 0x0002;
+pub const SEM_NOOPENFILEERRORBOX: UINT = 0x8000;
+
+pub const PAGE_NOACCESS: DWORD = 0x01;
+pub const PAGE_READONLY: DWORD = 0x02;
+pub const PAGE_READWRITE: DWORD = 0x04;
+pub const PAGE_EXECUTE_READ: DWORD = 0x20;
+pub const PAGE_EXECUTE_READWRITE: DWORD = 0x40;
+pub const PAGE_GUARD: DWORD = 0x100;
+pub const PAGE_NOCACHE: DWORD = 0x200;
+pub const PAGE_WRITECOMBINE: DWORD = 0x400;
+pub const MEM_COMMIT: DWORD = 0x1000;
+pub const MEM_RESERVE: DWORD = 0x2000;
+pub const MEM_RELEASE: DWORD = 0x8000;
+
 pub const PIPE_ACCESS_INBOUND: DWORD = 0x00000001;
 pub const PIPE_ACCESS_OUTBOUND: DWORD = 0x00000002;
 pub const FILE_FLAG_FIRST_PIPE_INSTANCE: DWORD = 0x00080000;
@@ -392,6 +472,13 @@ pub struct WIN32_FILE_ATTRIBUTE_DATA {
     pub nFileSizeLow: DWORD,
 }
 
+#[repr(C)]
+#[allow(dead_code)] // we only use some variants
+pub enum GET_FILEEX_INFO_LEVELS {
+    GetFileExInfoStandard = 0,
+    GetFileExMaxInfoLevel = 1,
+}
+
 #[repr(C)]
 #[allow(dead_code)] // we only use some variants
 pub enum FILE_INFO_BY_HANDLE_CLASS {
@@ -616,6 +703,31 @@ pub struct SYSTEM_INFO {
     pub wProcessorRevision: WORD,
 }
 
+#[repr(C)]
+pub struct MEMORYSTATUS {
+    pub dwLength: DWORD,
+    pub dwMemoryLoad: DWORD,
+    pub dwTotalPhys: SIZE_T,
+    pub dwAvailPhys: SIZE_T,
+    pub dwTotalPageFile: SIZE_T,
+    pub dwAvailPageFile: SIZE_T,
+    pub dwTotalVirtual: SIZE_T,
+    pub dwAvailVirtual: SIZE_T,
+}
+
+#[repr(C)]
+pub struct MEMORYSTATUSEX {
+    pub dwLength: DWORD,
+    pub dwMemoryLoad: DWORD,
+    pub ullTotalPhys: u64,
+    pub ullAvailPhys: u64,
+    pub ullTotalPageFile: u64,
+    pub ullAvailPageFile: u64,
+    pub ullTotalVirtual: u64,
+    pub ullAvailVirtual: u64,
+    pub ullAvailExtendedVirtual: u64,
+}
+
 #[repr(C)]
 pub struct OVERLAPPED {
     pub Internal: *mut c_ulong,
@@ -754,6 +866,38 @@ pub struct CONSOLE_READCONSOLE_CONTROL {
 
     pub type PCONSOLE_READCONSOLE_CONTROL = *mut CONSOLE_READCONSOLE_CONTROL;
 
+    pub const CTRL_C_EVENT: DWORD = 0;
+    pub const CTRL_BREAK_EVENT: DWORD = 1;
+    pub const CTRL_CLOSE_EVENT: DWORD = 2;
+
+    pub type PHANDLER_ROUTINE = Option<unsafe extern "system" fn(CtrlType: DWORD) -> BOOL>;
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct COORD {
+        pub X: SHORT,
+        pub Y: SHORT,
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct SMALL_RECT {
+        pub Left: SHORT,
+        pub Top: SHORT,
+        pub Right: SHORT,
+        pub Bottom: SHORT,
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct CONSOLE_SCREEN_BUFFER_INFO {
+        pub dwSize: COORD,
+        pub dwCursorPosition: COORD,
+        pub wAttributes: WORD,
+        pub srWindow: SMALL_RECT,
+        pub dwMaximumWindowSize: COORD,
+    }
+
     #[repr(C)]
     pub struct BY_HANDLE_FILE_INFORMATION {
         pub dwFileAttributes: DWORD,
@@ -794,7 +938,21 @@ pub fn WriteConsoleW(
             lpReserved: LPVOID,
         ) -> BOOL;
 
+        pub fn WriteConsoleA(
+            hConsoleOutput: HANDLE,
+            lpBuffer: LPCVOID,
+            nNumberOfCharsToWrite: DWORD,
+            lpNumberOfCharsWritten: LPDWORD,
+            lpReserved: LPVOID,
+        ) -> BOOL;
+        pub fn GetConsoleOutputCP() -> UINT;
+
         pub fn GetConsoleMode(hConsoleHandle: HANDLE, lpMode: LPDWORD) -> BOOL;
+        pub fn SetConsoleCtrlHandler(HandlerRoutine: PHANDLER_ROUTINE, Add: BOOL) -> BOOL;
+        pub fn GetConsoleScreenBufferInfo(
+            hConsoleOutput: HANDLE,
+            lpConsoleScreenBufferInfo: *mut CONSOLE_SCREEN_BUFFER_INFO,
+        ) -> BOOL;
         // Allowed but unused by UWP
         pub fn GetFileInformationByHandle(
             hFile: HANDLE,
@@ -835,9 +993,51 @@ pub struct FILE_STANDARD_INFO {
     pub fn SetLastError(dwErrCode: DWORD);
     pub fn GetCommandLineW() -> LPWSTR;
     pub fn GetTempPathW(nBufferLength: DWORD, lpBuffer: LPCWSTR) -> DWORD;
+    pub fn GetTempPathA(nBufferLength: DWORD, lpBuffer: LPSTR) -> DWORD;
+    pub fn GetTempFileNameW(
+        lpPathName: LPCWSTR,
+        lpPrefixString: LPCWSTR,
+        uUnique: UINT,
+        lpTempFileName: LPWSTR,
+    ) -> UINT;
+    pub fn GetTempFileNameA(
+        lpPathName: LPCSTR,
+        lpPrefixString: LPCSTR,
+        uUnique: UINT,
+        lpTempFileName: LPSTR,
+    ) -> UINT;
     pub fn GetCurrentProcess() -> HANDLE;
+    pub fn SetErrorMode(uMode: UINT) -> UINT;
+    pub fn GetFileType(hFile: HANDLE) -> DWORD;
+    pub fn PeekNamedPipe(
+        hNamedPipe: HANDLE,
+        lpBuffer: LPVOID,
+        nBufferSize: DWORD,
+        lpBytesRead: LPDWORD,
+        lpTotalBytesAvail: LPDWORD,
+        lpBytesLeftThisMessage: LPDWORD,
+    ) -> BOOL;
+    pub fn FlushInstructionCache(
+        hProcess: HANDLE,
+        lpBaseAddress: *const c_void,
+        dwSize: SIZE_T,
+    ) -> BOOL;
+    pub fn VirtualAlloc(
+        lpAddress: LPVOID,
+        dwSize: SIZE_T,
+        flAllocationType: DWORD,
+        flProtect: DWORD,
+    ) -> LPVOID;
+    pub fn VirtualFree(lpAddress: LPVOID, dwSize: SIZE_T, dwFreeType: DWORD) -> BOOL;
+    pub fn VirtualProtect(
+        lpAddress: LPVOID,
+        dwSize: SIZE_T,
+        flNewProtect: DWORD,
+        lpflOldProtect: LPDWORD,
+    ) -> BOOL;
     pub fn GetCurrentThread() -> HANDLE;
     pub fn GetStdHandle(which: DWORD) -> HANDLE;
+    pub fn SetStdHandle(nStdHandle: DWORD, hHandle: HANDLE) -> BOOL;
     pub fn ExitProcess(uExitCode: c_uint) -> !;
     pub fn DeviceIoControl(
         hDevice: HANDLE,
@@ -858,7 +1058,10 @@ pub fn CreateThread(
         lpThreadId: LPDWORD,
     ) -> HandleOrNull;
     pub fn WaitForSingleObject(hHandle: HANDLE, dwMilliseconds: DWORD) -> DWORD;
+    pub fn WaitForSingleObjectEx(hHandle: HANDLE, dwMilliseconds: DWORD, bAlertable: BOOL)
+    -> DWORD;
     pub fn Sleep(dwMilliseconds: DWORD);
+    pub fn SleepEx(dwMilliseconds: DWORD, bAlertable: BOOL) -> DWORD;
     pub fn FormatMessageW(
         flags: DWORD,
         lpSrc: LPVOID,
@@ -891,7 +1094,11 @@ pub fn CreateProcessW(
     pub fn GetEnvironmentVariableW(n: LPCWSTR, v: LPWSTR, nsize: DWORD) -> DWORD;
     pub fn SetEnvironmentVariableW(n: LPCWSTR, v: LPCWSTR) -> BOOL;
     pub fn GetEnvironmentStringsW() -> LPWCH;
+    pub fn GetEnvironmentVariableA(n: LPCSTR, v: LPSTR, nsize: DWORD) -> DWORD;
+    pub fn SetEnvironmentVariableA(n: LPCSTR, v: LPCSTR) -> BOOL;
+    pub fn GetEnvironmentStringsA() -> LPCH;
     pub fn GetModuleFileNameW(hModule: HMODULE, lpFilename: LPWSTR, nSize: DWORD) -> DWORD;
+    pub fn GetModuleFileNameA(hModule: HMODULE, lpFilename: LPSTR, nSize: DWORD) -> DWORD;
     pub fn CreateDirectoryW(
         lpPathName: LPCWSTR,
         lpSecurityAttributes: LPSECURITY_ATTRIBUTES,
@@ -941,10 +1148,13 @@ pub fn CreateFileW(
     pub fn FindNextFileW(findFile: HANDLE, findFileData: LPWIN32_FIND_DATAW) -> BOOL;
     pub fn FindClose(findFile: HANDLE) -> BOOL;
 
+    #[cfg(not(test))] // see `mock`
     pub fn GetProcAddress(handle: HMODULE, name: LPCSTR) -> *mut c_void;
+    #[cfg(not(test))] // see `mock`
     pub fn GetModuleHandleA(lpModuleName: LPCSTR) -> HMODULE;
 
     pub fn GetSystemInfo(lpSystemInfo: LPSYSTEM_INFO);
+    pub fn GlobalMemoryStatus(lpBuffer: *mut MEMORYSTATUS);
 
     pub fn WaitForMultipleObjects(
         nCount: DWORD,
@@ -976,6 +1186,74 @@ pub fn GetFullPathNameW(
         lpFilePart: *mut LPWSTR,
     ) -> DWORD;
     pub fn GetFileAttributesW(lpFileName: LPCWSTR) -> DWORD;
+    pub fn SetFileTime(
+        hFile: HANDLE,
+        lpCreationTime: *const FILETIME,
+        lpLastAccessTime: *const FILETIME,
+        lpLastWriteTime: *const FILETIME,
+    ) -> BOOL;
+    pub fn CreateFileA(
+        lpFileName: LPCSTR,
+        dwDesiredAccess: DWORD,
+        dwShareMode: DWORD,
+        lpSecurityAttributes: LPSECURITY_ATTRIBUTES,
+        dwCreationDisposition: DWORD,
+        dwFlagsAndAttributes: DWORD,
+        hTemplateFile: HANDLE,
+    ) -> HandleOrInvalid;
+    pub fn CreateDirectoryA(
+        lpPathName: LPCSTR,
+        lpSecurityAttributes: LPSECURITY_ATTRIBUTES,
+    ) -> BOOL;
+    pub fn GetFileAttributesA(lpFileName: LPCSTR) -> DWORD;
+    pub fn GetShortPathNameW(
+        lpszLongPath: LPCWSTR,
+        lpszShortPath: LPWSTR,
+        cchBuffer: DWORD,
+    ) -> DWORD;
+    pub fn GetShortPathNameA(lpszLongPath: LPCSTR, lpszShortPath: LPSTR, cchBuffer: DWORD)
+    -> DWORD;
+    pub fn MoveFileA(lpExistingFileName: LPCSTR, lpNewFileName: LPCSTR) -> BOOL;
+    pub fn DeleteFileA(lpFileName: LPCSTR) -> BOOL;
+    pub fn GetVolumeInformationA(
+        lpRootPathName: LPCSTR,
+        lpVolumeNameBuffer: LPSTR,
+        nVolumeNameSize: DWORD,
+        lpVolumeSerialNumber: LPDWORD,
+        lpMaximumComponentLength: LPDWORD,
+        lpFileSystemFlags: LPDWORD,
+        lpFileSystemNameBuffer: LPSTR,
+        nFileSystemNameSize: DWORD,
+    ) -> BOOL;
+    pub fn GetVolumeInformationW(
+        lpRootPathName: LPCWSTR,
+        lpVolumeNameBuffer: LPWSTR,
+        nVolumeNameSize: DWORD,
+        lpVolumeSerialNumber: LPDWORD,
+        lpMaximumComponentLength: LPDWORD,
+        lpFileSystemFlags: LPDWORD,
+        lpFileSystemNameBuffer: LPWSTR,
+        nFileSystemNameSize: DWORD,
+    ) -> BOOL;
+
+    pub fn WideCharToMultiByte(
+        CodePage: UINT,
+        dwFlags: DWORD,
+        lpWideCharStr: LPCWSTR,
+        cchWideChar: c_int,
+        lpMultiByteStr: LPSTR,
+        cbMultiByte: c_int,
+        lpDefaultChar: LPCSTR,
+        lpUsedDefaultChar: LPBOOL,
+    ) -> c_int;
+    pub fn MultiByteToWideChar(
+        CodePage: UINT,
+        dwFlags: DWORD,
+        lpMultiByteStr: LPCSTR,
+        cbMultiByte: c_int,
+        lpWideCharStr: LPWSTR,
+        cchWideChar: c_int,
+    ) -> c_int;
 }
 
 #[link(name = "ws2_32")]
@@ -1309,6 +1587,23 @@ pub fn SetHandleInformation(hObject: HANDLE,
         FALSE
     }
 
+    // >= NT 3.51+
+    // https://docs.microsoft.com/en-us/windows/win32/api/handleapi/nf-handleapi-gethandleinformation
+    pub fn GetHandleInformation(hObject: HANDLE, lpdwFlags: LPDWORD) -> BOOL {
+        SetLastError(ERROR_CALL_NOT_IMPLEMENTED as DWORD);
+        FALSE
+    }
+
+    // >= 2000
+    // https://docs.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-getvolumepathnamew
+    pub fn GetVolumePathNameW(lpszFileName: LPCWSTR,
+        lpszVolumePathName: LPWSTR,
+        cchBufferLength: DWORD)
+        -> BOOL {
+        SetLastError(ERROR_CALL_NOT_IMPLEMENTED as DWORD);
+        FALSE
+    }
+
     // >= 2000
     // https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-createhardlinkw
     pub fn CreateHardLinkW(lpSymlinkFileName: LPCWSTR,
@@ -1319,6 +1614,16 @@ pub fn CreateHardLinkW(lpSymlinkFileName: LPCWSTR,
         FALSE
     }
 
+    // >= 2000
+    // https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-createhardlinka
+    pub fn CreateHardLinkA(lpSymlinkFileName: LPCSTR,
+        lpTargetFileName: LPCSTR,
+        lpSecurityAttributes: LPSECURITY_ATTRIBUTES)
+        -> BOOL {
+        SetLastError(ERROR_CALL_NOT_IMPLEMENTED as DWORD);
+        FALSE
+    }
+
     // >= NT 4+
     // https://docs.microsoft.com/en-us/windows/win32/api/processthreadsapi/nf-processthreadsapi-switchtothread
     pub fn SwitchToThread() -> BOOL {
@@ -1337,6 +1642,13 @@ pub fn FreeEnvironmentStringsW(env_ptr: LPWCH) -> BOOL {
         TRUE
     }
 
+    // >= NT 3.5+, 95+
+    // https://docs.microsoft.com/en-us/windows/win32/api/processenv/nf-processenv-freeenvironmentstringsa
+    pub fn FreeEnvironmentStringsA(env_ptr: LPCH) -> BOOL {
+        // just leak it on NT 3.1
+        TRUE
+    }
+
     // >= NT 4+, 95+ (with unicows)
     // https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-copyfileexw
     pub fn CopyFileExW(
@@ -1402,7 +1714,19 @@ pub fn CreateEventA(
         lpName: LPCSTR,
     ) -> HANDLE;
 
-    pub fn PulseEvent(hEvent: HANDLE) -> BOOL;
+    pub fn SetEvent(hEvent: HANDLE) -> BOOL;
+    pub fn ResetEvent(hEvent: HANDLE) -> BOOL;
+    pub fn CreateSemaphoreA(
+        lpSemaphoreAttributes: LPSECURITY_ATTRIBUTES,
+        lInitialCount: LONG,
+        lMaximumCount: LONG,
+        lpName: LPCSTR,
+    ) -> HANDLE;
+    pub fn ReleaseSemaphore(
+        hSemaphore: HANDLE,
+        lReleaseCount: LONG,
+        lpPreviousCount: *mut LONG,
+    ) -> BOOL;
 
     pub fn GetSystemTime(lpSystemTime: LPSYSTEMTIME);
     pub fn SystemTimeToFileTime(lpSystemTime: *const SYSTEMTIME, lpFileTime: LPFILETIME) -> BOOL;
@@ -1418,6 +1742,7 @@ pub fn SetFilePointer(
 
     pub fn GetTickCount() -> DWORD;
     pub fn GetCurrentThreadId() -> DWORD;
+    #[cfg(not(test))] // see `mock`
     pub fn GetVersion() -> DWORD;
     pub fn GetFileSize(hFile: HANDLE, lpFileSizeHigh: *mut DWORD) -> DWORD;
     pub fn CopyFileW(
@@ -1425,6 +1750,11 @@ pub fn CopyFileW(
         lpNewFileName: LPCWSTR,
         bFailIfExists: BOOL,
     ) -> BOOL;
+    pub fn CopyFileA(
+        lpExistingFileName: LPCSTR,
+        lpNewFileName: LPCSTR,
+        bFailIfExists: BOOL,
+    ) -> BOOL;
     pub fn CreatePipe(
         hReadPipe: *mut HANDLE,
         hWritePipe: *mut HANDLE,
@@ -1452,7 +1782,64 @@ pub struct SYSTEMTIME {
 pub const INVALID_FILE_SIZE: DWORD = 0xFFFFFFFF;
 
 compat_fn_lazy! {
-    "bcrypt":{unicows: false, load: true}:
+    "kernel32":{load: false}:
+
+    // >= XP
+    // https://docs.microsoft.com/en-us/windows/win32/api/libloaderapi/nf-libloaderapi-getmodulehandleexa
+    pub fn GetModuleHandleExA(dwFlags: DWORD,
+        lpModuleName: LPCSTR,
+        phModule: *mut HMODULE) -> BOOL {
+        rtabort!("unavailable")
+    }
+
+    // >= Vista
+    // https://docs.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-getvolumeinformationbyhandlew
+    pub fn GetVolumeInformationByHandleW(hFile: HANDLE,
+        lpVolumeNameBuffer: LPWSTR,
+        nVolumeNameSize: DWORD,
+        lpVolumeSerialNumber: LPDWORD,
+        lpMaximumComponentLength: LPDWORD,
+        lpFileSystemFlags: LPDWORD,
+        lpFileSystemNameBuffer: LPWSTR,
+        nFileSystemNameSize: DWORD) -> BOOL {
+        rtabort!("unavailable")
+    }
+
+    // >= 95 / 2000 (not on NT 4)
+    // https://docs.microsoft.com/en-us/windows/win32/api/tlhelp32/nf-tlhelp32-createtoolhelp32snapshot
+    pub fn CreateToolhelp32Snapshot(dwFlags: DWORD, th32ProcessID: DWORD) -> HANDLE {
+        rtabort!("unavailable")
+    }
+    pub fn Process32First(hSnapshot: HANDLE, lppe: *mut PROCESSENTRY32) -> BOOL {
+        rtabort!("unavailable")
+    }
+    pub fn Process32Next(hSnapshot: HANDLE, lppe: *mut PROCESSENTRY32) -> BOOL {
+        rtabort!("unavailable")
+    }
+    pub fn Module32First(hSnapshot: HANDLE, lpme: *mut MODULEENTRY32) -> BOOL {
+        rtabort!("unavailable")
+    }
+    pub fn Module32Next(hSnapshot: HANDLE, lpme: *mut MODULEENTRY32) -> BOOL {
+        rtabort!("unavailable")
+    }
+
+    // >= 98 / NT 4
+    // https://docs.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-getfileattributesexa
+    pub fn GetFileAttributesExA(lpFileName: LPCSTR,
+        fInfoLevelId: GET_FILEEX_INFO_LEVELS,
+        lpFileInformation: LPVOID) -> BOOL {
+        rtabort!("unavailable")
+    }
+
+    // >= 2000
+    // https://docs.microsoft.com/en-us/windows/win32/api/sysinfoapi/nf-sysinfoapi-globalmemorystatusex
+    pub fn GlobalMemoryStatusEx(lpBuffer: *mut MEMORYSTATUSEX) -> BOOL {
+        rtabort!("unavailable")
+    }
+}
+
+compat_fn_lazy! {
+    "bcrypt":{load: true}:
 
     // >= Vista / Server 2008
     // https://docs.microsoft.com/en-us/windows/win32/api/bcrypt/nf-bcrypt-bcryptgenrandom
@@ -1471,7 +1858,7 @@ pub fn BCryptGenRandom(
 }
 
 compat_fn_lazy! {
-    "advapi32":{unicows: false, load: true}:
+    "advapi32":{load: true}:
 
     // >= NT 3.1+
     // https://docs.microsoft.com/en-us/windows/win32/api/processthreadsapi/nf-processthreadsapi-openprocesstoken
@@ -1496,7 +1883,7 @@ pub unsafe fn RtlGenRandom(RandomBuffer: *mut u8, RandomBufferLength: ULONG) ->
 }
 
 compat_fn_lazy! {
-    "userenv":{unicows: false, load: true}:
+    "userenv":{load: true}:
 
     // >= NT 4
     // https://docs.microsoft.com/en-us/windows/win32/api/userenv/nf-userenv-getuserprofiledirectoryw
@@ -1507,21 +1894,232 @@ pub fn GetUserProfileDirectoryW(hToken: HANDLE,
     }
 }
 
+pub const QS_ALLINPUT: DWORD = 0x04ff;
+pub const PM_REMOVE: UINT = 0x0001;
+pub const WM_QUIT: UINT = 0x0012;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct POINT {
+    pub x: LONG,
+    pub y: LONG,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct MSG {
+    pub hwnd: HWND,
+    pub message: UINT,
+    pub wParam: WPARAM,
+    pub lParam: LPARAM,
+    pub time: DWORD,
+    pub pt: POINT,
+}
+
+// Only loaded when a thread asks for messages to be pumped and user32 is already there, as loading
+// it makes a thread a GUI thread.
 compat_fn_lazy! {
-    "ws2_32":{unicows: false, load: true}:
-
-    // >= NT4/2000 with IPv6 Tech Preview
-    pub fn getaddrinfo(
-        node: *const c_char,
-        service: *const c_char,
-        hints: *const ADDRINFOA,
-        res: *mut *mut ADDRINFOA
-    ) -> c_int {
-        wship6::getaddrinfo(node, service, hints, res)
+    "user32":{load: true}:
+
+    // >= 95 / NT 3.1
+    // https://docs.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-msgwaitformultipleobjects
+    pub fn MsgWaitForMultipleObjects(
+        nCount: DWORD,
+        pHandles: *const HANDLE,
+        fWaitAll: BOOL,
+        dwMilliseconds: DWORD,
+        dwWakeMask: DWORD
+    ) -> DWORD {
+        rtabort!("unavailable")
+    }
+    // >= 95 / NT 3.1
+    // https://docs.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-peekmessagea
+    pub fn PeekMessageA(
+        lpMsg: *mut MSG,
+        hWnd: HWND,
+        wMsgFilterMin: UINT,
+        wMsgFilterMax: UINT,
+        wRemoveMsg: UINT
+    ) -> BOOL {
+        rtabort!("unavailable")
+    }
+    // >= 95 / NT 3.1
+    // https://docs.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-translatemessage
+    pub fn TranslateMessage(lpMsg: *const MSG) -> BOOL {
+        rtabort!("unavailable")
+    }
+    // >= 95 / NT 3.1
+    // https://docs.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-dispatchmessagea
+    pub fn DispatchMessageA(lpMsg: *const MSG) -> LPARAM {
+        rtabort!("unavailable")
+    }
+    // >= 95 / NT 3.1
+    // https://docs.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-postquitmessage
+    pub fn PostQuitMessage(nExitCode: c_int) -> () {
+        rtabort!("unavailable")
+    }
+}
+
+compat_fn_lazy! {
+    "winmm":{load: true}:
+
+    // >= 95 / NT 3.1, winmm may not be installed on minimal 95 setups
+    // https://docs.microsoft.com/en-us/windows/win32/api/timeapi/nf-timeapi-timegettime
+    pub fn timeGetTime() -> DWORD {
+        rtabort!("unavailable")
+    }
+}
+
+pub const MAX_ADAPTER_NAME_LENGTH: usize = 256;
+pub const MAX_ADAPTER_DESCRIPTION_LENGTH: usize = 128;
+pub const MAX_ADAPTER_ADDRESS_LENGTH: usize = 8;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct IP_ADDRESS_STRING {
+    pub String: [CHAR; 16],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct IP_ADDR_STRING {
+    pub Next: *mut IP_ADDR_STRING,
+    pub IpAddress: IP_ADDRESS_STRING,
+    pub IpMask: IP_ADDRESS_STRING,
+    pub Context: DWORD,
+}
+
+/// Without the trailing `LeaseObtained` and `LeaseExpires`, as the size of their `time_t` differs
+/// between iphlpapi builds. Only ever read through the pointers `GetAdaptersInfo` fills in.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct IP_ADAPTER_INFO {
+    pub Next: *mut IP_ADAPTER_INFO,
+    pub ComboIndex: DWORD,
+    pub AdapterName: [CHAR; MAX_ADAPTER_NAME_LENGTH + 4],
+    pub Description: [CHAR; MAX_ADAPTER_DESCRIPTION_LENGTH + 4],
+    pub AddressLength: UINT,
+    pub Address: [BYTE; MAX_ADAPTER_ADDRESS_LENGTH],
+    pub Index: DWORD,
+    pub Type: UINT,
+    pub DhcpEnabled: UINT,
+    pub CurrentIpAddress: *mut IP_ADDR_STRING,
+    pub IpAddressList: IP_ADDR_STRING,
+    pub GatewayList: IP_ADDR_STRING,
+    pub DhcpServer: IP_ADDR_STRING,
+    pub HaveWins: BOOL,
+    pub PrimaryWinsServer: IP_ADDR_STRING,
+    pub SecondaryWinsServer: IP_ADDR_STRING,
+}
+
+compat_fn_lazy! {
+    "iphlpapi":{load: true}:
+
+    // >= 98 / NT4 SP4, not on 95
+    // https://docs.microsoft.com/en-us/windows/win32/api/iphlpapi/nf-iphlpapi-getadaptersinfo
+    pub fn GetAdaptersInfo(AdapterInfo: *mut IP_ADAPTER_INFO, SizePointer: *mut ULONG) -> DWORD {
+        rtabort!("unavailable")
     }
-    // >= NT4/2000 with IPv6 Tech Preview
-    pub fn freeaddrinfo(res: *mut ADDRINFOA) -> () {
-        wship6::freeaddrinfo(res)
+}
+
+/// Lazily resolved overlapped socket functions for `net::overlapped`, so that it can check for
+/// them. The synchronous socket code imports `WSARecv`/`WSASend` directly.
+pub mod ws2_overlapped {
+    use super::{
+        c_int, BOOL, DWORD, LPDWORD, LPWSABUF, LPWSAOVERLAPPED, LPWSAOVERLAPPED_COMPLETION_ROUTINE,
+        SOCKET,
+    };
+
+    compat_fn_lazy! {
+        // load is not needed, we already link to ws2_32
+        "ws2_32":{load: false}:
+
+        // >= 95 with Winsock 2 / NT 4
+        // https://docs.microsoft.com/en-us/windows/win32/api/winsock2/nf-winsock2-wsarecv
+        pub fn WSARecv(
+            s: SOCKET,
+            lpBuffers: LPWSABUF,
+            dwBufferCount: DWORD,
+            lpNumberOfBytesRecvd: LPDWORD,
+            lpFlags: LPDWORD,
+            lpOverlapped: LPWSAOVERLAPPED,
+            lpCompletionRoutine: LPWSAOVERLAPPED_COMPLETION_ROUTINE
+        ) -> c_int {
+            rtabort!("unavailable")
+        }
+        // >= 95 with Winsock 2 / NT 4
+        // https://docs.microsoft.com/en-us/windows/win32/api/winsock2/nf-winsock2-wsasend
+        pub fn WSASend(
+            s: SOCKET,
+            lpBuffers: LPWSABUF,
+            dwBufferCount: DWORD,
+            lpNumberOfBytesSent: LPDWORD,
+            dwFlags: DWORD,
+            lpOverlapped: LPWSAOVERLAPPED,
+            lpCompletionRoutine: LPWSAOVERLAPPED_COMPLETION_ROUTINE
+        ) -> c_int {
+            rtabort!("unavailable")
+        }
+        // >= 95 with Winsock 2 / NT 4
+        // https://docs.microsoft.com/en-us/windows/win32/api/winsock2/nf-winsock2-wsagetoverlappedresult
+        pub fn WSAGetOverlappedResult(
+            s: SOCKET,
+            lpOverlapped: LPWSAOVERLAPPED,
+            lpcbTransfer: LPDWORD,
+            fWait: BOOL,
+            lpdwFlags: LPDWORD
+        ) -> BOOL {
+            rtabort!("unavailable")
+        }
+    }
+}
+
+// Tests can force the winsock 1 shim, see `mock::force_wspiapi`.
+#[cfg(test)]
+pub use mock::{freeaddrinfo, getaddrinfo};
+#[cfg(not(test))]
+pub use ws2_32::{freeaddrinfo, getaddrinfo};
+
+mod ws2_32 {
+    use super::{c_char, c_int, wship6, ADDRINFOA};
+
+    compat_fn_lazy! {
+        "ws2_32":{load: true}:
+
+        // >= NT4/2000 with IPv6 Tech Preview
+        pub fn getaddrinfo(
+            node: *const c_char,
+            service: *const c_char,
+            hints: *const ADDRINFOA,
+            res: *mut *mut ADDRINFOA
+        ) -> c_int {
+            wship6::getaddrinfo(node, service, hints, res)
+        }
+        // >= NT4/2000 with IPv6 Tech Preview
+        pub fn freeaddrinfo(res: *mut ADDRINFOA) -> () {
+            wship6::freeaddrinfo(res)
+        }
+    }
+}
+
+/// Which `getaddrinfo` the system resolves names with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Resolver {
+    /// ws2_32's own, XP+
+    Ws2_32,
+    /// From the IPv6 Technology Preview for 2000
+    Wship6,
+    /// The winsock 1 shim in `wspiapi`
+    Wspiapi,
+}
+
+pub fn resolver() -> Resolver {
+    if ws2_32::getaddrinfo::available() {
+        Resolver::Ws2_32
+    } else if wship6::getaddrinfo::available() {
+        Resolver::Wship6
+    } else {
+        Resolver::Wspiapi
     }
 }
 
@@ -1530,7 +2128,7 @@ mod wship6 {
     use super::{c_char, c_int, ADDRINFOA};
 
     compat_fn_lazy! {
-        "wship6":{unicows: false, load: true}:
+        "wship6":{load: true}:
 
         // >= 2000 with IPv6 Tech Preview
         pub fn getaddrinfo(
@@ -1790,6 +1790,13 @@
 pub const ERROR_AUDITING_DISABLED: DWORD = 0xC0090001;
 pub const ERROR_ALL_SIDS_FILTERED: DWORD = 0xC0090002;
 
+pub const WSA_INVALID_HANDLE: c_int = ERROR_INVALID_HANDLE as c_int;
+pub const WSA_NOT_ENOUGH_MEMORY: c_int = ERROR_NOT_ENOUGH_MEMORY as c_int;
+pub const WSA_INVALID_PARAMETER: c_int = ERROR_INVALID_PARAMETER as c_int;
+pub const WSA_OPERATION_ABORTED: c_int = ERROR_OPERATION_ABORTED as c_int;
+pub const WSA_IO_INCOMPLETE: c_int = ERROR_IO_INCOMPLETE as c_int;
+pub const WSA_IO_PENDING: c_int = ERROR_IO_PENDING as c_int;
+
 pub const WSABASEERR: c_int = 10000;
 pub const WSAEINTR: c_int = WSABASEERR + 4;
 pub const WSAEBADF: c_int = WSABASEERR + 9;
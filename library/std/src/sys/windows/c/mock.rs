@@ -0,0 +1,152 @@
+//! Swappable Win32 functions for unit tests.
+//!
+//! In test builds, the functions below are what `c` exports under the Win32 names, and they call
+//! a fake instead of the real function while a test has installed one. This allows testing code
+//! paths for old systems (e.g. version detection, or the winsock 1 `getaddrinfo` shim) on any
+//! host.
+//!
+//! A fake only applies to the thread that installed it, as the other tests keep running in
+//! parallel, and only one thread at a time can fake each function (the others wait for it).
+//! Checking for a fake only needs atomics and `GetCurrentThreadId`, so the wrappers are still fine
+//! to call from static initializers.
+//!
+//! The compat loader uses these too, so faking `GetModuleHandleA` and `GetProcAddress` also
+//! changes what the lazily resolved functions resolve to (see `compat::reset`). Faking
+//! `GetModuleHandleA` only affects the lookups of modules that aren't in the compat module cache
+//! yet, and the unicows check.
+
+use super::wspiapi::{hostent, wspiapi_freeaddrinfo, wspiapi_getaddrinfo};
+use super::{c_char, c_int, c_void, GetCurrentThreadId, ADDRINFOA, DWORD, HMODULE, LPCSTR};
+use crate::marker::PhantomData;
+use crate::mem;
+use crate::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use crate::thread;
+
+/// A fake for a function of type `F`, which must be a function pointer.
+pub struct Mock<F> {
+    /// The thread that installed the fake, or 0 (which is never a valid thread ID).
+    owner: AtomicU32,
+    fake: AtomicUsize,
+    _marker: PhantomData<F>,
+}
+
+unsafe impl<F> Sync for Mock<F> {}
+
+impl<F: Copy> Mock<F> {
+    pub const fn new() -> Mock<F> {
+        Mock { owner: AtomicU32::new(0), fake: AtomicUsize::new(0), _marker: PhantomData }
+    }
+
+    /// Makes the calling thread use `fake` until the returned guard is dropped. Blocks while
+    /// another thread has a fake installed, so this must not be nested on the same thread.
+    pub fn install(&'static self, fake: F) -> MockGuard<F> {
+        assert_eq!(mem::size_of::<F>(), mem::size_of::<usize>());
+        let me = unsafe { GetCurrentThreadId() };
+        while self.owner.compare_exchange(0, me, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            thread::yield_now();
+        }
+        self.fake.store(unsafe { mem::transmute_copy(&fake) }, Ordering::Relaxed);
+        MockGuard { mock: self }
+    }
+
+    fn fake(&self) -> Option<F> {
+        // Only the owner itself can observe its own ID here.
+        if self.owner.load(Ordering::Relaxed) != unsafe { GetCurrentThreadId() } {
+            return None;
+        }
+        match self.fake.load(Ordering::Relaxed) {
+            0 => None,
+            fake => Some(unsafe { mem::transmute_copy(&fake) }),
+        }
+    }
+}
+
+pub struct MockGuard<F: 'static> {
+    mock: &'static Mock<F>,
+}
+
+impl<F> Drop for MockGuard<F> {
+    fn drop(&mut self) {
+        self.mock.fake.store(0, Ordering::Relaxed);
+        self.mock.owner.store(0, Ordering::Release);
+    }
+}
+
+pub static GET_MODULE_HANDLE_A: Mock<unsafe extern "system" fn(LPCSTR) -> HMODULE> = Mock::new();
+pub static GET_PROC_ADDRESS: Mock<unsafe extern "system" fn(HMODULE, LPCSTR) -> *mut c_void> =
+    Mock::new();
+pub static GET_VERSION: Mock<unsafe extern "system" fn() -> DWORD> = Mock::new();
+pub static GETHOSTBYNAME: Mock<unsafe extern "system" fn(*const c_char) -> *const hostent> =
+    Mock::new();
+
+type GetAddrInfo =
+    unsafe fn(*const c_char, *const c_char, *const ADDRINFOA, *mut *mut ADDRINFOA) -> c_int;
+type FreeAddrInfo = unsafe fn(*mut ADDRINFOA);
+
+pub static GETADDRINFO: Mock<GetAddrInfo> = Mock::new();
+pub static FREEADDRINFO: Mock<FreeAddrInfo> = Mock::new();
+
+/// Makes the calling thread resolve names with the winsock 1 `getaddrinfo` shim, even if the
+/// system has a real `getaddrinfo`. Lists returned while the guards are alive have to be freed
+/// before dropping them, as they can only be freed by the shim.
+pub fn force_wspiapi() -> (MockGuard<GetAddrInfo>, MockGuard<FreeAddrInfo>) {
+    (GETADDRINFO.install(wspiapi_getaddrinfo), FREEADDRINFO.install(wspiapi_freeaddrinfo))
+}
+
+pub unsafe fn GetModuleHandleA(lpModuleName: LPCSTR) -> HMODULE {
+    match GET_MODULE_HANDLE_A.fake() {
+        Some(fake) => fake(lpModuleName),
+        None => real::GetModuleHandleA(lpModuleName),
+    }
+}
+
+pub unsafe fn GetProcAddress(handle: HMODULE, name: LPCSTR) -> *mut c_void {
+    match GET_PROC_ADDRESS.fake() {
+        Some(fake) => fake(handle, name),
+        None => real::GetProcAddress(handle, name),
+    }
+}
+
+pub unsafe fn GetVersion() -> DWORD {
+    match GET_VERSION.fake() {
+        Some(fake) => fake(),
+        None => real::GetVersion(),
+    }
+}
+
+pub unsafe fn gethostbyname(name: *const c_char) -> *const hostent {
+    match GETHOSTBYNAME.fake() {
+        Some(fake) => fake(name),
+        None => super::wspiapi::gethostbyname(name),
+    }
+}
+
+pub unsafe fn getaddrinfo(
+    node: *const c_char,
+    service: *const c_char,
+    hints: *const ADDRINFOA,
+    res: *mut *mut ADDRINFOA,
+) -> c_int {
+    match GETADDRINFO.fake() {
+        Some(fake) => fake(node, service, hints, res),
+        None => super::ws2_32::getaddrinfo(node, service, hints, res),
+    }
+}
+
+pub unsafe fn freeaddrinfo(res: *mut ADDRINFOA) {
+    match FREEADDRINFO.fake() {
+        Some(fake) => fake(res),
+        None => super::ws2_32::freeaddrinfo(res),
+    }
+}
+
+mod real {
+    use super::super::{c_void, DWORD, HMODULE, LPCSTR};
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn GetModuleHandleA(lpModuleName: LPCSTR) -> HMODULE;
+        pub fn GetProcAddress(handle: HMODULE, name: LPCSTR) -> *mut c_void;
+        pub fn GetVersion() -> DWORD;
+    }
+}
@@ -1,14 +1,58 @@
 //! WSPiApi.h getaddr/freeaddrinfo shim converted to rust
 
 use crate::{
-    ffi::CStr,
+    ffi::{CStr, CString},
+    marker::PhantomData,
+    net::Ipv4Addr,
     ptr,
     sys::c::{
-        in_addr, sockaddr_in, WSAGetLastError, ADDRESS_FAMILY, ADDRINFOA, AF_INET, SOCK_DGRAM,
-        SOCK_STREAM, USHORT,
+        in_addr, sockaddr_in, ADDRESS_FAMILY, ADDRINFOA, AF_INET, SOCK_DGRAM, SOCK_STREAM, USHORT,
     },
+    sys::last_net_error,
+    sys::net::{ntop_v4, pton_v4},
 };
-use libc::{c_char, c_int, c_ulong};
+use libc::{c_char, c_int};
+
+#[cfg(test)]
+mod tests;
+
+// Tests can fake this, see `c::mock`.
+#[cfg(not(test))]
+use self::gethostbyname as host_by_name;
+#[cfg(test)]
+use crate::sys::c::mock::gethostbyname as host_by_name;
+
+// Tests can make allocations fail, and check that all nodes are freed again.
+#[cfg(test)]
+mod alloc_shim {
+    use crate::cell::Cell;
+
+    thread_local! {
+        /// How many more allocations succeed, `None` for no limit.
+        pub static REMAINING: Cell<Option<usize>> = Cell::new(None);
+        /// Nodes allocated and not freed yet.
+        pub static LIVE_NODES: Cell<isize> = Cell::new(0);
+    }
+
+    pub fn allocate() -> bool {
+        let allowed = REMAINING.with(|remaining| match remaining.get() {
+            Some(0) => false,
+            Some(n) => {
+                remaining.set(Some(n - 1));
+                true
+            }
+            None => true,
+        });
+        if allowed {
+            LIVE_NODES.with(|live| live.set(live.get() + 1));
+        }
+        allowed
+    }
+
+    pub fn free() {
+        LIVE_NODES.with(|live| live.set(live.get() - 1));
+    }
+}
 
 const WSABASEERR: c_int = 10000;
 const WSAHOST_NOT_FOUND: c_int = WSABASEERR + 1001;
@@ -42,8 +86,17 @@
 const INADDR_ANY: u32 = 0x00000000;
 const INADDR_LOOPBACK: u32 = 0x7f000001;
 
+/// The address (in host byte order) returned for a null node without `AI_PASSIVE`. Passive
+/// lookups always get `INADDR_ANY`.
+const NON_PASSIVE_ADDRESS: u32 = INADDR_LOOPBACK;
+
 const NI_MAXHOST: usize = 1025;
 
+/// Only accept numeric hosts in plain dotted decimal form, instead of everything `inet_addr`
+/// takes. `inet_addr` reads octets with a leading zero as octal (`010` is 8) and also accepts
+/// hexadecimal ones, so other parsers may disagree on which address a string means.
+const STRICT_V4_PARSING: bool = false;
+
 pub unsafe fn wspiapi_freeaddrinfo(mut head: *mut ADDRINFOA) {
     let mut next_ptr = head;
 
@@ -64,10 +117,64 @@ pub unsafe fn wspiapi_freeaddrinfo(mut head: *mut ADDRINFOA) {
         }
 
         drop(Box::<ADDRINFOA>::from_raw(next_ptr));
+        #[cfg(test)]
+        alloc_shim::free();
         next_ptr = head;
     }
 }
 
+/// Owns a list of `ADDRINFOA` nodes built by the shim, and frees it with `wspiapi_freeaddrinfo`
+/// when dropped.
+pub struct AddrInfoList(*mut ADDRINFOA);
+
+impl AddrInfoList {
+    pub const fn empty() -> Self {
+        Self(ptr::null_mut())
+    }
+
+    /// Takes ownership of a list returned by `wspiapi_getaddrinfo`.
+    ///
+    /// # Safety
+    ///
+    /// `head` must be null or the head of such a list, which must not be freed otherwise.
+    pub unsafe fn from_raw(head: *mut ADDRINFOA) -> Self {
+        Self(head)
+    }
+
+    /// Gives up ownership, for freeing with `wspiapi_freeaddrinfo` later.
+    pub fn into_raw(self) -> *mut ADDRINFOA {
+        let head = self.0;
+        crate::mem::forget(self);
+        head
+    }
+
+    pub fn iter(&self) -> AddrInfoIter<'_> {
+        AddrInfoIter { next: self.0, list: PhantomData }
+    }
+}
+
+impl Drop for AddrInfoList {
+    fn drop(&mut self) {
+        unsafe { wspiapi_freeaddrinfo(self.0) }
+    }
+}
+
+pub struct AddrInfoIter<'a> {
+    next: *const ADDRINFOA,
+    list: PhantomData<&'a AddrInfoList>,
+}
+
+impl<'a> Iterator for AddrInfoIter<'a> {
+    type Item = &'a ADDRINFOA;
+
+    fn next(&mut self) -> Option<&'a ADDRINFOA> {
+        // SAFETY: the nodes live as long as the list they're borrowed from
+        let node = unsafe { self.next.as_ref()? };
+        self.next = node.ai_next;
+        Some(node)
+    }
+}
+
 /// Protocol-independent name-to-address translation.
 ///
 /// As specified in RFC 2553, Section 6.4.
@@ -145,12 +252,20 @@ pub unsafe fn wspiapi_getaddrinfo(
 
     // do service lookup
     if !service.is_null() {
-        if let Some(raw_port) =
-            CStr::from_ptr(service).to_str().ok().and_then(|s| s.parse::<c_ulong>().ok())
-        {
+        let service_name = CStr::from_ptr(service).to_bytes();
+        if service_name.is_empty() {
+            // same as a service that doesn't exist
+            return if socket_type != 0 { EAI_SERVICE } else { EAI_NONAME };
+        }
+
+        if let Some(numeric) = parse_numeric_service(service_name) {
             // numeric port string
 
-            port = (raw_port as USHORT).to_be();
+            let raw_port = match numeric {
+                NumericService::Port(raw_port) => raw_port,
+                NumericService::Invalid => return EAI_SERVICE,
+            };
+            port = raw_port.to_be();
             udp_port = port;
 
             if socket_type == 0 {
@@ -201,28 +316,36 @@ pub unsafe fn wspiapi_getaddrinfo(
     // return the binary address.
     //
 
+    // the constants are in host byte order, `to_be` turns them into network byte order
     let address: Option<u32> = if node.is_null() {
-        Some((if flags & AI_PASSIVE != 0 { INADDR_ANY } else { INADDR_LOOPBACK }).to_be())
+        Some((if flags & AI_PASSIVE != 0 { INADDR_ANY } else { NON_PASSIVE_ADDRESS }).to_be())
     } else {
         wspiapi_parse_v4_address(CStr::from_ptr(node))
     };
 
     let mut error: i32 = 0;
 
+    // freed on every error return
+    let mut list = AddrInfoList::empty();
+
     if let Some(address) = address {
         // create an addrinfo structure...
-        *res = wspiapi_new_addr_info(socket_type, protocol, port, address);
+        list.0 = wspiapi_new_addr_info(socket_type, protocol, port, address);
 
-        if error != 0 && !node.is_null() {
+        if list.0.is_null() {
+            error = EAI_MEMORY;
+        } else if !node.is_null() {
             // implementation specific behavior: set AI_NUMERICHOST
             // to indicate that we got a numeric host address string.
-            (**res).ai_flags |= AI_NUMERICHOST;
+            (*list.0).ai_flags |= AI_NUMERICHOST;
 
             // return the numeric address string as the canonical name
             if flags & AI_CANONNAME != 0 {
-                (**res).ai_canonname = wspiapi_strdup(inet_ntoa(in_addr { s_addr: address }));
+                let name = ntop_v4(&Ipv4Addr::from(u32::from_be(address)));
+                (*list.0).ai_canonname =
+                    CString::new(name).map_or(ptr::null_mut(), |name| name.into_raw());
 
-                if (**res).ai_canonname.is_null() {
+                if (*list.0).ai_canonname.is_null() {
                     error = EAI_MEMORY;
                 }
             }
@@ -240,20 +363,20 @@ pub unsafe fn wspiapi_getaddrinfo(
             protocol,
             port,
             flags & AI_CANONNAME != 0,
-            res,
+            &mut list.0,
         );
     }
 
     if error == 0 && clone {
-        error = wspiapi_clone(udp_port, *res);
+        error = wspiapi_clone(udp_port, list.0);
     }
 
     if error != 0 {
-        wspiapi_freeaddrinfo(*res);
-        *res = ptr::null_mut();
+        return error;
     }
 
-    return error;
+    *res = list.into_raw();
+    0
 }
 
 unsafe fn wspiapi_clone(udp_port: USHORT, res: *mut ADDRINFOA) -> i32 {
@@ -269,6 +392,9 @@ unsafe fn wspiapi_clone(udp_port: USHORT, res: *mut ADDRINFOA) -> i32 {
             udp_port,
             (*(next.ai_addr as *mut sockaddr_in)).sin_addr.s_addr,
         );
+        if new_ptr.is_null() {
+            return EAI_MEMORY;
+        }
         let new = &mut *new_ptr;
 
         // link the cloned addrinfo
@@ -280,6 +406,31 @@ unsafe fn wspiapi_clone(udp_port: USHORT, res: *mut ADDRINFOA) -> i32 {
     0
 }
 
+enum NumericService {
+    Port(u16),
+    /// Negative, or too large for a port.
+    Invalid,
+}
+
+/// Parses a decimal service string, with an optional sign. Returns `None` for anything else,
+/// which is a service name then.
+fn parse_numeric_service(service: &[u8]) -> Option<NumericService> {
+    let (negative, digits) = match service {
+        [b'-', digits @ ..] => (true, digits),
+        [b'+', digits @ ..] => (false, digits),
+        digits => (false, digits),
+    };
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    // `from_utf8` can't fail, it's all digits
+    let port = crate::str::from_utf8(digits).ok()?.parse::<u16>();
+    Some(match port {
+        Ok(port) if !negative => NumericService::Port(port),
+        _ => NumericService::Invalid,
+    })
+}
+
 /// Resolve a nodename and return a list of addrinfo structures.
 /// IPv4 specific internal function, not exported.
 ///
@@ -369,27 +520,18 @@ unsafe fn wspiapi_query_dns(
 
     alias_ref[0] = b'\0';
 
-    let host = gethostbyname(node.as_ptr());
+    let host = host_by_name(node.as_ptr());
     if let Some(host) = ptr::NonNull::<hostent>::new(host as *mut _) {
         let host = host.as_ref();
 
-        if host.h_addrtype == AF_INET as USHORT
-            && host.h_length == crate::mem::size_of::<in_addr>() as USHORT
-        {
-            let mut addresses = host.h_addr_list;
-
-            while !(*addresses).is_null() {
-                *next = wspiapi_new_addr_info(
-                    socket_type,
-                    protocol,
-                    port,
-                    (*((*addresses) as *const in_addr)).s_addr,
-                );
-
-                next = ptr::addr_of_mut!((**next).ai_next);
-
-                addresses = addresses.add(1);
+        for address in HostentAddrs::new(host) {
+            let address = u32::from_ne_bytes(address.octets());
+            *next = wspiapi_new_addr_info(socket_type, protocol, port, address);
+            if (*next).is_null() {
+                return EAI_MEMORY;
             }
+
+            next = ptr::addr_of_mut!((**next).ai_next);
         }
 
         wspiapi_strcpy_ni_maxhost(alias_ref, CStr::from_ptr(host.h_name).to_bytes());
@@ -397,38 +539,51 @@ unsafe fn wspiapi_query_dns(
         return 0;
     }
 
-    match WSAGetLastError() {
-        WSAHOST_NOT_FOUND => EAI_NONAME,
-        WSATRY_AGAIN => EAI_AGAIN,
-        WSANO_RECOVERY => EAI_FAIL,
-        WSANO_DATA => EAI_NODATA,
+    match last_net_error().raw_os_error() {
+        Some(WSAHOST_NOT_FOUND) => EAI_NONAME,
+        Some(WSATRY_AGAIN) => EAI_AGAIN,
+        Some(WSANO_RECOVERY) => EAI_FAIL,
+        Some(WSANO_DATA) => EAI_NODATA,
         _ => EAI_NONAME,
     }
 }
 
+/// Returns null if out of memory.
 unsafe fn wspiapi_new_addr_info(
     socket_type: i32,
     protocol: i32,
     port: USHORT,
     address: u32,
 ) -> *mut ADDRINFOA {
-    let sockaddr = box sockaddr_in {
+    #[cfg(test)]
+    if !alloc_shim::allocate() {
+        return ptr::null_mut();
+    }
+
+    let sockaddr = match Box::try_new(sockaddr_in {
         sin_family: AF_INET as ADDRESS_FAMILY,
         sin_port: port,
         sin_addr: in_addr { s_addr: address },
         sin_zero: [0; 8],
+    }) {
+        Ok(sockaddr) => sockaddr,
+        Err(_) => return ptr::null_mut(),
     };
 
-    let new = box ADDRINFOA {
+    let mut new = match Box::try_new(ADDRINFOA {
         ai_family: PF_INET,
         ai_socktype: socket_type,
         ai_protocol: protocol,
         ai_addrlen: crate::mem::size_of::<sockaddr_in>(),
-        ai_addr: Box::into_raw(sockaddr) as *mut _,
+        ai_addr: ptr::null_mut(),
         ai_canonname: ptr::null_mut(),
         ai_flags: 0,
         ai_next: ptr::null_mut(),
+    }) {
+        Ok(new) => new,
+        Err(_) => return ptr::null_mut(),
     };
+    new.ai_addr = Box::into_raw(sockaddr) as *mut _;
 
     Box::into_raw(new)
 }
@@ -443,6 +598,10 @@ unsafe fn wspiapi_new_addr_info(
 /// Return Value
 /// - Returns FALSE if there is an error, TRUE for success.
 fn wspiapi_parse_v4_address(address: &CStr) -> Option<u32> {
+    parse_v4_address(address, STRICT_V4_PARSING)
+}
+
+fn parse_v4_address(address: &CStr, strict: bool) -> Option<u32> {
     // ensure there are 3 '.' (periods)
     if address.to_bytes().iter().filter(|&&c| c == b'.').count() != 3 {
         return None;
@@ -450,7 +609,12 @@ fn wspiapi_parse_v4_address(address: &CStr) -> Option<u32> {
 
     // return an error if dwAddress is INADDR_NONE (255.255.255.255)
     // since this is never a valid argument to getaddrinfo.
-    let addr: u32 = unsafe { inet_addr(address.as_ptr()) };
+    let addr: u32 = if strict {
+        let address = pton_v4(crate::str::from_utf8(address.to_bytes()).ok()?)?;
+        u32::from_ne_bytes(address.octets())
+    } else {
+        unsafe { inet_addr(address.as_ptr()) }
+    };
 
     const INADDR_NONE: u32 = 0xffffffff;
     if addr == INADDR_NONE {
@@ -460,6 +624,23 @@ fn wspiapi_parse_v4_address(address: &CStr) -> Option<u32> {
     return Some(addr);
 }
 
+/// Looks up the host name of an IPv4 address (in network byte order).
+///
+/// Returns `None` if the address has no name (`WSAHOST_NOT_FOUND`), or the lookup failed.
+pub fn wspiapi_reverse_lookup(addr_be: u32) -> Option<CString> {
+    let addr = in_addr { s_addr: addr_be };
+    unsafe {
+        let host = gethostbyaddr(
+            &addr as *const in_addr as *const c_char,
+            crate::mem::size_of::<in_addr>() as c_int,
+            AF_INET,
+        );
+        // copy the name right away, the next call on this thread reuses the `hostent`
+        ptr::NonNull::new(host as *mut hostent)
+            .map(|host| CStr::from_ptr(host.as_ref().h_name).to_owned())
+    }
+}
+
 unsafe fn wspiapi_strdup(string: *const c_char) -> *mut c_char {
     if string.is_null() { ptr::null_mut() } else { CStr::from_ptr(string).to_owned().into_raw() }
 }
@@ -488,9 +669,52 @@ pub struct hostent {
     h_addr_list: *const *const c_char,
 }
 
+/// The IPv4 addresses of a `hostent`, up to the null that ends `h_addr_list`. Yields nothing for
+/// other address types, or if `h_length` isn't the size of an IPv4 address.
+pub struct HostentAddrs<'a> {
+    /// Null once done.
+    next: *const *const c_char,
+    _host: PhantomData<&'a hostent>,
+}
+
+impl<'a> HostentAddrs<'a> {
+    /// # Safety
+    ///
+    /// `host.h_addr_list` has to be a null-terminated list of pointers to `h_length` bytes each,
+    /// as Winsock returns it, and must stay valid for `'a`.
+    pub unsafe fn new(host: &'a hostent) -> HostentAddrs<'a> {
+        let is_v4 = host.h_addrtype == AF_INET as USHORT
+            && host.h_length as usize == crate::mem::size_of::<in_addr>();
+        let next = if is_v4 { host.h_addr_list } else { ptr::null() };
+        HostentAddrs { next, _host: PhantomData }
+    }
+}
+
+impl Iterator for HostentAddrs<'_> {
+    type Item = Ipv4Addr;
+
+    fn next(&mut self) -> Option<Ipv4Addr> {
+        if self.next.is_null() {
+            return None;
+        }
+        unsafe {
+            let address = *self.next;
+            if address.is_null() {
+                self.next = ptr::null();
+                return None;
+            }
+            self.next = self.next.add(1);
+            // the addresses aren't necessarily aligned
+            let mut octets = [0u8; 4];
+            ptr::copy_nonoverlapping(address as *const u8, octets.as_mut_ptr(), octets.len());
+            Some(Ipv4Addr::from(octets))
+        }
+    }
+}
+
 compat_fn_lazy! {
     // load is not needed, we already need ws2_32 to get here
-    "ws2_32":{unicows: false, load: false}:
+    "ws2_32":{load: false}:
     /// The pointer that is returned points to the SERVENT structure allocated by the
     /// Windows Sockets library. The application must never attempt to modify this
     /// structure or to free any of its components. Furthermore only one copy of this
@@ -510,10 +734,12 @@ pub fn getservbyname(name: *const c_char, proto: *const c_char) -> *const serven
     pub fn gethostbyname(name: *const c_char) -> *const hostent {
         rtabort!("unavailable")
     }
-    pub fn inet_addr(cp: *const c_char) -> u32 {
+    /// Like `gethostbyname`, the returned structure is reused by the next call on the same
+    /// thread.
+    pub fn gethostbyaddr(addr: *const c_char, len: c_int, r#type: c_int) -> *const hostent {
         rtabort!("unavailable")
     }
-    pub fn inet_ntoa(r#in: in_addr) -> *const c_char {
+    pub fn inet_addr(cp: *const c_char) -> u32 {
         rtabort!("unavailable")
     }
 }
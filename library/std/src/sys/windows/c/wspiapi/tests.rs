@@ -0,0 +1,234 @@
+use super::{
+    alloc_shim, hostent, parse_v4_address, wspiapi_getaddrinfo, wspiapi_query_dns,
+    wspiapi_reverse_lookup, AddrInfoList, HostentAddrs, AI_CANONNAME, AI_NUMERICHOST, AI_PASSIVE,
+    EAI_MEMORY, EAI_NONAME, EAI_SERVICE, NI_MAXHOST,
+};
+use crate::ffi::CStr;
+use crate::ptr;
+use crate::sys::c::{mock, sockaddr_in, ADDRINFOA, AF_INET, SOCK_DGRAM, SOCK_STREAM, USHORT};
+use libc::c_char;
+
+static ADDRESSES: [[u8; 4]; 2] = [[192, 0, 2, 1], [192, 0, 2, 2]];
+
+unsafe extern "system" fn fake_gethostbyname(name: *const c_char) -> *const hostent {
+    assert_eq!(CStr::from_ptr(name).to_bytes(), b"example.test");
+    // winsock owns the result and never frees it, neither do we
+    let addr_list = Box::leak(Box::new([
+        ADDRESSES[0].as_ptr() as *const c_char,
+        ADDRESSES[1].as_ptr() as *const c_char,
+        ptr::null(),
+    ]));
+    Box::leak(Box::new(hostent {
+        h_name: b"canonical.example.test\0".as_ptr() as *const c_char,
+        h_aliases: ptr::null(),
+        h_addrtype: AF_INET as USHORT,
+        h_length: 4,
+        h_addr_list: addr_list.as_ptr(),
+    }))
+}
+
+#[test]
+fn query_dns_with_fake_gethostbyname() {
+    let _guard = mock::GETHOSTBYNAME.install(fake_gethostbyname);
+
+    let node = CStr::from_bytes_with_nul(b"example.test\0").unwrap();
+    let port = 80u16.to_be();
+    let mut alias = [0; NI_MAXHOST];
+    let mut res = ptr::null_mut();
+    let error = unsafe { wspiapi_query_dns(node, SOCK_STREAM, 0, port, &mut alias, &mut res) };
+    assert_eq!(error, 0);
+
+    let alias_len = alias.iter().position(|&b| b == 0).unwrap();
+    assert_eq!(&alias[..alias_len], b"canonical.example.test");
+
+    let list = unsafe { AddrInfoList::from_raw(res) };
+    let mut found = Vec::new();
+    for node in list.iter() {
+        let addr = unsafe { &*(node.ai_addr as *const sockaddr_in) };
+        assert_eq!(addr.sin_port, port);
+        assert_eq!(node.ai_socktype, SOCK_STREAM);
+        found.push(addr.sin_addr.s_addr.to_ne_bytes());
+    }
+    assert_eq!(found, ADDRESSES);
+}
+
+/// Resolves `example.test` with only `allocations` nodes allocated successfully, returning the
+/// error and the number of nodes that weren't freed.
+fn getaddrinfo_with_allocations(service: Option<&[u8]>, allocations: usize) -> (i32, isize) {
+    alloc_shim::REMAINING.with(|remaining| remaining.set(Some(allocations)));
+    let mut res = ptr::null_mut();
+    let error = unsafe {
+        wspiapi_getaddrinfo(
+            b"example.test\0".as_ptr() as *const c_char,
+            service.map_or(ptr::null(), |service| service.as_ptr() as *const c_char),
+            ptr::null(),
+            &mut res,
+        )
+    };
+    alloc_shim::REMAINING.with(|remaining| remaining.set(None));
+    if error != 0 {
+        assert!(res.is_null());
+    }
+    drop(unsafe { AddrInfoList::from_raw(res) });
+    (error, alloc_shim::LIVE_NODES.with(|live| live.replace(0)))
+}
+
+#[test]
+fn no_leak_on_allocation_failure() {
+    let _guard = mock::GETHOSTBYNAME.install(fake_gethostbyname);
+
+    // fails on the second address
+    assert_eq!(getaddrinfo_with_allocations(None, 1), (EAI_MEMORY, 0));
+    // fails while adding the UDP copies of both addresses
+    assert_eq!(getaddrinfo_with_allocations(Some(b"80\0"), 3), (EAI_MEMORY, 0));
+    assert_eq!(getaddrinfo_with_allocations(Some(b"80\0"), 4), (0, 0));
+}
+
+#[test]
+fn addr_info_list_iterates_all_nodes() {
+    let _guard = mock::GETHOSTBYNAME.install(fake_gethostbyname);
+
+    let mut res = ptr::null_mut();
+    let error = unsafe {
+        wspiapi_getaddrinfo(
+            b"example.test\0".as_ptr() as *const c_char,
+            b"80\0".as_ptr() as *const c_char,
+            ptr::null(),
+            &mut res,
+        )
+    };
+    assert_eq!(error, 0);
+    let list = unsafe { AddrInfoList::from_raw(res) };
+    let socket_types: Vec<_> = list.iter().map(|node| node.ai_socktype).collect();
+    assert_eq!(socket_types, [SOCK_STREAM, SOCK_DGRAM, SOCK_STREAM, SOCK_DGRAM]);
+}
+
+#[test]
+fn numeric_host_is_its_own_canonical_name() {
+    crate::sys::net::init();
+
+    let hints = ADDRINFOA {
+        ai_flags: AI_CANONNAME,
+        ai_family: 0,
+        ai_socktype: 0,
+        ai_protocol: 0,
+        ai_addrlen: 0,
+        ai_canonname: ptr::null_mut(),
+        ai_addr: ptr::null_mut(),
+        ai_next: ptr::null_mut(),
+    };
+    let mut res = ptr::null_mut();
+    let error = unsafe {
+        wspiapi_getaddrinfo(b"1.2.3.4\0".as_ptr() as *const c_char, ptr::null(), &hints, &mut res)
+    };
+    assert_eq!(error, 0);
+    let list = unsafe { AddrInfoList::from_raw(res) };
+    let node = list.iter().next().unwrap();
+    assert_ne!(node.ai_flags & AI_NUMERICHOST, 0);
+    assert_eq!(unsafe { CStr::from_ptr(node.ai_canonname) }.to_bytes(), b"1.2.3.4");
+}
+
+#[test]
+fn strict_v4_parsing() {
+    crate::sys::net::init();
+
+    let parse = |address: &[u8], strict| {
+        parse_v4_address(CStr::from_bytes_with_nul(address).unwrap(), strict).map(u32::to_ne_bytes)
+    };
+    // (address, strict, loose)
+    let table: [(&[u8], _, _); 8] = [
+        (b"1.2.3.4\0", Some([1, 2, 3, 4]), Some([1, 2, 3, 4])),
+        (b"0.0.0.0\0", Some([0, 0, 0, 0]), Some([0, 0, 0, 0])),
+        (b"010.0.0.1\0", None, Some([8, 0, 0, 1])),
+        (b"0x7f.0.0.1\0", None, Some([127, 0, 0, 1])),
+        (b"1.2.3.256\0", None, None),
+        (b"1.2..4\0", None, None),
+        (b"1.2.3.4.5\0", None, None),
+        (b"255.255.255.255\0", None, None),
+    ];
+    for (address, strict, loose) in table {
+        assert_eq!(parse(address, true), strict, "{:?}", address);
+        assert_eq!(parse(address, false), loose, "{:?}", address);
+    }
+}
+
+#[test]
+fn reverse_lookup_loopback() {
+    crate::sys::net::init();
+
+    // whether loopback has a name depends on the hosts file
+    if let Some(name) = wspiapi_reverse_lookup(u32::from_ne_bytes([127, 0, 0, 1])) {
+        assert!(!name.as_bytes().is_empty());
+    }
+}
+
+#[test]
+fn null_node_addresses_are_in_network_byte_order() {
+    let resolve = |flags| {
+        let hints = ADDRINFOA {
+            ai_flags: flags,
+            ai_family: 0,
+            ai_socktype: SOCK_STREAM,
+            ai_protocol: 0,
+            ai_addrlen: 0,
+            ai_canonname: ptr::null_mut(),
+            ai_addr: ptr::null_mut(),
+            ai_next: ptr::null_mut(),
+        };
+        let mut res = ptr::null_mut();
+        let service = b"80\0".as_ptr() as *const c_char;
+        assert_eq!(unsafe { wspiapi_getaddrinfo(ptr::null(), service, &hints, &mut res) }, 0);
+        let list = unsafe { AddrInfoList::from_raw(res) };
+        list.iter()
+            .map(|node| unsafe { *(node.ai_addr as *const sockaddr_in) })
+            .map(|addr| (addr.sin_addr.s_addr.to_ne_bytes(), u16::from_be(addr.sin_port)))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(resolve(0), [([127, 0, 0, 1], 80)]);
+    assert_eq!(resolve(AI_PASSIVE), [([0, 0, 0, 0], 80)]);
+}
+
+#[test]
+fn invalid_numeric_services_are_rejected() {
+    let resolve = |service: &[u8]| {
+        let mut res = ptr::null_mut();
+        let service = service.as_ptr() as *const c_char;
+        let error = unsafe { wspiapi_getaddrinfo(ptr::null(), service, ptr::null(), &mut res) };
+        drop(unsafe { AddrInfoList::from_raw(res) });
+        error
+    };
+
+    assert_eq!(resolve(b"65535\0"), 0);
+    assert_eq!(resolve(b"+80\0"), 0);
+    // would be truncated to 4464
+    assert_eq!(resolve(b"70000\0"), EAI_SERVICE);
+    assert_eq!(resolve(b"-1\0"), EAI_SERVICE);
+    assert_eq!(resolve(b"\0"), EAI_NONAME);
+}
+
+#[test]
+fn hostent_addrs_stop_at_the_terminator() {
+    let addresses: [[u8; 4]; 3] = [[192, 0, 2, 1], [192, 0, 2, 2], [192, 0, 2, 3]];
+    let list = [
+        addresses[0].as_ptr() as *const c_char,
+        addresses[1].as_ptr() as *const c_char,
+        addresses[2].as_ptr() as *const c_char,
+        ptr::null(),
+    ];
+    let mut host = hostent {
+        h_name: b"three.example.test\0".as_ptr() as *const c_char,
+        h_aliases: ptr::null(),
+        h_addrtype: AF_INET as USHORT,
+        h_length: 4,
+        h_addr_list: list.as_ptr(),
+    };
+
+    let found: Vec<_> = unsafe { HostentAddrs::new(&host) }.collect();
+    assert_eq!(found.len(), 3);
+    assert_eq!(found[2].octets(), addresses[2]);
+
+    // an IPv6 sized entry isn't read as IPv4
+    host.h_length = 16;
+    assert_eq!(unsafe { HostentAddrs::new(&host) }.count(), 0);
+}
@@ -48,14 +48,151 @@
 //!   loader lock.
 //! * call any Rust function or CRT function that touches any static
 //!   (global) state.
-
+//!
+//! All of the initializers of this crate go into `.CRT$XCU_RUST_*` sections,
+//! which the linker sorts between the other `.CRT$XCU` initializers and
+//! `.CRT$XCV`. Without the MSVC CRT, nothing calls them, so an embedder with
+//! a custom entry point has to call [`init_all`] before anything else, which
+//! runs the same table.
+
+use crate::ffi::{CStr, CString};
+use crate::mem;
 use crate::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use crate::sys::c;
+#[cfg(test)]
+use crate::sys_common::mutex::StaticMutex;
+use libc::c_char;
 
+pub mod loader;
 pub(crate) mod version;
 
+/// Marks the start of the initializer table, see `init_all`.
+#[used]
+#[link_section = ".CRT$XCU_RUST_A"]
+static INIT_TABLE_START: Option<unsafe extern "C" fn()> = None;
+
+/// Marks the end of the initializer table, see `init_all`.
+#[used]
+#[link_section = ".CRT$XCU_RUST_Z"]
+static INIT_TABLE_END: Option<unsafe extern "C" fn()> = None;
+
+/// Runs last, so that `init_all` knows whether the CRT already ran the table.
+#[used]
+#[link_section = ".CRT$XCU_RUST_Y"]
+static INIT_TABLE_DONE: unsafe extern "C" fn() = mark_initialized;
+
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+unsafe extern "C" fn mark_initialized() {
+    INITIALIZED.store(true, Ordering::Relaxed);
+}
+
+/// Runs the static initializers of this crate (version detection, the eager `compat_fn!` lookups
+/// and the `MutexKind` selection), unless that already happened.
+///
+/// With the MSVC CRT, this happens automatically before `main`. Without it, call this first thing
+/// from the custom entry point, while the process is still single-threaded, as the initializers
+/// write to unsynchronized statics.
+pub fn init_all() {
+    if INITIALIZED.load(Ordering::Relaxed) {
+        return;
+    }
+    unsafe {
+        // Same as what the CRT's `_initterm` does. The linker may pad the table with zeroes.
+        let mut entry = (&INIT_TABLE_START as *const Option<unsafe extern "C" fn()>).add(1);
+        let end = &INIT_TABLE_END as *const Option<unsafe extern "C" fn()>;
+        while entry < end {
+            if let Some(init) = *entry {
+                init();
+            }
+            entry = entry.add(1);
+        }
+    }
+    // in case the linker dropped the `INIT_TABLE_DONE` entry
+    INITIALIZED.store(true, Ordering::Relaxed);
+}
+
 pub(crate) const UNICOWS_MODULE_NAME: &str = "unicows\0";
 
+/// The system DLLs whose functions unicows (the Microsoft Layer for Unicode) implements. Unless a
+/// `compat_fn_lazy!` group says otherwise, unicows is checked first for the functions of these.
+const UNICOWS_MODULES: &[&str] = &[
+    "advapi32",
+    "avicap32",
+    "comdlg32",
+    "gdi32",
+    "kernel32",
+    "mpr",
+    "msvfw32",
+    "oleacc",
+    "oledlg",
+    "rasapi32",
+    "secur32",
+    "sensapi",
+    "shell32",
+    "user32",
+    "version",
+    "winmm",
+    "winspool.drv",
+];
+
+/// Whether unicows wraps `module`, which is its default for checking unicows.
+pub fn unicows_wraps(module: &str) -> bool {
+    UNICOWS_MODULES.iter().any(|wrapped| wrapped.eq_ignore_ascii_case(module))
+}
+
+/// Whether the exports of unicows are preferred. Which modules unicows is checked for at all is
+/// decided per module (see `unicows_wraps`), this is the crate-wide switch on top of that.
+static PREFER_UNICOWS: AtomicBool = AtomicBool::new(true);
+
+/// Sets whether unicows is used when it's loaded. This affects functions that are resolved after
+/// the call (the eager `compat_fn!`s are resolved before `main`) and the choice between the `W`
+/// APIs and their ANSI fallbacks on 9x/ME, see `wide_apis_available`.
+pub fn set_prefer_unicows(prefer: bool) {
+    PREFER_UNICOWS.store(prefer, Ordering::Relaxed);
+}
+
+pub fn prefer_unicows() -> bool {
+    PREFER_UNICOWS.load(Ordering::Relaxed)
+}
+
+/// Whether unicows is loaded into the process.
+pub fn unicows_loaded() -> bool {
+    unsafe { !c::GetModuleHandleA(UNICOWS_MODULE_NAME.as_ptr() as *const i8).is_null() }
+}
+
+/// Whether the `W` APIs are usable, either natively on NT or through unicows on 9x/ME. Code with an
+/// ANSI fallback for 9x/ME should check this instead of `is_windows_nt`.
+pub fn wide_apis_available() -> bool {
+    version::is_windows_nt() || prefer_unicows() && unicows_loaded()
+}
+
+/// The logger set with `set_fallback_logger`, 0 if there is none.
+static FALLBACK_LOGGER: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets a function that's called with the module and symbol name whenever a function declared with
+/// `compat_fn_lazy!` is called but wasn't found, right before its fallback runs. This lets
+/// applications log that they're running a degraded path, instead of only learning about missing
+/// functions from the fallbacks that abort.
+///
+/// The logger is called on the thread that called the function, possibly from within std's
+/// locking or IO code, so it shouldn't do more than recording the names somewhere. `None` removes
+/// the logger again.
+pub fn set_fallback_logger(logger: Option<fn(module: &str, symbol: &str)>) {
+    FALLBACK_LOGGER.store(logger.map_or(0, |logger| logger as usize), Ordering::Release);
+}
+
+/// Calls the fallback logger, if there is one and the function isn't `available`. Without a
+/// logger, this is a single atomic load.
+#[inline(always)]
+pub fn log_fallback(available: &AtomicBool, module: &str, symbol: &str) {
+    let logger = FALLBACK_LOGGER.load(Ordering::Acquire);
+    if logger != 0 && !available.load(Ordering::SeqCst) {
+        let logger = unsafe { mem::transmute::<usize, fn(&str, &str)>(logger) };
+        logger(module, symbol);
+    }
+}
+
 macro_rules! compat_fn {
     ($module:literal: $(
         $(#[$meta:meta])*
@@ -81,7 +218,7 @@ pub mod $symbol {
             /// It is marked `#[used]` because otherwise Rust would assume that it was not
             /// used, and would remove it.
             #[used]
-            #[link_section = ".CRT$XCU"]
+            #[link_section = ".CRT$XCU_RUST_B"]
             static INIT_TABLE_ENTRY: unsafe extern "C" fn() = init;
 
             unsafe extern "C" fn init() {
@@ -160,7 +297,14 @@ pub unsafe fn call($($argname: $argtype),*) -> $rettype {
 }
 
 macro_rules! compat_fn_lazy {
-    ($module:literal:{unicows: $unicows:literal, load: $load:literal}: $(
+    // Checks unicows if it wraps the module, see `unicows_wraps`.
+    ($module:literal:{load: $load:literal}: $($functions:tt)*) => (
+        compat_fn_lazy! {
+            $module:{unicows: crate::sys::compat::unicows_wraps($module), load: $load}:
+            $($functions)*
+        }
+    );
+    ($module:literal:{unicows: $unicows:expr, load: $load:literal}: $(
         $(#[$meta:meta])*
         pub fn $symbol:ident($($argname:ident: $argtype:ty),*) -> $rettype:ty $fallback_body:block
     )*) => ($(
@@ -176,8 +320,13 @@ pub mod $symbol {
             static PTR: AtomicUsize = AtomicUsize::new(0);
             static AVAILABLE: AtomicBool = AtomicBool::new(false);
 
+            #[cfg(test)]
+            static REGISTERED: AtomicBool = AtomicBool::new(false);
+
             #[allow(dead_code)]
             fn load() -> usize {
+                #[cfg(test)]
+                crate::sys::compat::register_reset(&REGISTERED, reset);
                 unsafe {
                     crate::sys::compat::store_func(
                         &PTR,
@@ -221,9 +370,18 @@ pub unsafe fn call($($argname: $argtype),*) -> $rettype {
                     0 => load(),
                     n => n,
                 };
+                crate::sys::compat::log_fallback(&AVAILABLE, $module, stringify!($symbol));
                 mem::transmute::<usize, F>(addr)($($argname),*)
             }
 
+            /// Clears `AVAILABLE` first: a concurrent lookup only resolves again once it sees
+            /// `PTR` cleared, so it can't be left with a found function marked unavailable.
+            #[cfg(test)]
+            fn reset() {
+                AVAILABLE.store(false, Ordering::SeqCst);
+                PTR.store(0, Ordering::SeqCst);
+            }
+
             #[allow(dead_code)]
             unsafe extern "system" fn fallback(
                 $(#[allow(unused_variables)] $argname: $argtype),*
@@ -237,37 +395,142 @@ pub unsafe fn call($($argname: $argtype),*) -> $rettype {
     )*)
 }
 
+/// The `reset` functions of the lazily resolved functions that were used so far.
+#[cfg(test)]
+static RESET_TABLE_LOCK: StaticMutex = StaticMutex::new();
+#[cfg(test)]
+static mut RESET_TABLE: Vec<fn()> = Vec::new();
+
+#[cfg(test)]
+pub fn register_reset(registered: &AtomicBool, reset: fn()) {
+    if !registered.swap(true, Ordering::SeqCst) {
+        unsafe {
+            let _guard = RESET_TABLE_LOCK.lock();
+            RESET_TABLE.push(reset);
+        }
+    }
+}
+
+/// Forgets all lazily resolved functions and cached module handles, so that each is resolved again
+/// on its next use, e.g. after a test changed what a mock returns.
+///
+/// This is strictly for tests, and racy: it affects all threads, so functions that other tests use
+/// at the same time may be resolved again while they're running, possibly against the mocks of the
+/// calling thread. Tests should only check the availability of functions no other test uses.
+#[cfg(test)]
+pub fn reset() {
+    unsafe {
+        let _guard = RESET_TABLE_LOCK.lock();
+        for reset in RESET_TABLE.iter() {
+            reset();
+        }
+    }
+    for slot in &MODULE_CACHE {
+        slot.handle.store(0, Ordering::SeqCst);
+    }
+}
+
 unsafe fn lookup(
     module: *const u8,
     symbol: *const u8,
     check_unicows: bool,
     load_library: bool,
 ) -> Option<usize> {
-    if check_unicows {
-        let unicows_handle = c::GetModuleHandleA(UNICOWS_MODULE_NAME.as_ptr() as *const i8);
-        if !unicows_handle.is_null() {
-            match c::GetProcAddress(unicows_handle, symbol as *const i8) as usize {
-                0 => {}
-                n => {
-                    return Some(n);
-                }
-            }
+    if let Some(addr) = loader::lookup_unicows(symbol, unicows(check_unicows)) {
+        return Some(addr);
+    }
+    loader::lookup_in(module_handle(module, load_library), symbol)
+}
+
+/// A module whose handle `module_handle` caches once it was found, so that the lazy lookups of
+/// many symbols from the same DLL don't each ask the loader again. Only modules that were found are
+/// cached, as the others might still be loaded later. Like the function pointers resolved from
+/// them, the handles are assumed to stay valid: std never unloads a module it loaded, and the
+/// others are ones std links against anyway.
+struct ModuleSlot {
+    /// NUL-terminated.
+    name: &'static str,
+    /// The module handle, or 0 if it wasn't found yet.
+    handle: AtomicUsize,
+}
+
+impl ModuleSlot {
+    const fn new(name: &'static str) -> ModuleSlot {
+        ModuleSlot { name, handle: AtomicUsize::new(0) }
+    }
+}
+
+/// The modules std resolves functions from lazily, each in its own slot, so that looking them up
+/// takes no lock and allocates nothing. Other modules are looked up every time.
+static MODULE_CACHE: [ModuleSlot; 8] = [
+    ModuleSlot::new("kernel32\0"),
+    ModuleSlot::new("advapi32\0"),
+    ModuleSlot::new("bcrypt\0"),
+    ModuleSlot::new("iphlpapi\0"),
+    ModuleSlot::new("user32\0"),
+    ModuleSlot::new("userenv\0"),
+    ModuleSlot::new("winmm\0"),
+    ModuleSlot::new("ws2_32\0"),
+];
+
+/// Returns the handle of `module`, which is null if it isn't loaded (or couldn't be loaded with
+/// `load_library`).
+unsafe fn module_handle(module: *const u8, load_library: bool) -> c::HMODULE {
+    module_handle_in(&MODULE_CACHE, module, load_library)
+}
+
+unsafe fn module_handle_in(
+    cache: &[ModuleSlot],
+    module: *const u8,
+    load_library: bool,
+) -> c::HMODULE {
+    let name = CStr::from_ptr(module as *const c_char).to_bytes_with_nul();
+    let slot = cache.iter().find(|slot| slot.name.as_bytes() == name);
+    if let Some(slot) = slot {
+        match slot.handle.load(Ordering::Acquire) {
+            0 => {}
+            handle => return handle as c::HMODULE,
         }
     }
 
+    // Two threads may both get here for the same module, and both store the same handle. Loading
+    // a module twice only bumps its reference count, and it's never unloaded anyway.
     let handle = if load_library {
-        c::LoadLibraryA(module as *const i8)
+        c::LoadLibraryA(module as c::LPCSTR)
     } else {
-        c::GetModuleHandleA(module as *const i8)
+        c::GetModuleHandleA(module as c::LPCSTR)
     };
-
-    if handle.is_null() {
-        return None;
+    if let Some(slot) = slot {
+        slot.handle.store(handle as usize, Ordering::Release);
     }
+    handle
+}
+
+/// The unicows module to check first, if it should be checked at all.
+fn unicows(check_unicows: bool) -> Option<*const u8> {
+    if check_unicows && prefer_unicows() { Some(UNICOWS_MODULE_NAME.as_ptr()) } else { None }
+}
 
-    match c::GetProcAddress(handle, symbol as *const i8) as usize {
-        0 => None,
-        n => Some(n),
+/// Looks up `symbol` in `module`, for APIs that aren't declared through one of the macros above.
+///
+/// Like the macros, this prefers the unicows export of `symbol` if `check_unicows` is set and the
+/// Microsoft Layer for Unicode is loaded. With `load_library`, `module` is loaded if necessary
+/// (and never unloaded), otherwise it has to be loaded already. This must not be called from a
+/// static initializer if `load_library` is set, as it takes the loader lock.
+pub fn try_get_proc(
+    module: &CStr,
+    symbol: &CStr,
+    check_unicows: bool,
+    load_library: bool,
+) -> Option<*const ()> {
+    unsafe {
+        lookup(
+            module.as_ptr() as *const u8,
+            symbol.as_ptr() as *const u8,
+            check_unicows,
+            load_library,
+        )
+        .map(|addr| addr as *const ())
     }
 }
 
@@ -280,14 +543,86 @@ pub unsafe fn store_func(
     check_unicows: bool,
     load_library: bool,
 ) -> usize {
-    let value = match lookup(module, symbol, check_unicows, load_library) {
-        Some(value) => {
-            available.store(true, Ordering::SeqCst);
-            value
+    loader::store(ptr, available, lookup(module, symbol, check_unicows, load_library), fallback)
+}
+
+/// A function from a DLL that's only chosen at runtime, e.g. from an optional redistributable an
+/// embedder ships. It's resolved on first use, like the functions declared with
+/// `compat_fn_lazy!`, and calls to it go to `fallback` if it wasn't found.
+///
+/// `F` has to be a function pointer type matching the export, such as
+/// `unsafe extern "system" fn() -> DWORD`. By default, unicows isn't checked and the module is
+/// loaded if necessary, see `try_get_proc` for what these mean.
+pub struct DynFn<F> {
+    module: CString,
+    symbol: CString,
+    fallback: F,
+    check_unicows: bool,
+    load_library: bool,
+    ptr: AtomicUsize,
+    available: AtomicBool,
+}
+
+impl<F: Copy> DynFn<F> {
+    pub fn new(module: &CStr, symbol: &CStr, fallback: F) -> DynFn<F> {
+        assert_eq!(mem::size_of::<F>(), mem::size_of::<usize>());
+        DynFn {
+            module: module.to_owned(),
+            symbol: symbol.to_owned(),
+            fallback,
+            check_unicows: false,
+            load_library: true,
+            ptr: AtomicUsize::new(0),
+            available: AtomicBool::new(false),
         }
-        None => fallback,
-    };
+    }
 
-    ptr.store(value, Ordering::SeqCst);
-    value
+    /// Sets whether the unicows export of the symbol is preferred, if unicows is loaded.
+    pub fn unicows(mut self, check_unicows: bool) -> DynFn<F> {
+        self.check_unicows = check_unicows;
+        self
+    }
+
+    /// Sets whether the module is loaded if it isn't already.
+    pub fn load_library(mut self, load_library: bool) -> DynFn<F> {
+        self.load_library = load_library;
+        self
+    }
+
+    /// Returns the function, or the fallback if it wasn't found. This must not be called from a
+    /// static initializer if the module may be loaded, as that takes the loader lock.
+    pub fn get(&self) -> F {
+        let addr = match self.ptr.load(Ordering::SeqCst) {
+            0 => self.load(),
+            n => n,
+        };
+        unsafe { mem::transmute_copy(&addr) }
+    }
+
+    pub fn option(&self) -> Option<F> {
+        let f = self.get();
+        if self.available.load(Ordering::SeqCst) { Some(f) } else { None }
+    }
+
+    pub fn available(&self) -> bool {
+        self.option().is_some()
+    }
+
+    fn load(&self) -> usize {
+        unsafe {
+            store_func(
+                &self.ptr,
+                &self.available,
+                self.module.as_ptr() as *const u8,
+                self.symbol.as_ptr() as *const u8,
+                mem::transmute_copy(&self.fallback),
+                self.check_unicows,
+                self.load_library,
+            )
+        }
+    }
 }
+
+// After the macros, so that the tests can use them.
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,123 @@
+//! The lookup core of the compat loader.
+//!
+//! This only depends on `core` and the three Win32 functions it declares itself, so it can be
+//! included into a `#![no_std]` runtime shim as is, with `#[path]`. That's also why the paths are
+//! absolute and nothing comes from the prelude: the tests build it in a `#[no_implicit_prelude]`
+//! module to keep it that way. Only test builds take `GetModuleHandleA` and `GetProcAddress` from
+//! `c` instead, so that tests can fake them.
+//!
+//! Unlike `compat::lookup`, this doesn't know about the crate-wide unicows preference. The caller
+//! passes the name of the unicows module when it wants it to be checked.
+
+use ::core::ffi::c_void;
+use ::core::option::Option::{self, None, Some};
+use ::core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+#[cfg(not(test))]
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetModuleHandleA(lpModuleName: *const i8) -> *mut c_void;
+    fn GetProcAddress(handle: *mut c_void, name: *const i8) -> *mut c_void;
+}
+
+// Tests can fake these, see `c::mock`.
+#[cfg(test)]
+use crate::sys::c::{GetModuleHandleA, GetProcAddress};
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn LoadLibraryA(lpFileName: *const i8) -> *mut c_void;
+}
+
+/// Looks up `symbol` in `unicows` (if given and loaded), and then in `module`, loading it first
+/// with `load_library`. All names are NUL-terminated.
+///
+/// # Safety
+///
+/// The names must be valid NUL-terminated strings. With `load_library`, this must not be called
+/// while holding the loader lock (e.g. from `DllMain` or a static initializer).
+pub unsafe fn lookup(
+    module: *const u8,
+    symbol: *const u8,
+    unicows: Option<*const u8>,
+    load_library: bool,
+) -> Option<usize> {
+    if let Some(addr) = lookup_unicows(symbol, unicows) {
+        return Some(addr);
+    }
+
+    let handle = if load_library {
+        LoadLibraryA(module as *const i8)
+    } else {
+        GetModuleHandleA(module as *const i8)
+    };
+
+    lookup_in(handle, symbol)
+}
+
+/// Looks up `symbol` in `unicows`, if given and loaded.
+///
+/// # Safety
+///
+/// See `lookup`.
+pub unsafe fn lookup_unicows(symbol: *const u8, unicows: Option<*const u8>) -> Option<usize> {
+    let unicows_handle = GetModuleHandleA(unicows? as *const i8);
+    if unicows_handle.is_null() {
+        return None;
+    }
+    lookup_in(unicows_handle, symbol)
+}
+
+/// Looks up `symbol` in the module `handle`, which may be null if the module wasn't found.
+///
+/// # Safety
+///
+/// `symbol` must be a valid NUL-terminated string, and `handle` a loaded module (or null).
+pub unsafe fn lookup_in(handle: *mut c_void, symbol: *const u8) -> Option<usize> {
+    if handle.is_null() {
+        return None;
+    }
+
+    match GetProcAddress(handle, symbol as *const i8) as usize {
+        0 => None,
+        n => Some(n),
+    }
+}
+
+/// Resolves `symbol` like `lookup`, and stores it (or `fallback`) in `ptr`. Sets `available` if
+/// the symbol was found. Returns the stored address.
+///
+/// # Safety
+///
+/// See `lookup`.
+pub unsafe fn store_func(
+    ptr: &AtomicUsize,
+    available: &AtomicBool,
+    module: *const u8,
+    symbol: *const u8,
+    fallback: usize,
+    unicows: Option<*const u8>,
+    load_library: bool,
+) -> usize {
+    store(ptr, available, lookup(module, symbol, unicows, load_library), fallback)
+}
+
+/// Stores the `found` address (or `fallback`) in `ptr`, and sets `available` if it was found.
+/// Returns the stored address.
+pub fn store(
+    ptr: &AtomicUsize,
+    available: &AtomicBool,
+    found: Option<usize>,
+    fallback: usize,
+) -> usize {
+    let value = match found {
+        Some(value) => {
+            available.store(true, Ordering::SeqCst);
+            value
+        }
+        None => fallback,
+    };
+
+    ptr.store(value, Ordering::SeqCst);
+    value
+}
@@ -0,0 +1,353 @@
+use super::try_get_proc;
+use crate::ffi::{c_void, CStr};
+use crate::ptr;
+
+fn cstr(s: &str) -> &CStr {
+    CStr::from_bytes_with_nul(s.as_bytes()).unwrap()
+}
+
+#[test]
+fn try_get_proc_resolves_exports() {
+    let kernel32 = cstr("kernel32\0");
+    assert!(try_get_proc(kernel32, cstr("GetTickCount\0"), false, false).is_some());
+    assert!(try_get_proc(kernel32, cstr("GetTickCount\0"), true, false).is_some());
+    assert!(try_get_proc(kernel32, cstr("ThisFunctionDoesNotExist\0"), true, false).is_none());
+    assert!(
+        try_get_proc(cstr("not_a_real_module\0"), cstr("GetTickCount\0"), false, true).is_none()
+    );
+}
+
+#[test]
+fn init_all_is_idempotent() {
+    let is_nt = super::version::is_windows_nt();
+    let srw = crate::sys::c::TryAcquireSRWLockExclusive::available();
+
+    super::init_all();
+    super::init_all();
+
+    assert_eq!(super::version::is_windows_nt(), is_nt);
+    assert_eq!(crate::sys::c::TryAcquireSRWLockExclusive::available(), srw);
+}
+
+// The loader again, without the std prelude, so that it breaks if it starts using anything but
+// `core`.
+#[no_implicit_prelude]
+#[path = "loader.rs"]
+mod core_only_loader;
+
+#[test]
+fn loader_builds_with_core_only() {
+    use crate::sync::atomic::{AtomicBool, AtomicUsize};
+
+    let (ptr, available) = (AtomicUsize::new(0), AtomicBool::new(false));
+    let found = unsafe {
+        core_only_loader::store_func(
+            &ptr,
+            &available,
+            b"kernel32\0".as_ptr(),
+            b"GetTickCount\0".as_ptr(),
+            1,
+            Some(b"unicows\0".as_ptr()),
+            false,
+        )
+    };
+    assert_ne!(found, 1);
+    assert!(available.into_inner());
+    assert_eq!(ptr.into_inner(), found);
+
+    let missing = unsafe {
+        core_only_loader::lookup(b"kernel32\0".as_ptr(), b"NotAnExport\0".as_ptr(), None, false)
+    };
+    assert_eq!(missing, None);
+}
+
+#[test]
+fn version_classification() {
+    use crate::sys::c::{self, mock};
+
+    unsafe extern "system" fn windows_98() -> c::DWORD {
+        // build 1998, platform bit set, 4.10
+        0xc7ce_0a04
+    }
+    unsafe extern "system" fn windows_xp() -> c::DWORD {
+        // build 2600, 5.1
+        0x0a28_0105
+    }
+
+    unsafe extern "system" fn windows_95() -> c::DWORD {
+        // build 950, platform bit set, 4.0
+        0xc3b6_0004
+    }
+    unsafe extern "system" fn windows_me() -> c::DWORD {
+        // build 3000, platform bit set, 4.90
+        0xcbb8_5a04
+    }
+
+    let guard = mock::GET_VERSION.install(windows_98);
+    assert!(!super::version::detect_windows_nt());
+    assert_eq!(super::version::detect_windows_9x_version(), Some((4, 10, 1998)));
+    drop(guard);
+    let guard = mock::GET_VERSION.install(windows_95);
+    assert_eq!(super::version::detect_windows_9x_version(), Some((4, 0, 950)));
+    drop(guard);
+    let guard = mock::GET_VERSION.install(windows_me);
+    assert_eq!(super::version::detect_windows_9x_version(), Some((4, 90, 3000)));
+    drop(guard);
+    let _guard = mock::GET_VERSION.install(windows_xp);
+    assert!(super::version::detect_windows_nt());
+    assert_eq!(super::version::detect_windows_9x_version(), None);
+}
+
+#[test]
+fn unicows_detection() {
+    use crate::sys::c::{self, mock};
+
+    unsafe extern "system" fn with_unicows(name: c::LPCSTR) -> c::HMODULE {
+        let name = CStr::from_ptr(name).to_bytes();
+        if name.eq_ignore_ascii_case(b"unicows") { 0x1000 as c::HMODULE } else { ptr::null_mut() }
+    }
+
+    let _guard = mock::GET_MODULE_HANDLE_A.install(with_unicows);
+    assert!(super::unicows_loaded());
+}
+
+#[test]
+fn store_func_resolves_through_the_loader() {
+    use crate::sync::atomic::{AtomicBool, AtomicUsize};
+    use crate::sys::c::{self, mock};
+
+    const MODULE: c::HMODULE = 0x1000 as c::HMODULE;
+
+    unsafe extern "system" fn get_module_handle(name: c::LPCSTR) -> c::HMODULE {
+        let name = CStr::from_ptr(name).to_bytes();
+        if name == b"compat_store_test" { MODULE } else { ptr::null_mut() }
+    }
+    unsafe extern "system" fn get_proc_address(handle: c::HMODULE, name: c::LPCSTR) -> *mut c_void {
+        match (handle, CStr::from_ptr(name).to_bytes()) {
+            (MODULE, b"Present") => 0x1234 as *mut c_void,
+            _ => ptr::null_mut(),
+        }
+    }
+
+    let _module_guard = mock::GET_MODULE_HANDLE_A.install(get_module_handle);
+    let _proc_guard = mock::GET_PROC_ADDRESS.install(get_proc_address);
+    let store = |symbol: &str| {
+        let (ptr, available) = (AtomicUsize::new(0), AtomicBool::new(false));
+        let stored = unsafe {
+            super::store_func(
+                &ptr,
+                &available,
+                b"compat_store_test\0".as_ptr(),
+                symbol.as_ptr(),
+                1,
+                false,
+                false,
+            )
+        };
+        assert_eq!(ptr.into_inner(), stored);
+        (stored, available.into_inner())
+    };
+
+    assert_eq!(store("Present\0"), (0x1234, true));
+    assert_eq!(store("Missing\0"), (1, false));
+}
+
+#[allow(nonstandard_style)]
+mod unicows_default_test {
+    pub mod wrapped {
+        use crate::sys::c::DWORD;
+
+        compat_fn_lazy! {
+            "kernel32":{load: false}:
+            pub fn CompatUnicowsTest() -> DWORD {
+                0
+            }
+        }
+    }
+
+    pub mod not_wrapped {
+        use crate::sys::c::DWORD;
+
+        compat_fn_lazy! {
+            "ws2_32":{load: false}:
+            pub fn CompatUnicowsTest() -> DWORD {
+                0
+            }
+        }
+    }
+}
+
+#[test]
+fn unicows_is_checked_for_the_modules_it_wraps() {
+    use super::{prefer_unicows, unicows_wraps};
+    use crate::sys::c::{self, mock};
+    use unicows_default_test::{not_wrapped, wrapped};
+
+    // a simulated unicows, which is the only module that exports the test function
+    const UNICOWS: c::HMODULE = 0x2000 as c::HMODULE;
+
+    unsafe extern "system" fn get_module_handle(name: c::LPCSTR) -> c::HMODULE {
+        let name = CStr::from_ptr(name).to_bytes();
+        if name == b"unicows" { UNICOWS } else { ptr::null_mut() }
+    }
+    unsafe extern "system" fn get_proc_address(handle: c::HMODULE, name: c::LPCSTR) -> *mut c_void {
+        match (handle, CStr::from_ptr(name).to_bytes()) {
+            (UNICOWS, b"CompatUnicowsTest") => 0x1234 as *mut c_void,
+            _ => ptr::null_mut(),
+        }
+    }
+
+    assert!(unicows_wraps("kernel32"));
+    assert!(unicows_wraps("KERNEL32"));
+    assert!(!unicows_wraps("ws2_32"));
+
+    let _module_guard = mock::GET_MODULE_HANDLE_A.install(get_module_handle);
+    let _proc_guard = mock::GET_PROC_ADDRESS.install(get_proc_address);
+    // nothing in std turns the preference off
+    assert!(prefer_unicows());
+    assert_eq!(wrapped::CompatUnicowsTest::option().map(|f| f as usize), Some(0x1234));
+    assert!(!not_wrapped::CompatUnicowsTest::available());
+}
+
+#[test]
+fn module_handles_are_cached() {
+    use super::{module_handle_in, ModuleSlot};
+    use crate::sync::atomic::{AtomicUsize, Ordering};
+    use crate::sys::c::{self, mock};
+
+    static KERNEL32: AtomicUsize = AtomicUsize::new(0);
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    // aliases for kernel32 that no other test looks up
+    unsafe extern "system" fn counting(name: c::LPCSTR) -> c::HMODULE {
+        assert!(CStr::from_ptr(name).to_bytes().starts_with(b"module_cache_test"));
+        CALLS.fetch_add(1, Ordering::Relaxed);
+        KERNEL32.load(Ordering::Relaxed) as c::HMODULE
+    }
+
+    let kernel32 = unsafe { c::GetModuleHandleA(b"kernel32\0".as_ptr() as c::LPCSTR) };
+    KERNEL32.store(kernel32 as usize, Ordering::Relaxed);
+
+    // a cache of its own, so that nothing of this is left in the global one
+    let cache = [ModuleSlot::new("module_cache_test\0")];
+    let _guard = mock::GET_MODULE_HANDLE_A.install(counting);
+    unsafe {
+        assert_eq!(module_handle_in(&cache, b"module_cache_test\0".as_ptr(), false), kernel32);
+        assert_eq!(module_handle_in(&cache, b"module_cache_test\0".as_ptr(), false), kernel32);
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+
+        let uncached = b"module_cache_test_uncached\0".as_ptr();
+        assert_eq!(module_handle_in(&cache, uncached, false), kernel32);
+        assert_eq!(module_handle_in(&cache, uncached, false), kernel32);
+        assert_eq!(CALLS.load(Ordering::Relaxed), 3);
+    }
+}
+
+#[allow(nonstandard_style)]
+mod reset_test {
+    use crate::sys::c::DWORD;
+
+    // only resolvable through the fake `GetModuleHandleA` below
+    compat_fn_lazy! {
+        "compat_reset_test":{unicows: false, load: false}:
+        pub fn GetTickCount() -> DWORD {
+            0
+        }
+    }
+}
+
+#[test]
+fn reset_resolves_again() {
+    use crate::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use crate::sys::c::{self, mock};
+
+    static KERNEL32: AtomicUsize = AtomicUsize::new(0);
+    static LOADED: AtomicBool = AtomicBool::new(false);
+
+    unsafe extern "system" fn toggled(name: c::LPCSTR) -> c::HMODULE {
+        assert_eq!(CStr::from_ptr(name).to_bytes(), b"compat_reset_test");
+        if LOADED.load(Ordering::Relaxed) {
+            KERNEL32.load(Ordering::Relaxed) as c::HMODULE
+        } else {
+            ptr::null_mut()
+        }
+    }
+
+    let kernel32 = unsafe { c::GetModuleHandleA(b"kernel32\0".as_ptr() as c::LPCSTR) };
+    KERNEL32.store(kernel32 as usize, Ordering::Relaxed);
+
+    let _guard = mock::GET_MODULE_HANDLE_A.install(toggled);
+    super::reset();
+    assert!(!reset_test::GetTickCount::available());
+
+    LOADED.store(true, Ordering::Relaxed);
+    assert!(!reset_test::GetTickCount::available());
+    super::reset();
+    assert!(reset_test::GetTickCount::available());
+    assert_ne!(unsafe { reset_test::GetTickCount() }, 0);
+
+    LOADED.store(false, Ordering::Relaxed);
+    super::reset();
+    assert!(!reset_test::GetTickCount::available());
+}
+
+#[test]
+fn dyn_fn_from_winmm() {
+    use super::DynFn;
+    use crate::sys::c::DWORD;
+
+    type TimeGetTime = unsafe extern "system" fn() -> DWORD;
+    unsafe extern "system" fn fallback() -> DWORD {
+        0
+    }
+
+    let time_get_time = DynFn::<TimeGetTime>::new(cstr("winmm\0"), cstr("timeGetTime\0"), fallback);
+    // winmm is an optional component on some systems
+    if let Some(f) = time_get_time.option() {
+        assert!(time_get_time.available());
+        assert_ne!(unsafe { f() }, 0);
+        assert_ne!(unsafe { time_get_time.get()() }, 0);
+    }
+
+    let missing = DynFn::<TimeGetTime>::new(cstr("winmm\0"), cstr("NotAnExport\0"), fallback)
+        .load_library(false)
+        .unicows(true);
+    assert!(!missing.available());
+    assert_eq!(unsafe { missing.get()() }, 0);
+}
+
+#[allow(nonstandard_style)]
+mod fallback_logger_test {
+    use crate::sys::c::DWORD;
+
+    compat_fn_lazy! {
+        "compat_fallback_logger_test":{unicows: false, load: false}:
+        pub fn GetTickCount() -> DWORD {
+            7
+        }
+    }
+}
+
+#[test]
+fn fallback_logger_sees_missing_functions() {
+    use crate::cell::RefCell;
+
+    thread_local! {
+        static LOGGED: RefCell<Vec<(String, String)>> = RefCell::new(Vec::new());
+    }
+    fn logger(module: &str, symbol: &str) {
+        LOGGED.with(|logged| logged.borrow_mut().push((module.to_owned(), symbol.to_owned())));
+    }
+
+    super::set_fallback_logger(Some(logger));
+    assert_eq!(unsafe { fallback_logger_test::GetTickCount() }, 7);
+    // the logger is process wide, so it mustn't outlive this test
+    super::set_fallback_logger(None);
+    LOGGED.with(|logged| {
+        let logged = logged.borrow();
+        assert_eq!(
+            *logged,
+            [("compat_fallback_logger_test".to_owned(), "GetTickCount".to_owned())]
+        );
+    });
+}
@@ -1,15 +1,33 @@
 use crate::sys::c;
 
 static mut IS_NT: bool = true;
+static mut VERSION_9X: Option<(u32, u32, u32)> = None;
 
 // See compat.rs for the explanation of how this works.
 #[used]
-#[link_section = ".CRT$XCU"]
+#[link_section = ".CRT$XCU_RUST_B"]
 static INIT_TABLE_ENTRY: unsafe extern "C" fn() = init;
 
 unsafe extern "C" fn init() {
+    IS_NT = detect_windows_nt();
+    VERSION_9X = detect_windows_9x_version();
+}
+
+pub(super) fn detect_windows_nt() -> bool {
     // according to old MSDN info, the high-order bit is set only on 95/98/ME.
-    IS_NT = c::GetVersion() < 0x8000_0000;
+    unsafe { c::GetVersion() < 0x8000_0000 }
+}
+
+/// `(major, minor, build)` on 9x/ME.
+pub(super) fn detect_windows_9x_version() -> Option<(u32, u32, u32)> {
+    if detect_windows_nt() {
+        return None;
+    }
+    // The low word is the version, major in the low byte. The high word is the build, with its top
+    // two bits set on 9x/ME (the highest being the platform bit), so those have to be masked off.
+    // Some releases leave the build at 0, so it's of no help in telling them apart.
+    let version = unsafe { c::GetVersion() };
+    Some((version & 0xff, (version >> 8) & 0xff, (version >> 16) & 0x3fff))
 }
 
 /// Returns true if we are running on a Windows NT-based system. Only use this for APIs where the
@@ -18,3 +36,20 @@
 pub(crate) fn is_windows_nt() -> bool {
     unsafe { IS_NT }
 }
+
+/// The `(major, minor)` version if this is 9x/ME: `(4, 0)` is 95, `(4, 10)` is 98 and `(4, 90)` is
+/// ME. `None` on NT. For code that depends on the actual release, such as on Winsock 2, which the
+/// original 95 doesn't come with.
+#[allow(dead_code)] // not needed within std yet
+#[inline(always)]
+pub(crate) fn windows_9x_version() -> Option<(u32, u32)> {
+    unsafe { VERSION_9X }.map(|(major, minor, _)| (major, minor))
+}
+
+/// The build number if this is 9x/ME, e.g. 950 for the original 95 or 1998 for 98. `None` on NT,
+/// and possibly 0 if the release doesn't report it.
+#[allow(dead_code)] // not needed within std yet
+#[inline(always)]
+pub(crate) fn windows_9x_build() -> Option<u32> {
+    unsafe { VERSION_9X }.map(|(_, _, build)| build)
+}
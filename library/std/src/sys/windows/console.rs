@@ -0,0 +1,77 @@
+//! Console control handlers.
+//!
+//! `SetConsoleCtrlHandler` is available on both 9x and NT, but there are differences in how
+//! much time a handler gets: on NT, the process is terminated 5 seconds after a
+//! `CTRL_CLOSE_EVENT` handler started running, while 9x/ME terminates it almost immediately, so
+//! handlers shouldn't rely on getting to clean up anything when the console is closed.
+
+#[cfg(test)]
+mod tests;
+
+use crate::io;
+use crate::mem;
+use crate::ptr;
+use crate::sync::atomic::{AtomicPtr, Ordering};
+use crate::sys::c;
+use crate::sys::cvt;
+
+/// A console control event.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CtrlEvent {
+    /// Ctrl-C was pressed, or a `CTRL_C_EVENT` was generated.
+    CtrlC,
+    /// Ctrl-Break was pressed, or a `CTRL_BREAK_EVENT` was generated.
+    CtrlBreak,
+    /// The console is being closed.
+    Close,
+}
+
+impl CtrlEvent {
+    fn from_raw(ctrl_type: c::DWORD) -> Option<CtrlEvent> {
+        match ctrl_type {
+            c::CTRL_C_EVENT => Some(CtrlEvent::CtrlC),
+            c::CTRL_BREAK_EVENT => Some(CtrlEvent::CtrlBreak),
+            c::CTRL_CLOSE_EVENT => Some(CtrlEvent::Close),
+            _ => None,
+        }
+    }
+}
+
+/// The registered `fn(CtrlEvent) -> bool`, or null.
+static HANDLER: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Registers `handler` to be called for console control events, replacing the previous one.
+///
+/// The handler runs on a thread created by the system for each event, and returns whether it
+/// handled the event. Unhandled events go on to the next handler, which is the default handler
+/// terminating the process unless there are others.
+pub fn set_ctrl_handler(handler: fn(CtrlEvent) -> bool) -> io::Result<()> {
+    let previous = HANDLER.swap(handler as *mut (), Ordering::AcqRel);
+    if previous.is_null() {
+        if let Err(err) = cvt(unsafe { c::SetConsoleCtrlHandler(Some(ctrl_thunk), c::TRUE) }) {
+            HANDLER.store(ptr::null_mut(), Ordering::Release);
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Unregisters the handler set by `set_ctrl_handler`, if any.
+pub fn clear_ctrl_handler() -> io::Result<()> {
+    if !HANDLER.swap(ptr::null_mut(), Ordering::AcqRel).is_null() {
+        cvt(unsafe { c::SetConsoleCtrlHandler(Some(ctrl_thunk), c::FALSE) })?;
+    }
+    Ok(())
+}
+
+unsafe extern "system" fn ctrl_thunk(ctrl_type: c::DWORD) -> c::BOOL {
+    let handler = HANDLER.load(Ordering::Acquire);
+    match CtrlEvent::from_raw(ctrl_type) {
+        Some(event) if !handler.is_null() => {
+            let handler: fn(CtrlEvent) -> bool = mem::transmute(handler);
+            handler(event) as c::BOOL
+        }
+        // Logoff and shutdown events are only sent to services.
+        _ => c::FALSE,
+    }
+}
@@ -0,0 +1,29 @@
+use super::{clear_ctrl_handler, ctrl_thunk, set_ctrl_handler, CtrlEvent};
+use crate::sync::atomic::{AtomicUsize, Ordering};
+use crate::sys::c;
+
+#[test]
+fn register_and_unregister() {
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    fn handler(event: CtrlEvent) -> bool {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        event == CtrlEvent::CtrlC
+    }
+
+    set_ctrl_handler(handler).unwrap();
+    // replacing keeps the registration
+    set_ctrl_handler(handler).unwrap();
+
+    unsafe {
+        assert_eq!(ctrl_thunk(c::CTRL_C_EVENT), c::TRUE);
+        assert_eq!(ctrl_thunk(c::CTRL_BREAK_EVENT), c::FALSE);
+        // not passed to the handler
+        assert_eq!(ctrl_thunk(5), c::FALSE);
+    }
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    clear_ctrl_handler().unwrap();
+    clear_ctrl_handler().unwrap();
+    unsafe { assert_eq!(ctrl_thunk(c::CTRL_C_EVENT), c::FALSE) };
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+}
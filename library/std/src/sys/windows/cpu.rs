@@ -0,0 +1,67 @@
+//! CPU feature detection for the code paths that have to run on anything down to a 486.
+
+/// Returns whether the CPU has a time stamp counter, i.e. whether `rdtsc` can be executed.
+///
+/// The TSC was introduced with the Pentium, on a 486 `rdtsc` raises an illegal instruction
+/// exception. Early 486s don't support `cpuid` either, which is checked first.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub fn has_tsc() -> bool {
+    #[cfg(target_arch = "x86")]
+    use crate::arch::x86::{__cpuid, has_cpuid};
+    #[cfg(target_arch = "x86_64")]
+    use crate::arch::x86_64::__cpuid;
+
+    #[cfg(target_arch = "x86")]
+    if !has_cpuid() {
+        return false;
+    }
+
+    // CPUID.01H:EDX.TSC[bit 4]
+    unsafe { __cpuid(0).eax >= 1 && __cpuid(1).edx & (1 << 4) != 0 }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+pub fn has_tsc() -> bool {
+    false
+}
+
+/// Returns whether the CPU has `cmpxchg8b`, i.e. can do 64-bit atomics natively.
+///
+/// Like the TSC, this came with the Pentium.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub fn has_cmpxchg8b() -> bool {
+    #[cfg(target_arch = "x86")]
+    use crate::arch::x86::{__cpuid, has_cpuid};
+    #[cfg(target_arch = "x86_64")]
+    use crate::arch::x86_64::__cpuid;
+
+    #[cfg(target_arch = "x86")]
+    if !has_cpuid() {
+        return false;
+    }
+
+    // CPUID.01H:EDX.CX8[bit 8]
+    unsafe { __cpuid(0).eax >= 1 && __cpuid(1).edx & (1 << 8) != 0 }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+pub fn has_cmpxchg8b() -> bool {
+    // 64-bit atomics are native everywhere else.
+    true
+}
+
+/// Reads the time stamp counter, if the CPU has one.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub fn read_tsc() -> Option<u64> {
+    #[cfg(target_arch = "x86")]
+    use crate::arch::x86::_rdtsc;
+    #[cfg(target_arch = "x86_64")]
+    use crate::arch::x86_64::_rdtsc;
+
+    if has_tsc() { Some(unsafe { _rdtsc() }) } else { None }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+pub fn read_tsc() -> Option<u64> {
+    None
+}
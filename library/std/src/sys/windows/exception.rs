@@ -0,0 +1,128 @@
+//! Manually registered SEH frames for reporting fatal exceptions on x86.
+//!
+//! `stack_overflow` reports stack overflows from a vectored exception handler, but
+//! `AddVectoredExceptionHandler` is XP+. On 9x/ME and NT 3.x/4, this module reports them (and
+//! access violations) from a structured exception frame instead, which it links into the
+//! per-thread handler chain at `FS:[0]` by hand.
+//!
+//! The compiler registers frames of its own there too: on i686-pc-windows-msvc, LLVM links one in
+//! for every function with cleanups (like `with_frame` itself, because of its guard), to run them
+//! during unwinding. Those only ever run cleanups and continue the search otherwise, and are
+//! nested properly with this one, as both are unlinked before their function returns.
+//!
+//! The handler is never listed in a SafeSEH table, which is why this is only used on systems
+//! predating SafeSEH validation (XP SP2). It only prints a message and always continues the
+//! search, so the process still ends up in the default handler (or a debugger), like it would
+//! without the frame.
+//!
+//! # Safety contract
+//!
+//! The OS walks the chain on every exception (including the ones used for panics on MSVC), so
+//! the registration record must:
+//! * live on the stack of the thread that registered it, as NT validates that,
+//! * stay valid and in place until it's unlinked again,
+//! * be unlinked in LIFO order, i.e. before returning from the frame that registered it.
+//!
+//! `with_exception_frame` upholds all of this by keeping the record in its own stack frame and
+//! unlinking it before returning, i.e. before the compiler unlinks the frame of `with_frame`.
+//! During unwinding, `RtlUnwind` unlinks the record itself before the guard runs, so the guard
+//! only restores `FS:[0]` if the record is still the head of the chain.
+
+#[cfg(test)]
+mod tests;
+
+use crate::ptr;
+use crate::sys::c;
+use crate::thread;
+
+const EXCEPTION_ACCESS_VIOLATION: c::DWORD = 0xc0000005;
+/// `ExceptionFlags` bits that are set while unwinding (`EXCEPTION_UNWINDING | EXCEPTION_EXIT_UNWIND`).
+const EXCEPTION_UNWIND: c::DWORD = 0x2 | 0x4;
+
+/// `EXCEPTION_REGISTRATION_RECORD`
+#[repr(C)]
+struct Registration {
+    next: *mut Registration,
+    handler: unsafe extern "C" fn(
+        *mut c::EXCEPTION_RECORD,
+        *mut Registration,
+        *mut c::CONTEXT,
+        c::LPVOID,
+    ) -> c::EXCEPTION_DISPOSITION,
+}
+
+/// Whether the frame is needed, i.e. whether there are no vectored exception handlers.
+pub fn is_needed() -> bool {
+    !c::AddVectoredExceptionHandler::available()
+}
+
+/// Runs `f` with an exception frame reporting fatal exceptions, if `is_needed`.
+pub fn with_exception_frame<R>(f: impl FnOnce() -> R) -> R {
+    if is_needed() { unsafe { with_frame(f) } } else { f() }
+}
+
+/// Runs `f` with the exception frame, even if it isn't needed.
+///
+/// # Safety
+///
+/// The handler isn't in a SafeSEH table, so on systems validating handlers (XP SP2+), an
+/// exception inside `f` terminates the process before any other handler gets to see it.
+#[inline(never)]
+unsafe fn with_frame<R>(f: impl FnOnce() -> R) -> R {
+    let mut registration = Registration { next: ptr::null_mut(), handler };
+    let record = &mut registration as *mut Registration;
+    (*record).next = chain_head();
+    set_chain_head(record);
+
+    struct Unlink(*mut Registration);
+    impl Drop for Unlink {
+        fn drop(&mut self) {
+            unsafe {
+                if chain_head() == self.0 {
+                    set_chain_head((*self.0).next);
+                }
+            }
+        }
+    }
+    let _unlink = Unlink(record);
+
+    f()
+}
+
+unsafe fn chain_head() -> *mut Registration {
+    let head: *mut Registration;
+    crate::arch::asm!("mov {}, fs:[0]", out(reg) head, options(nostack, readonly, preserves_flags));
+    head
+}
+
+unsafe fn set_chain_head(head: *mut Registration) {
+    crate::arch::asm!("mov fs:[0], {}", in(reg) head, options(nostack, preserves_flags));
+}
+
+unsafe extern "C" fn handler(
+    record: *mut c::EXCEPTION_RECORD,
+    _frame: *mut Registration,
+    _context: *mut c::CONTEXT,
+    _dispatcher: c::LPVOID,
+) -> c::EXCEPTION_DISPOSITION {
+    let record = &*record;
+    if record.ExceptionFlags & EXCEPTION_UNWIND == 0 {
+        match record.ExceptionCode {
+            c::EXCEPTION_STACK_OVERFLOW => {
+                rtprintpanic!(
+                    "\nthread '{}' has overflowed its stack\n",
+                    thread::current().name().unwrap_or("<unknown>")
+                );
+            }
+            EXCEPTION_ACCESS_VIOLATION => {
+                rtprintpanic!(
+                    "\nthread '{}' caused an access violation at {:p}\n",
+                    thread::current().name().unwrap_or("<unknown>"),
+                    record.ExceptionAddress
+                );
+            }
+            _ => {}
+        }
+    }
+    c::ExceptionContinueSearch
+}
@@ -0,0 +1,30 @@
+use crate::env;
+use crate::process::Command;
+use crate::ptr;
+
+const CHILD_VAR: &str = "RUST_TEST_EXCEPTION_FRAME_CHILD";
+
+#[test]
+fn access_violation_is_reported() {
+    if env::var_os(CHILD_VAR).is_some() {
+        unsafe {
+            super::with_frame(|| ptr::read_volatile(ptr::null::<u32>()));
+        }
+        unreachable!();
+    }
+
+    let output = Command::new(env::current_exe().unwrap())
+        .arg("sys::windows::exception::tests::access_violation_is_reported")
+        .args(&["--exact", "--test-threads=1", "--nocapture"])
+        .env(CHILD_VAR, "1")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    // Handlers outside of the SafeSEH table are skipped from XP SP2 on, so the report can only be
+    // checked on the systems the frame is meant for.
+    if super::is_needed() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("caused an access violation"), "{stderr}");
+    }
+}
@@ -1,12 +1,12 @@
 use crate::os::windows::prelude::*;
 
 use crate::convert::{TryFrom, TryInto};
-use crate::ffi::OsString;
+use crate::ffi::{OsStr, OsString};
 use crate::fmt;
 use crate::io::{self, Error, IoSlice, IoSliceMut, ReadBuf, SeekFrom};
 use crate::mem;
 use crate::os::windows::io::{AsHandle, BorrowedHandle};
-use crate::path::{Path, PathBuf};
+use crate::path::{Component, Path, PathBuf};
 use crate::ptr;
 use crate::slice;
 use crate::sync::Arc;
@@ -14,10 +14,16 @@
 use crate::sys::time::SystemTime;
 use crate::sys::{c, compat, cvt};
 use crate::sys_common::{AsInner, FromInner, IntoInner};
+use crate::time::Duration;
 
 use super::path::maybe_verbatim;
 use super::to_u16s;
 
+pub mod thread_cwd;
+
+#[cfg(test)]
+mod tests;
+
 pub struct File {
     handle: Handle,
 }
@@ -305,17 +311,38 @@ fn get_flags_and_attributes(&self) -> c::DWORD {
 
 impl File {
     pub fn open(path: &Path, opts: &OpenOptions) -> io::Result<File> {
-        let path = maybe_verbatim(path)?;
-        let handle = unsafe {
-            c::CreateFileW(
-                path.as_ptr(),
-                opts.get_access_mode()?,
-                opts.share_mode,
-                opts.security_attributes,
-                opts.get_creation_mode()?,
-                opts.get_flags_and_attributes(),
-                ptr::null_mut(),
-            )
+        File::open_with(&thread_cwd::resolve(path), opts, compat::wide_apis_available())
+    }
+
+    /// Opens `path` with `CreateFileW` if `wide`, and with `CreateFileA` otherwise. The latter
+    /// also covers devices like `NUL`, which 9x/ME only know by their plain names.
+    fn open_with(path: &Path, opts: &OpenOptions, wide: bool) -> io::Result<File> {
+        let handle = if wide {
+            let path = maybe_verbatim(path)?;
+            unsafe {
+                c::CreateFileW(
+                    path.as_ptr(),
+                    opts.get_access_mode()?,
+                    opts.share_mode,
+                    opts.security_attributes,
+                    opts.get_creation_mode()?,
+                    opts.get_flags_and_attributes(),
+                    ptr::null_mut(),
+                )
+            }
+        } else {
+            let path = super::to_ansi(path)?;
+            unsafe {
+                c::CreateFileA(
+                    path.as_ptr() as c::LPCSTR,
+                    opts.get_access_mode()?,
+                    opts.share_mode,
+                    opts.security_attributes,
+                    opts.get_creation_mode()?,
+                    opts.get_flags_and_attributes(),
+                    ptr::null_mut(),
+                )
+            }
         };
         if let Ok(handle) = handle.try_into() {
             let file = File { handle: Handle::from_inner(handle) };
@@ -334,8 +361,19 @@ pub fn open(path: &Path, opts: &OpenOptions) -> io::Result<File> {
     }
 
     pub fn fsync(&self) -> io::Result<()> {
-        cvt(unsafe { c::FlushFileBuffers(self.handle.as_raw_handle()) })?;
-        Ok(())
+        match cvt(unsafe { c::FlushFileBuffers(self.handle.as_raw_handle()) }) {
+            Ok(_) => Ok(()),
+            // Character devices like `NUL` have nothing to flush, but some fail instead of
+            // succeeding, depending on the system.
+            Err(e)
+                if e.raw_os_error() == Some(c::ERROR_INVALID_FUNCTION as i32)
+                    && unsafe { c::GetFileType(self.handle.as_raw_handle()) }
+                        == c::FILE_TYPE_CHAR =>
+            {
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
     }
 
     pub fn datasync(&self) -> io::Result<()> {
@@ -356,37 +394,35 @@ pub fn truncate(&self, size: u64) -> io::Result<()> {
                 )
             })?;
         } else {
-            let mut saved_pos = 0;
-            unsafe {
-                // get current file pointer position
-                cvt(c::SetFilePointerEx(
-                    self.handle.as_raw_handle(),
-                    0,
-                    &mut saved_pos,
-                    c::FILE_CURRENT,
-                ))?;
+            self.truncate_by_seeking(size)?;
+        }
 
-                // seek to new end position
-                cvt(c::SetFilePointerEx(
-                    self.handle.as_raw_handle(),
-                    size as c::LARGE_INTEGER,
-                    ptr::null_mut(),
-                    c::FILE_BEGIN,
-                ))?;
+        Ok(())
+    }
 
-                // set current position as end of file
-                cvt(c::SetEndOfFile(self.handle.as_raw_handle()))?;
+    /// `truncate` for systems without `SetFileInformationByHandle`, which moves the file pointer
+    /// to `size` for `SetEndOfFile`, and back to where it was afterwards, also when that failed.
+    ///
+    /// Growing a file leaves the new part zero-filled. NTFS does that lazily, but FAT writes out
+    /// every zero right away, so extending a file by a lot is slow on 9x/ME.
+    fn truncate_by_seeking(&self, size: u64) -> io::Result<()> {
+        let size: c::LARGE_INTEGER = size
+            .try_into()
+            .map_err(|_| io::const_io_error!(io::ErrorKind::InvalidInput, "file size too large"))?;
+        let handle = self.handle.as_raw_handle();
+        let mut saved_pos = 0;
+        unsafe {
+            // get current file pointer position
+            cvt(c::SetFilePointerEx(handle, 0, &mut saved_pos, c::FILE_CURRENT))?;
 
-                // go back to saved position
-                cvt(c::SetFilePointerEx(
-                    self.handle.as_raw_handle(),
-                    saved_pos as c::LARGE_INTEGER,
-                    ptr::null_mut(),
-                    c::FILE_BEGIN,
-                ))?;
-            }
-        }
+            // seek to new end position, and set it as the end of file
+            let res = cvt(c::SetFilePointerEx(handle, size, ptr::null_mut(), c::FILE_BEGIN))
+                .and_then(|_| cvt(c::SetEndOfFile(handle)));
 
+            // go back to saved position
+            cvt(c::SetFilePointerEx(handle, saved_pos, ptr::null_mut(), c::FILE_BEGIN))?;
+            res?;
+        }
         Ok(())
     }
 
@@ -613,6 +649,56 @@ pub fn set_permissions(&self, perm: FilePermissions) -> io::Result<()> {
         })?;
         Ok(())
     }
+    /// Sets the last access and modification times, leaving the ones that are `None` unchanged.
+    ///
+    /// The handle needs `FILE_WRITE_ATTRIBUTES` access, or `GENERIC_WRITE` on 9x/ME. Times before
+    /// 1980 are rejected on FAT volumes, see `to_file_time`. Before Vista, the file system of a
+    /// handle can't be queried, so only 9x/ME volumes are taken to be FAT then.
+    pub fn set_times(
+        &self,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+    ) -> io::Result<()> {
+        let fat = if c::GetVolumeInformationByHandleW::available() {
+            let mut fs_name = [0u16; c::MAX_PATH + 1];
+            cvt(unsafe {
+                c::GetVolumeInformationByHandleW(
+                    self.handle.as_raw_handle(),
+                    ptr::null_mut(),
+                    0,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    fs_name.as_mut_ptr(),
+                    fs_name.len() as c::DWORD,
+                )
+            })?;
+            is_fat(&OsString::from_wide(super::truncate_utf16_at_nul(&fs_name)))
+        } else {
+            !crate::sys::compat::version::is_windows_nt()
+        };
+        self.set_times_as(accessed, modified, fat)
+    }
+
+    fn set_times_as(
+        &self,
+        accessed: Option<SystemTime>,
+        modified: Option<SystemTime>,
+        fat: bool,
+    ) -> io::Result<()> {
+        let accessed = accessed.map(|t| to_file_time(t, fat)).transpose()?;
+        let modified = modified.map(|t| to_file_time(t, fat)).transpose()?;
+        cvt(unsafe {
+            c::SetFileTime(
+                self.handle.as_raw_handle(),
+                ptr::null(),
+                accessed.as_ref().map_or(ptr::null(), |t| t as *const _),
+                modified.as_ref().map_or(ptr::null(), |t| t as *const _),
+            )
+        })?;
+        Ok(())
+    }
+
     /// Get only basic file information such as attributes and file times.
     fn basic_info(&self) -> io::Result<c::FILE_BASIC_INFO> {
         unsafe {
@@ -994,15 +1080,99 @@ pub fn new() -> DirBuilder {
     }
 
     pub fn mkdir(&self, p: &Path) -> io::Result<()> {
-        let p = maybe_verbatim(p)?;
+        let p = maybe_verbatim(&thread_cwd::resolve(p))?;
         cvt(unsafe { c::CreateDirectoryW(p.as_ptr(), ptr::null_mut()) })?;
         Ok(())
     }
 }
 
+/// Like `fs::create_dir_all`, with `CreateDirectoryA` for 9x/ME, which doesn't have the `W`
+/// version. Each missing level is created in turn, and ones that exist already are skipped.
+///
+/// 9x can't create shares, and its redirector is unreliable about what it reports for missing
+/// ones, so a UNC share that can't be found is reported as such before creating anything in it.
+/// Verbatim (`\\?\`) paths are unknown to 9x, and rejected.
+pub fn create_dir_all_compat(path: &Path) -> io::Result<()> {
+    let path = thread_cwd::resolve(path);
+    let mut current = PathBuf::new();
+    let mut unc = false;
+    for component in path.components() {
+        current.push(component);
+        match component {
+            Component::Prefix(prefix) => match prefix.kind() {
+                crate::path::Prefix::UNC(..) => unc = true,
+                crate::path::Prefix::Disk(_) => {}
+                _ => {
+                    return Err(io::const_io_error!(
+                        io::ErrorKind::Unsupported,
+                        "verbatim and device paths are not supported by the ANSI APIs",
+                    ));
+                }
+            },
+            // `current` is the root of the share now
+            Component::RootDir if unc && !is_dir_ansi(&current)? => {
+                return Err(io::const_io_error!(
+                    io::ErrorKind::NotFound,
+                    "the UNC share does not exist or can't be reached",
+                ));
+            }
+            Component::RootDir | Component::CurDir | Component::ParentDir => {}
+            Component::Normal(_) => {
+                let ansi = super::to_ansi(&current)?;
+                let created =
+                    unsafe { c::CreateDirectoryA(ansi.as_ptr() as c::LPCSTR, ptr::null_mut()) };
+                if created == 0 {
+                    let error = Error::last_os_error();
+                    // `ERROR_ALREADY_EXISTS`, or `ERROR_ACCESS_DENIED` on 9x for drive roots
+                    if !is_dir_ansi(&current)? {
+                        return Err(error);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_dir_ansi(path: &Path) -> io::Result<bool> {
+    // an unreachable share is just not a directory here
+    Ok(matches!(
+        attributes_ansi(path),
+        Ok(Some(attributes)) if attributes & c::FILE_ATTRIBUTE_DIRECTORY != 0
+    ))
+}
+
+/// The attributes of `path` from the ANSI APIs, for 9x/ME without unicows, or `None` if there's
+/// nothing at `path`. Other errors, like a file that's in use, are still reported.
+pub fn attributes_ansi(path: &Path) -> io::Result<Option<c::DWORD>> {
+    let path = super::to_ansi(&*thread_cwd::resolve(path))?;
+    let attributes = if c::GetFileAttributesExA::available() {
+        let mut data: c::WIN32_FILE_ATTRIBUTE_DATA = unsafe { mem::zeroed() };
+        let res = unsafe {
+            c::GetFileAttributesExA(
+                path.as_ptr() as c::LPCSTR,
+                c::GetFileExInfoStandard,
+                &mut data as *mut _ as c::LPVOID,
+            )
+        };
+        if res == c::FALSE { c::INVALID_FILE_ATTRIBUTES } else { data.dwFileAttributes }
+    } else {
+        // 95 only has the plain version
+        unsafe { c::GetFileAttributesA(path.as_ptr() as c::LPCSTR) }
+    };
+
+    if attributes != c::INVALID_FILE_ATTRIBUTES {
+        return Ok(Some(attributes));
+    }
+    match unsafe { c::GetLastError() } {
+        c::ERROR_FILE_NOT_FOUND | c::ERROR_PATH_NOT_FOUND => Ok(None),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
 pub fn readdir(p: &Path) -> io::Result<ReadDir> {
     let root = p.to_path_buf();
-    let star = p.join("*");
+    let star = thread_cwd::resolve(p).join("*");
     let path = maybe_verbatim(&star)?;
 
     unsafe {
@@ -1021,34 +1191,94 @@ pub fn readdir(p: &Path) -> io::Result<ReadDir> {
 }
 
 pub fn unlink(p: &Path) -> io::Result<()> {
-    let p_u16s = maybe_verbatim(p)?;
+    let p_u16s = maybe_verbatim(&thread_cwd::resolve(p))?;
     cvt(unsafe { c::DeleteFileW(p_u16s.as_ptr()) })?;
     Ok(())
 }
 
 pub fn rename(old: &Path, new: &Path) -> io::Result<()> {
-    let old = maybe_verbatim(old)?;
-    let new = maybe_verbatim(new)?;
-    let res =
-        cvt(unsafe { c::MoveFileExW(old.as_ptr(), new.as_ptr(), c::MOVEFILE_REPLACE_EXISTING) });
+    let (old, new) = (thread_cwd::resolve(old), thread_cwd::resolve(new));
+    let old_w = maybe_verbatim(&old)?;
+    let new_w = maybe_verbatim(&new)?;
+    let res = cvt(unsafe {
+        c::MoveFileExW(old_w.as_ptr(), new_w.as_ptr(), c::MOVEFILE_REPLACE_EXISTING)
+    });
 
     match res {
         Err(ref e) if e.raw_os_error() == Some(c::ERROR_CALL_NOT_IMPLEMENTED as i32) => {
-            // 9x/ME doesn't support MoveFileEx, so we fall back to copy + delete and hope for the
-            // best
-            unsafe {
-                cvt(c::CopyFileW(old.as_ptr(), new.as_ptr(), c::TRUE))?;
-                cvt(c::DeleteFileW(old.as_ptr()))?;
-                Ok(())
-            }
+            // 9x/ME doesn't support MoveFileEx
+            replace_with(&old, &new, move_file_ansi)
         }
         Err(e) => Err(e),
         Ok(_) => Ok(()),
     }
 }
 
+fn move_file_ansi(from: &Path, to: &Path) -> io::Result<()> {
+    let from = super::to_ansi(from)?;
+    let to = super::to_ansi(to)?;
+    cvt(unsafe { c::MoveFileA(from.as_ptr() as c::LPCSTR, to.as_ptr() as c::LPCSTR) })?;
+    Ok(())
+}
+
+/// Renames `old` to `new` with `move_file`, which fails if `new` exists. An existing `new` file is
+/// moved aside first, and only deleted once `old` took its place, or moved back if that failed.
+/// This isn't atomic, but `new` is only missing between two renames.
+fn replace_with(
+    old: &Path,
+    new: &Path,
+    move_file: impl Fn(&Path, &Path) -> io::Result<()>,
+) -> io::Result<()> {
+    fn exists(e: &io::Error) -> bool {
+        let code = e.raw_os_error();
+        code == Some(c::ERROR_ALREADY_EXISTS as i32) || code == Some(c::ERROR_FILE_EXISTS as i32)
+    }
+
+    let error = match move_file(old, new) {
+        Ok(()) => return Ok(()),
+        Err(e) => e,
+    };
+    // directories can't be replaced on NT either
+    let replaceable = matches!(
+        attributes_ansi(new),
+        Ok(Some(attributes)) if attributes & c::FILE_ATTRIBUTE_DIRECTORY == 0
+    );
+    let name = match new.file_name() {
+        Some(name) if exists(&error) && replaceable => name,
+        _ => return Err(error),
+    };
+
+    let pid = crate::sys::process::current_pid();
+    let mut aside = None;
+    for i in 0..100 {
+        let mut aside_name = name.to_os_string();
+        aside_name.push(format!(".{:x}-{}.old", pid, i));
+        let path = new.with_file_name(aside_name);
+        match move_file(new, &path) {
+            Ok(()) => {
+                aside = Some(path);
+                break;
+            }
+            Err(e) if exists(&e) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let aside = aside.ok_or(error)?;
+
+    if let Err(e) = move_file(old, new) {
+        // if even this fails, `new` is still at `aside`, which is all that's left to do
+        let _ = move_file(&aside, new);
+        return Err(e);
+    }
+    // the replaced file may still be open somewhere, in which case it's left behind
+    if let Ok(aside) = super::to_ansi(&aside) {
+        unsafe { c::DeleteFileA(aside.as_ptr() as c::LPCSTR) };
+    }
+    Ok(())
+}
+
 pub fn rmdir(p: &Path) -> io::Result<()> {
-    let p = maybe_verbatim(p)?;
+    let p = maybe_verbatim(&thread_cwd::resolve(p))?;
     cvt(unsafe { c::RemoveDirectoryW(p.as_ptr()) })?;
     Ok(())
 }
@@ -1064,6 +1294,8 @@ fn open_link(path: &Path, access_mode: u32) -> io::Result<File> {
 }
 
 pub fn remove_dir_all(path: &Path) -> io::Result<()> {
+    let path = thread_cwd::resolve(path);
+    let path = &*path;
     // if the modern file/directory APIs are not available, we'll fall back to the old (unsafe, see
     // https://github.com/rust-lang/rust/pull/93112) directory removal implementation
     if !c::NtCreateFile::available()
@@ -1207,8 +1439,9 @@ pub fn symlink(original: &Path, link: &Path) -> io::Result<()> {
 }
 
 pub fn symlink_inner(original: &Path, link: &Path, dir: bool) -> io::Result<()> {
+    // a relative `original` is relative to the link, not to any current directory
     let original = to_u16s(original)?;
-    let link = maybe_verbatim(link)?;
+    let link = maybe_verbatim(&thread_cwd::resolve(link))?;
     let flags = if dir { c::SYMBOLIC_LINK_FLAG_DIRECTORY } else { 0 };
     // Formerly, symlink creation required the SeCreateSymbolicLink privilege. For the Windows 10
     // Creators Update, Microsoft loosened this to allow unprivileged symlink creation if the
@@ -1237,9 +1470,51 @@ pub fn symlink_inner(original: &Path, link: &Path, dir: bool) -> io::Result<()>
 
 #[cfg(not(target_vendor = "uwp"))]
 pub fn link(original: &Path, link: &Path) -> io::Result<()> {
-    let original = maybe_verbatim(original)?;
-    let link = maybe_verbatim(link)?;
-    cvt(unsafe { c::CreateHardLinkW(link.as_ptr(), original.as_ptr(), ptr::null_mut()) })?;
+    link_with(
+        &thread_cwd::resolve(original),
+        &thread_cwd::resolve(link),
+        compat::wide_apis_available(),
+    )
+}
+
+#[cfg(not(target_vendor = "uwp"))]
+fn link_with(original: &Path, link: &Path, wide: bool) -> io::Result<()> {
+    // 9x/ME and NT 4 don't know about hard links at all
+    let available =
+        if wide { c::CreateHardLinkW::available() } else { c::CreateHardLinkA::available() };
+    if !available {
+        return Err(io::const_io_error!(
+            io::ErrorKind::Unsupported,
+            "hard links are not supported on this version of Windows",
+        ));
+    }
+
+    let created = if wide {
+        let original = maybe_verbatim(original)?;
+        let link = maybe_verbatim(link)?;
+        unsafe { c::CreateHardLinkW(link.as_ptr(), original.as_ptr(), ptr::null_mut()) }
+    } else {
+        let original = super::to_ansi(original)?;
+        let link = super::to_ansi(link)?;
+        unsafe {
+            c::CreateHardLinkA(
+                link.as_ptr() as c::LPCSTR,
+                original.as_ptr() as c::LPCSTR,
+                ptr::null_mut(),
+            )
+        }
+    };
+    cvt(created).map_err(|e| {
+        // FAT volumes fail with a rather cryptic `ERROR_INVALID_FUNCTION`
+        if let Ok(false) = volume_info(original).map(|info| info.supports_hard_links()) {
+            io::const_io_error!(
+                io::ErrorKind::Unsupported,
+                "the file system does not support hard links",
+            )
+        } else {
+            e
+        }
+    })?;
     Ok(())
 }
 
@@ -1252,6 +1527,8 @@ pub fn link(_original: &Path, _link: &Path) -> io::Result<()> {
 }
 
 pub fn stat(path: &Path) -> io::Result<FileAttr> {
+    let path = thread_cwd::resolve(path);
+    let path = &*path;
     let mut opts = OpenOptions::new();
     // No read or write permissions are necessary
     opts.access_mode(0);
@@ -1329,7 +1606,7 @@ pub fn lstat(path: &Path) -> io::Result<FileAttr> {
 }
 
 pub fn set_perm(p: &Path, perm: FilePermissions) -> io::Result<()> {
-    let p = maybe_verbatim(p)?;
+    let p = maybe_verbatim(&thread_cwd::resolve(p))?;
     unsafe {
         cvt(c::SetFileAttributesW(p.as_ptr(), perm.attrs))?;
         Ok(())
@@ -1346,6 +1623,8 @@ fn get_path(f: &File) -> io::Result<PathBuf> {
 }
 
 pub fn canonicalize(p: &Path) -> io::Result<PathBuf> {
+    let p = thread_cwd::resolve(p);
+    let p = &*p;
     if c::GetFinalPathNameByHandleW::available() {
         let mut opts = OpenOptions::new();
         // No read or write permissions are necessary
@@ -1366,7 +1645,43 @@ pub fn canonicalize(p: &Path) -> io::Result<PathBuf> {
     }
 }
 
+/// The 8.3 form of `path`, for programs and APIs that can't handle long names or spaces. `path`
+/// has to exist.
+///
+/// Volumes with short name generation disabled (an NT setting, or file systems without 8.3 names)
+/// return components without a short name unchanged, so this can be (partly) the long path.
+pub fn short_path(path: &Path) -> io::Result<OsString> {
+    let path = thread_cwd::resolve(path);
+    if compat::version::is_windows_nt() {
+        let path = to_u16s(&*path)?;
+        super::fill_utf16_buf(
+            |buf, sz| unsafe { c::GetShortPathNameW(path.as_ptr(), buf, sz) },
+            |buf| OsString::from_wide(buf),
+        )
+    } else {
+        let path = super::to_ansi(&path)?;
+        let mut buf = vec![0u8; c::MAX_PATH];
+        loop {
+            let len = unsafe {
+                c::GetShortPathNameA(
+                    path.as_ptr() as c::LPCSTR,
+                    buf.as_mut_ptr() as c::LPSTR,
+                    buf.len() as c::DWORD,
+                )
+            } as usize;
+            match len {
+                0 => return Err(Error::last_os_error()),
+                // too small, `len` is the required size including the terminator
+                len if len >= buf.len() => buf.resize(len, 0),
+                len => return Ok(super::from_ansi(&buf[..len])),
+            }
+        }
+    }
+}
+
 pub fn copy(from: &Path, to: &Path) -> io::Result<u64> {
+    let (from, to) = (thread_cwd::resolve(from), thread_cwd::resolve(to));
+    let (from, to) = (&*from, &*to);
     let pfrom = maybe_verbatim(from)?;
     let pto = maybe_verbatim(to)?;
 
@@ -1411,24 +1726,30 @@ pub fn copy(from: &Path, to: &Path) -> io::Result<u64> {
         // If `CopyFileExW` is not available, we have to copy the file with the non-Ex API,
         // then open it with `dwDesiredAccess = 0` (query attributes only),
         // then use `GetFileSize` to retrieve the size
-        cvt(unsafe {
-            c::CopyFileW(
-                pfrom.as_ptr(),
-                pto.as_ptr(),
-                c::FALSE, // FALSE: allow overwriting
-            )
-        })?;
+        let handle = if compat::version::is_windows_nt() {
+            cvt(unsafe {
+                c::CopyFileW(
+                    pfrom.as_ptr(),
+                    pto.as_ptr(),
+                    c::FALSE, // FALSE: allow overwriting
+                )
+            })?;
 
-        let handle = unsafe {
-            c::CreateFileW(
-                pto.as_ptr(),
-                0,
-                c::FILE_SHARE_READ | c::FILE_SHARE_WRITE,
-                ptr::null_mut(),
-                c::OPEN_EXISTING,
-                0,
-                ptr::null_mut(),
-            )
+            unsafe {
+                c::CreateFileW(
+                    pto.as_ptr(),
+                    0,
+                    c::FILE_SHARE_READ | c::FILE_SHARE_WRITE,
+                    ptr::null_mut(),
+                    c::OPEN_EXISTING,
+                    0,
+                    ptr::null_mut(),
+                )
+            }
+        } else {
+            // 9x/ME only implement the ANSI APIs without unicows. `CopyFileA` copies the
+            // attributes along with the data, which is all FAT has.
+            copy_ansi(from, to)?
         };
 
         let handle = if let Ok(handle) = OwnedHandle::try_from(handle) {
@@ -1451,6 +1772,31 @@ pub fn copy(from: &Path, to: &Path) -> io::Result<u64> {
     }
 }
 
+/// `copy` for 9x/ME, returns the destination opened for querying its size.
+fn copy_ansi(from: &Path, to: &Path) -> io::Result<c::HandleOrInvalid> {
+    let from = super::to_ansi(from)?;
+    let to = super::to_ansi(to)?;
+    cvt(unsafe {
+        c::CopyFileA(
+            from.as_ptr() as c::LPCSTR,
+            to.as_ptr() as c::LPCSTR,
+            c::FALSE, // FALSE: allow overwriting
+        )
+    })?;
+
+    Ok(unsafe {
+        c::CreateFileA(
+            to.as_ptr() as c::LPCSTR,
+            0,
+            c::FILE_SHARE_READ | c::FILE_SHARE_WRITE,
+            ptr::null_mut(),
+            c::OPEN_EXISTING,
+            0,
+            ptr::null_mut(),
+        )
+    })
+}
+
 #[allow(dead_code)]
 pub fn symlink_junction<P: AsRef<Path>, Q: AsRef<Path>>(
     original: P,
@@ -1509,6 +1855,186 @@ fn symlink_junction_inner(original: &Path, junction: &Path) -> io::Result<()> {
     }
 }
 
+/// Information about the file system of a volume, see `volume_info`.
+#[derive(Clone, Debug)]
+pub struct VolumeInfo {
+    fs_flags: c::DWORD,
+    max_component_len: c::DWORD,
+    fs_name: OsString,
+}
+
+impl VolumeInfo {
+    /// The `FILE_*` file system flags, like `FILE_SUPPORTS_REPARSE_POINTS`.
+    pub fn fs_flags(&self) -> u32 {
+        self.fs_flags
+    }
+
+    /// The maximum length of a single path component, e.g. 12 (8.3) for FAT without long names.
+    pub fn max_component_len(&self) -> u32 {
+        self.max_component_len
+    }
+
+    /// The name of the file system, e.g. "FAT", "FAT32" or "NTFS".
+    pub fn fs_name(&self) -> &OsStr {
+        &self.fs_name
+    }
+
+    pub fn supports_hard_links(&self) -> bool {
+        // `FILE_SUPPORTS_HARD_LINKS` is only reported since Windows 7
+        self.fs_flags & c::FILE_SUPPORTS_HARD_LINKS != 0 || self.fs_name == "NTFS"
+    }
+
+    pub fn supports_reparse_points(&self) -> bool {
+        self.fs_flags & c::FILE_SUPPORTS_REPARSE_POINTS != 0
+    }
+
+    /// The resolution of modification times, see `timestamp_granularity`.
+    pub fn timestamp_granularity(&self) -> Duration {
+        timestamp_granularity_of(&self.fs_name)
+    }
+}
+
+/// Whether `fs_name` is one of the FAT file systems, which can't store times before 1980.
+fn is_fat(fs_name: &OsStr) -> bool {
+    matches!(fs_name.to_str(), Some("FAT" | "FAT12" | "FAT16" | "FAT32" | "exFAT"))
+}
+
+fn timestamp_granularity_of(fs_name: &OsStr) -> Duration {
+    match fs_name.to_str() {
+        // FAT stores modification times with a 2 second resolution (and in local time)
+        Some("FAT" | "FAT12" | "FAT16" | "FAT32") => Duration::from_secs(2),
+        Some("exFAT") => Duration::from_millis(10),
+        // NTFS and friends store full FILETIMEs
+        _ => Duration::from_nanos(100),
+    }
+}
+
+/// Returns the resolution of modification times on the volume containing `path`.
+///
+/// Up-to-date checks comparing modification times should round to this, as a time that was just
+/// set may come back truncated. This is mostly a concern on 9x/ME, where volumes are usually FAT.
+/// If the volume can't be queried, the coarsest (FAT) granularity is assumed.
+pub fn timestamp_granularity(path: &Path) -> Duration {
+    volume_info(path)
+        .map(|info| info.timestamp_granularity())
+        .unwrap_or_else(|_| timestamp_granularity_of(OsStr::new("FAT")))
+}
+
+/// Queries the file system of the volume containing `path`.
+pub fn volume_info(path: &Path) -> io::Result<VolumeInfo> {
+    let root = volume_root(&thread_cwd::resolve(path))?;
+    let mut fs_flags = 0;
+    let mut max_component_len = 0;
+
+    let fs_name = if compat::wide_apis_available() {
+        let root = to_u16s(&root)?;
+        let mut fs_name = [0u16; c::MAX_PATH + 1];
+        cvt(unsafe {
+            c::GetVolumeInformationW(
+                root.as_ptr(),
+                ptr::null_mut(),
+                0,
+                ptr::null_mut(),
+                &mut max_component_len,
+                &mut fs_flags,
+                fs_name.as_mut_ptr(),
+                fs_name.len() as c::DWORD,
+            )
+        })?;
+        OsString::from_wide(super::truncate_utf16_at_nul(&fs_name))
+    } else {
+        let root = super::to_ansi(&root)?;
+        let mut fs_name = [0u8; c::MAX_PATH + 1];
+        cvt(unsafe {
+            c::GetVolumeInformationA(
+                root.as_ptr() as c::LPCSTR,
+                ptr::null_mut(),
+                0,
+                ptr::null_mut(),
+                &mut max_component_len,
+                &mut fs_flags,
+                fs_name.as_mut_ptr() as c::LPSTR,
+                fs_name.len() as c::DWORD,
+            )
+        })?;
+        let len = fs_name.iter().position(|&b| b == 0).unwrap_or(fs_name.len());
+        super::from_ansi(&fs_name[..len])
+    };
+
+    Ok(VolumeInfo { fs_flags, max_component_len, fs_name })
+}
+
+/// Returns the root of the volume containing `path`, with a trailing backslash.
+fn volume_root(path: &Path) -> io::Result<PathBuf> {
+    if c::GetVolumePathNameW::available() {
+        let path = maybe_verbatim(path)?;
+        let mut root = [0u16; c::MAX_PATH + 1];
+        cvt(unsafe {
+            c::GetVolumePathNameW(path.as_ptr(), root.as_mut_ptr(), root.len() as c::DWORD)
+        })?;
+        return Ok(PathBuf::from(OsString::from_wide(super::truncate_utf16_at_nul(&root))));
+    }
+
+    // 9x/ME and NT 4 don't have mounted folders, so this is just the drive or share
+    let path = if path.is_absolute() { path.to_owned() } else { super::os::getcwd()?.join(path) };
+    match path.components().next() {
+        Some(Component::Prefix(prefix)) => {
+            let mut root = prefix.as_os_str().to_owned();
+            root.push("\\");
+            Ok(PathBuf::from(root))
+        }
+        _ => Err(io::const_io_error!(io::ErrorKind::InvalidInput, "path has no drive or share")),
+    }
+}
+
+/// Converts `t` for `SetFileTime`.
+///
+/// FAT can't store times before 1980, and a FILETIME of all ones means "don't change" to
+/// `SetFileTime`, so both are rejected instead of silently writing something else.
+fn to_file_time(t: SystemTime, fat: bool) -> io::Result<c::FILETIME> {
+    // 1980-01-01 00:00:00 UTC, in 100ns intervals since 1601-01-01
+    const FAT_EPOCH: u64 = 11_960_006_400 * 10_000_000;
+
+    let ft: c::FILETIME = t.into_inner();
+    let intervals = (ft.dwHighDateTime as u64) << 32 | ft.dwLowDateTime as u64;
+    if fat && intervals < FAT_EPOCH {
+        return Err(io::const_io_error!(
+            io::ErrorKind::InvalidInput,
+            "FAT file systems can't store times before 1980",
+        ));
+    }
+    if intervals == u64::MAX {
+        return Err(io::const_io_error!(
+            io::ErrorKind::InvalidInput,
+            "cannot set file time to 0xFFFF_FFFF_FFFF_FFFF",
+        ));
+    }
+    Ok(ft)
+}
+
+/// Sets the last access and modification times of the file at `path`.
+pub fn set_times(
+    path: &Path,
+    accessed: Option<SystemTime>,
+    modified: Option<SystemTime>,
+) -> io::Result<()> {
+    let mut opts = OpenOptions::new();
+    if crate::sys::compat::version::is_windows_nt() {
+        opts.access_mode(c::FILE_WRITE_ATTRIBUTES);
+        // This flag is so we can open directories too
+        opts.custom_flags(c::FILE_FLAG_BACKUP_SEMANTICS);
+    } else {
+        // 9x/ME refuse `SetFileTime` on handles without write access
+        opts.access_mode(c::GENERIC_WRITE);
+    }
+    // unlike the file's handle, its path can be asked for the file system on any version
+    let fat = match volume_info(path) {
+        Ok(info) => is_fat(info.fs_name()),
+        Err(_) => !crate::sys::compat::version::is_windows_nt(),
+    };
+    File::open(path, &opts)?.set_times_as(accessed, modified, fat)
+}
+
 // Try to see if a file exists but, unlike `exists`, report I/O errors.
 pub fn try_exists(path: &Path) -> io::Result<bool> {
     fn match_kind(e: crate::io::Error) -> io::Result<bool> {
@@ -1539,17 +2065,12 @@ fn match_kind(e: crate::io::Error) -> io::Result<bool> {
         // Backup semantics enables opening directories as well as files.
         opts.custom_flags(c::FILE_FLAG_BACKUP_SEMANTICS);
     } else {
-        let path = maybe_verbatim(path)?;
-
-        let attr = unsafe { c::GetFileAttributesW(path.as_ptr()) };
-
-        if attr == c::INVALID_FILE_ATTRIBUTES {
-            return match_kind(io::Error::last_os_error());
-        } else {
+        match attributes_ansi(path) {
+            Ok(None) => return Ok(false),
             // can't open a directory on 9x/ME anyways
-            if attr & c::FILE_ATTRIBUTE_DIRECTORY != 0 {
-                return Ok(true);
-            }
+            Ok(Some(attr)) if attr & c::FILE_ATTRIBUTE_DIRECTORY != 0 => return Ok(true),
+            Ok(Some(_)) => {}
+            Err(e) => return match_kind(e),
         }
     }
 
@@ -0,0 +1,247 @@
+use crate::ffi::OsStr;
+use crate::fs;
+use crate::io::ErrorKind;
+use crate::sys::os::temp_dir;
+use crate::sys_common::io::test::tmpdir;
+use crate::sys_common::IntoInner;
+use crate::time::{Duration, SystemTime};
+
+#[test]
+fn hard_link_or_unsupported() {
+    let tmp = tmpdir();
+    let original = tmp.join("original");
+    let link = tmp.join("link");
+    fs::write(&original, b"hello").unwrap();
+
+    match super::link(&original, &link) {
+        Ok(()) => {
+            assert_eq!(fs::read(&link).unwrap(), b"hello");
+            fs::write(&link, b"world").unwrap();
+            assert_eq!(fs::read(&original).unwrap(), b"world");
+        }
+        // 9x/ME, NT 4 or a temp dir on FAT
+        Err(e) => assert_eq!(e.kind(), ErrorKind::Unsupported),
+    }
+}
+
+// the 9x/ME code path, which works on NT as well
+#[test]
+fn hard_link_ansi_or_unsupported() {
+    let tmp = tmpdir();
+    let original = tmp.join("original");
+    let link = tmp.join("link");
+    fs::write(&original, b"hello").unwrap();
+
+    match super::link_with(&original, &link, false) {
+        Ok(()) => {
+            assert_eq!(fs::read(&link).unwrap(), b"hello");
+            fs::write(&link, b"world").unwrap();
+            assert_eq!(fs::read(&original).unwrap(), b"world");
+        }
+        // NT 4 or a temp dir on FAT
+        Err(e) => assert_eq!(e.kind(), ErrorKind::Unsupported),
+    }
+}
+
+#[test]
+fn volume_info_of_temp_dir() {
+    let info = super::volume_info(&temp_dir()).unwrap();
+    assert!(!info.fs_name().is_empty());
+    assert!(info.max_component_len() >= 12);
+}
+
+#[test]
+fn set_times_round_trips() {
+    let tmp = tmpdir();
+    let path = tmp.join("file");
+    fs::write(&path, b"").unwrap();
+
+    // an even number of seconds, so this works with FAT's 2s resolution as well
+    let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    super::set_times(&path, None, Some(mtime.into_inner())).unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), mtime);
+}
+
+#[test]
+fn fat_rejects_times_before_1980() {
+    let before = SystemTime::UNIX_EPOCH + Duration::from_secs(315_532_799);
+    let after = SystemTime::UNIX_EPOCH + Duration::from_secs(315_532_800);
+
+    let err = super::to_file_time(before.into_inner(), true).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert!(super::to_file_time(after.into_inner(), true).is_ok());
+    assert!(super::to_file_time(before.into_inner(), false).is_ok());
+}
+
+#[test]
+fn pre_1980_times_follow_the_file_system() {
+    let tmp = tmpdir();
+    let path = tmp.join("file");
+    fs::write(&path, b"").unwrap();
+
+    let before = SystemTime::UNIX_EPOCH + Duration::from_secs(315_532_798);
+    let res = super::set_times(&path, None, Some(before.into_inner()));
+    if super::is_fat(super::volume_info(&path).unwrap().fs_name()) {
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::InvalidInput);
+    } else {
+        res.unwrap();
+    }
+}
+
+#[test]
+fn fat_timestamp_granularity() {
+    use super::timestamp_granularity_of as granularity;
+
+    assert_eq!(granularity(OsStr::new("FAT")), Duration::from_secs(2));
+    assert_eq!(granularity(OsStr::new("FAT32")), Duration::from_secs(2));
+    assert_eq!(granularity(OsStr::new("NTFS")), Duration::from_nanos(100));
+    assert!(super::timestamp_granularity(&temp_dir()) <= Duration::from_secs(2));
+}
+
+#[test]
+fn null_device_through_ansi_path() {
+    use super::{File, OpenOptions};
+    use crate::path::Path;
+
+    let mut opts = OpenOptions::new();
+    opts.read(true);
+    opts.write(true);
+    for wide in [false, true] {
+        let null = File::open_with(Path::new("NUL"), &opts, wide).unwrap();
+        assert_eq!(null.write(b"discarded").unwrap(), 9);
+        assert_eq!(null.read(&mut [0; 8]).unwrap(), 0);
+        null.fsync().unwrap();
+    }
+}
+
+#[test]
+fn create_dir_all_through_ansi_path() {
+    let tmp = tmpdir();
+    let deepest = tmp.join("one").join("two").join("three");
+    super::create_dir_all_compat(&deepest).unwrap();
+    assert!(fs::metadata(&deepest).unwrap().is_dir());
+    // existing levels are fine
+    super::create_dir_all_compat(&deepest).unwrap();
+
+    // a file in the way isn't
+    fs::write(tmp.join("file"), b"").unwrap();
+    assert!(super::create_dir_all_compat(&tmp.join("file").join("dir")).is_err());
+
+    let verbatim = super::create_dir_all_compat(r"\\?\C:\rust9x".as_ref()).unwrap_err();
+    assert_eq!(verbatim.kind(), ErrorKind::Unsupported);
+}
+
+#[test]
+fn short_path_of_a_name_with_spaces() {
+    let tmp = tmpdir();
+    let long = tmp.join("a name with spaces.txt");
+    fs::write(&long, b"short").unwrap();
+
+    let short = super::short_path(&long).unwrap();
+    // still the same file either way
+    assert_eq!(fs::read(&short).unwrap(), b"short");
+    let name = crate::path::Path::new(&short).file_name().unwrap().to_str().unwrap();
+    // the long name comes back unchanged if the volume doesn't generate short names
+    if name != "a name with spaces.txt" {
+        assert!(!name.contains(' '), "{:?}", short);
+        assert!(name.len() <= 12, "{:?}", short);
+    }
+
+    assert!(super::short_path(&tmp.join("missing")).is_err());
+}
+
+#[test]
+fn copy_through_ansi_apis() {
+    use crate::convert::TryFrom;
+    use crate::os::windows::io::OwnedHandle;
+
+    let tmp = tmpdir();
+    let from = tmp.join("from");
+    let to = tmp.join("to");
+    fs::write(&from, b"copied by CopyFileA").unwrap();
+    fs::write(&to, b"overwritten").unwrap();
+
+    let handle = super::copy_ansi(&from, &to).unwrap();
+    drop(OwnedHandle::try_from(handle).unwrap());
+    assert_eq!(fs::read(&to).unwrap(), b"copied by CopyFileA");
+    assert_eq!(fs::metadata(&to).unwrap().len(), 19);
+
+    assert!(super::copy_ansi(&tmp.join("missing"), &to).is_err());
+}
+
+#[test]
+fn truncate_and_extend_by_seeking() {
+    use super::{File, OpenOptions};
+    use crate::io::SeekFrom;
+
+    let tmp = tmpdir();
+    let path = tmp.join("truncated");
+    let mut opts = OpenOptions::new();
+    opts.read(true);
+    opts.write(true);
+    opts.create(true);
+    let file = File::open_with(&path, &opts, false).unwrap();
+    file.write(b"hello world").unwrap();
+    file.seek(SeekFrom::Start(3)).unwrap();
+
+    file.truncate_by_seeking(5).unwrap();
+    assert_eq!(file.file_attr().unwrap().size(), 5);
+    file.truncate_by_seeking(8).unwrap();
+    assert_eq!(file.file_attr().unwrap().size(), 8);
+    // the position is kept
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 3);
+
+    drop(file);
+    assert_eq!(fs::read(&path).unwrap(), b"hello\0\0\0");
+}
+
+#[test]
+fn attributes_through_ansi_apis() {
+    use crate::sys::c;
+
+    let tmp = tmpdir();
+    let file = tmp.join("file");
+    fs::write(&file, b"").unwrap();
+
+    let dir = super::attributes_ansi(tmp.path()).unwrap().unwrap();
+    assert!(dir & c::FILE_ATTRIBUTE_DIRECTORY != 0);
+    let file_attributes = super::attributes_ansi(&file).unwrap().unwrap();
+    assert!(file_attributes & c::FILE_ATTRIBUTE_DIRECTORY == 0);
+
+    // neither a missing file nor a missing parent directory is an error
+    assert_eq!(super::attributes_ansi(&tmp.join("missing")).unwrap(), None);
+    assert_eq!(super::attributes_ansi(&tmp.join("missing").join("file")).unwrap(), None);
+}
+
+#[test]
+fn replace_through_ansi_moves() {
+    use crate::cell::Cell;
+
+    let tmp = tmpdir();
+    let old = tmp.join("old");
+    let new = tmp.join("new");
+    fs::write(&old, b"old").unwrap();
+    fs::write(&new, b"new").unwrap();
+    super::replace_with(&old, &new, super::move_file_ansi).unwrap();
+    assert_eq!(fs::read(&new).unwrap(), b"old");
+    assert!(!old.exists());
+    // nothing is left behind
+    assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+
+    // fail moving `old` into place, after `new` was moved aside
+    fs::write(&old, b"old").unwrap();
+    let calls = Cell::new(0);
+    let failing = |from: &crate::path::Path, to: &crate::path::Path| {
+        calls.set(calls.get() + 1);
+        if calls.get() == 3 {
+            return Err(crate::io::Error::from_raw_os_error(5));
+        }
+        super::move_file_ansi(from, to)
+    };
+    let err = super::replace_with(&old, &new, failing).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(5));
+    assert_eq!(calls.get(), 4);
+    assert_eq!(fs::read(&old).unwrap(), b"old");
+    assert_eq!(fs::read(&new).unwrap(), b"old");
+    assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 2);
+}
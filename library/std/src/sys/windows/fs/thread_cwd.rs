@@ -0,0 +1,81 @@
+//! Per-thread current directories.
+//!
+//! Windows only has a process-wide current directory, and on 9x/ME `SetCurrentDirectoryA` in one
+//! thread races with relative paths being resolved in every other one. A thread that needs stable
+//! relative paths can set a directory of its own here, which all functions in `fs` that take a
+//! path then resolve relative paths against, instead of the process one. Other threads are not
+//! affected. Symlink targets are the exception, as relative ones are relative to the link.
+//!
+//! This is opt-in: until the first `set`, resolving a path is a single atomic load. After that,
+//! every call with a path also looks up the thread-local, and relative paths on threads with a
+//! directory of their own are copied to be joined to it. While the thread-locals of a thread are
+//! destroyed, its paths are no longer resolved, as if it had no directory of its own.
+
+use crate::borrow::Cow;
+use crate::cell::RefCell;
+use crate::io;
+use crate::path::{Component, Path, PathBuf};
+use crate::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(test)]
+mod tests;
+
+/// Whether any thread ever set a directory, to skip the thread-local lookup otherwise.
+static IN_USE: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static THREAD_CWD: RefCell<Option<PathBuf>> = RefCell::new(None);
+}
+
+/// Sets the directory relative paths are resolved against on this thread, or goes back to the
+/// process' current directory with `None`. A relative `dir` is resolved against the current
+/// directory of the thread first.
+pub fn set(dir: Option<&Path>) -> io::Result<()> {
+    let dir = match dir {
+        Some(dir) => {
+            let dir = crate::path::absolute(resolve(dir))?;
+            if !super::stat(&dir)?.file_type().is_dir() {
+                return Err(io::const_io_error!(
+                    io::ErrorKind::NotADirectory,
+                    "the thread's current directory has to be a directory",
+                ));
+            }
+            IN_USE.store(true, Ordering::Relaxed);
+            Some(dir)
+        }
+        None => None,
+    };
+    THREAD_CWD.with(|cwd| *cwd.borrow_mut() = dir);
+    Ok(())
+}
+
+/// The directory set with `set` on this thread, if any.
+pub fn get() -> Option<PathBuf> {
+    if !IN_USE.load(Ordering::Relaxed) {
+        return None;
+    }
+    THREAD_CWD.try_with(|cwd| cwd.borrow().clone()).ok().flatten()
+}
+
+/// Resolves `path` against the directory of this thread, if it has one. Paths that are rooted
+/// without a drive (`\dir`) get the drive of the thread's directory, while paths relative to the
+/// current directory of a drive (`C:dir`) are left to the system, as they don't use the current
+/// directory of the process either.
+pub fn resolve(path: &Path) -> Cow<'_, Path> {
+    if !IN_USE.load(Ordering::Relaxed) || path.is_absolute() {
+        return Cow::Borrowed(path);
+    }
+    let resolved = THREAD_CWD.try_with(|cwd| match (&*cwd.borrow(), path.components().next()) {
+        (None, _) | (_, Some(Component::Prefix(_))) => Cow::Borrowed(path),
+        (Some(cwd), Some(Component::RootDir)) => match cwd.components().next() {
+            Some(Component::Prefix(prefix)) => {
+                let mut resolved = PathBuf::from(prefix.as_os_str());
+                resolved.push(path);
+                Cow::Owned(resolved)
+            }
+            _ => Cow::Borrowed(path),
+        },
+        (Some(cwd), _) => Cow::Owned(cwd.join(path)),
+    });
+    resolved.unwrap_or(Cow::Borrowed(path))
+}
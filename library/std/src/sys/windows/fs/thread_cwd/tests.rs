@@ -0,0 +1,91 @@
+use super::{get, resolve, set};
+use crate::env;
+use crate::fs;
+use crate::io::Read;
+use crate::path::Path;
+use crate::thread;
+
+#[test]
+fn threads_open_relative_paths_in_their_own_directory() {
+    let base = env::temp_dir().join(format!("rust9x-thread-cwd-{}", crate::process::id()));
+    let dirs = [base.join("a"), base.join("b")];
+    for (dir, contents) in dirs.iter().zip(["first", "second"]) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("same.txt"), contents).unwrap();
+    }
+
+    let threads: Vec<_> = dirs
+        .iter()
+        .cloned()
+        .map(|dir| {
+            thread::spawn(move || {
+                set(Some(&dir)).unwrap();
+                assert_eq!(get().as_deref(), Some(&*dir));
+                let mut contents = String::new();
+                fs::File::open("same.txt").unwrap().read_to_string(&mut contents).unwrap();
+                let listed: Vec<_> = fs::read_dir(".").unwrap().map(|e| e.unwrap()).collect();
+                assert_eq!(listed.len(), 1);
+                contents
+            })
+        })
+        .collect();
+    let contents: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+    assert_eq!(contents, ["first", "second"]);
+
+    // threads without a directory of their own aren't affected
+    assert_eq!(get(), None);
+    assert_eq!(resolve(Path::new("same.txt")), Path::new("same.txt"));
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn every_fs_function_uses_the_thread_directory() {
+    let dir = env::temp_dir().join(format!("rust9x-thread-cwd-fs-{}", crate::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let inside = dir.clone();
+    thread::spawn(move || {
+        set(Some(&inside)).unwrap();
+        fs::create_dir("sub").unwrap();
+        fs::write("sub/file.txt", "contents").unwrap();
+        assert!(fs::metadata("sub").unwrap().is_dir());
+        assert_eq!(fs::copy("sub/file.txt", "copy.txt").unwrap(), 8);
+        fs::rename("copy.txt", "renamed.txt").unwrap();
+        assert!(fs::metadata("renamed.txt").unwrap().is_file());
+        let mut perms = fs::metadata("renamed.txt").unwrap().permissions();
+        perms.set_readonly(false);
+        fs::set_permissions("renamed.txt", perms).unwrap();
+        fs::remove_file("renamed.txt").unwrap();
+        fs::remove_file("sub/file.txt").unwrap();
+        fs::remove_dir("sub").unwrap();
+    })
+    .join()
+    .unwrap();
+
+    // all of it happened in `dir`, and nothing is left there
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn resolving_during_thread_local_destruction_does_not_panic() {
+    struct ResolveOnDrop(crate::path::PathBuf);
+    impl Drop for ResolveOnDrop {
+        fn drop(&mut self) {
+            // depends on whether the directory of the thread was destroyed already
+            let resolved = resolve(Path::new("late.txt"));
+            assert!(resolved == Path::new("late.txt") || resolved == self.0.join("late.txt"));
+        }
+    }
+    thread_local! {
+        static LATE: crate::cell::RefCell<Option<ResolveOnDrop>> = Default::default();
+    }
+
+    thread::spawn(|| {
+        let dir = env::temp_dir();
+        set(Some(&dir)).unwrap();
+        LATE.with(|late| *late.borrow_mut() = Some(ResolveOnDrop(get().unwrap())));
+    })
+    .join()
+    .unwrap();
+}
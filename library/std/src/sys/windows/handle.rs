@@ -11,6 +11,9 @@
 use crate::sys::cvt;
 use crate::sys_common::{AsInner, FromInner, IntoInner};
 
+#[cfg(test)]
+mod tests;
+
 /// An owned container for `HANDLE` object, closing them on Drop.
 ///
 /// All methods are inherited through a `Deref` impl to `RawHandle`
@@ -324,3 +327,50 @@ fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
         (**self).read_vectored(bufs)
     }
 }
+
+/// How a successful `wait` ended.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WaitOutcome {
+    Signaled,
+    /// The handle is a mutex whose owner thread exited without releasing it. The wait acquired it
+    /// nonetheless.
+    Abandoned,
+    TimedOut,
+}
+
+/// Waits up to `timeout` milliseconds for `handle` to be signaled. `WAIT_FAILED` (e.g. for an
+/// invalid handle) is turned into the error it stands for.
+pub fn wait(handle: c::HANDLE, timeout: c::DWORD) -> io::Result<WaitOutcome> {
+    match unsafe { c::WaitForSingleObject(handle, timeout) } {
+        c::WAIT_OBJECT_0 => Ok(WaitOutcome::Signaled),
+        c::WAIT_ABANDONED => Ok(WaitOutcome::Abandoned),
+        c::WAIT_TIMEOUT => Ok(WaitOutcome::TimedOut),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Makes `handle` (non-)inheritable.
+///
+/// `SetHandleInformation` is only a stub on 9x/ME (and doesn't exist before NT 3.51), where
+/// inheritance can't be changed in place. There, `handle` is replaced by a duplicate with the
+/// desired flag, and the original is closed. Note that 9x/ME only pass inheritable handles to
+/// children created with `bInheritHandles`, like NT, but also ignore the flag for some kinds of
+/// handles, sockets in particular.
+pub fn set_inheritable(handle: &mut Handle, inheritable: bool) -> io::Result<()> {
+    let flags = if inheritable { c::HANDLE_FLAG_INHERIT } else { 0 };
+    match cvt(unsafe {
+        c::SetHandleInformation(handle.as_raw_handle(), c::HANDLE_FLAG_INHERIT, flags)
+    }) {
+        Ok(_) => Ok(()),
+        Err(e) if e.raw_os_error() == Some(c::ERROR_CALL_NOT_IMPLEMENTED as i32) => {
+            set_inheritable_by_duplicating(handle, inheritable)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn set_inheritable_by_duplicating(handle: &mut Handle, inheritable: bool) -> io::Result<()> {
+    // dropping the original closes it
+    *handle = handle.duplicate(0, inheritable, c::DUPLICATE_SAME_ACCESS)?;
+    Ok(())
+}
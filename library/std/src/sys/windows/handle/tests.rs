@@ -0,0 +1,55 @@
+use super::{set_inheritable, set_inheritable_by_duplicating, Handle};
+use crate::os::windows::io::AsRawHandle;
+use crate::sys::c;
+use crate::sys::pipe::anon_pipe;
+
+#[test]
+fn read_through_duplicate() {
+    let pipes = anon_pipe(true, false).unwrap();
+    for inheritable in [false, true] {
+        let dup = pipes.ours.handle().duplicate(0, inheritable, c::DUPLICATE_SAME_ACCESS).unwrap();
+        assert_ne!(dup.as_raw_handle(), pipes.ours.handle().as_raw_handle());
+
+        assert_eq!(pipes.theirs.write(b"dup").unwrap(), 3);
+        let mut buf = [0; 3];
+        assert_eq!(dup.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf, b"dup");
+    }
+}
+
+fn is_inheritable(handle: c::HANDLE) -> bool {
+    let mut flags = 0;
+    assert!(unsafe { c::GetHandleInformation(handle, &mut flags) } != 0);
+    flags & c::HANDLE_FLAG_INHERIT != 0
+}
+
+#[test]
+fn inheritance_is_changed() {
+    if !c::GetHandleInformation::available() {
+        return;
+    }
+    let pipes = anon_pipe(true, false).unwrap();
+    let mut handle = pipes.ours.handle().duplicate(0, true, c::DUPLICATE_SAME_ACCESS).unwrap();
+    assert!(is_inheritable(handle.as_raw_handle()));
+
+    // what 9x/ME do
+    set_inheritable_by_duplicating(&mut handle, false).unwrap();
+    assert!(!is_inheritable(handle.as_raw_handle()));
+
+    set_inheritable(&mut handle, true).unwrap();
+    assert!(is_inheritable(handle.as_raw_handle()));
+}
+
+#[test]
+fn wait_on_invalid_handle_fails_cleanly() {
+    use super::{wait, WaitOutcome};
+    use crate::ptr;
+
+    let err = wait(ptr::null_mut(), 0).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(c::ERROR_INVALID_HANDLE as i32));
+
+    let event = Handle::new_event(true, false).unwrap();
+    assert_eq!(wait(event.as_raw_handle(), 0).unwrap(), WaitOutcome::TimedOut);
+    assert!(unsafe { c::SetEvent(event.as_raw_handle()) } != 0);
+    assert_eq!(wait(event.as_raw_handle(), 0).unwrap(), WaitOutcome::Signaled);
+}
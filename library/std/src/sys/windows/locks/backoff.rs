@@ -0,0 +1,61 @@
+//! Timed acquisition for the locks that only come with a `try_*` operation.
+
+use crate::hint;
+use crate::sync::atomic::{AtomicU8, Ordering};
+use crate::sys::{c, thread};
+use crate::time::{Duration, Instant};
+
+const SPIN_UNKNOWN: u8 = 0;
+const SPIN: u8 = 1;
+const NO_SPIN: u8 = 2;
+
+static SPIN_DECISION: AtomicU8 = AtomicU8::new(SPIN_UNKNOWN);
+
+/// Rounds of busy-waiting, doubling the number of spins each round.
+const SPIN_ROUNDS: u32 = 7;
+/// Rounds of `Sleep(0)` after spinning, before sleeping for real.
+const YIELD_ROUNDS: u32 = 4;
+
+/// Whether busy-waiting for a lock makes sense, i.e. whether there's another processor the holder
+/// could be running on meanwhile. On uniprocessor systems spinning only delays the holder.
+pub fn should_spin() -> bool {
+    match SPIN_DECISION.load(Ordering::Relaxed) {
+        SPIN => true,
+        NO_SPIN => false,
+        _ => {
+            let spin = thread::available_parallelism().map_or(false, |n| n.get() > 1);
+            SPIN_DECISION.store(if spin { SPIN } else { NO_SPIN }, Ordering::Relaxed);
+            spin
+        }
+    }
+}
+
+/// Calls `try_acquire` until it succeeds or `dur` has passed, backing off in between. It's always
+/// called at least once, so a zero `dur` is the same as a plain `try_*`.
+pub fn try_for(dur: Duration, mut try_acquire: impl FnMut() -> bool) -> bool {
+    if try_acquire() {
+        return true;
+    }
+    // `None` is as good as waiting forever.
+    let deadline = Instant::now().checked_add(dur);
+    let mut round = if should_spin() { 0 } else { SPIN_ROUNDS };
+    loop {
+        if round < SPIN_ROUNDS {
+            for _ in 0..1 << round {
+                hint::spin_loop();
+            }
+        } else {
+            // `Sleep(0)` only gives up the time slice to threads of the same priority, so a
+            // lower-priority holder needs an actual sleep to get to run.
+            unsafe { c::Sleep(if round < SPIN_ROUNDS + YIELD_ROUNDS { 0 } else { 1 }) };
+        }
+        round = round.saturating_add(1);
+
+        if try_acquire() {
+            return true;
+        }
+        if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+            return false;
+        }
+    }
+}
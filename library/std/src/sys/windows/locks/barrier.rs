@@ -0,0 +1,85 @@
+//! A barrier built from the mutex and condvar of the selected kind.
+//!
+//! Without SRW locks, the condvar is an `EventCondvar`, whose notifications stay pending until a
+//! waiter takes them, so a release can't be missed however the threads are scheduled, even with
+//! more threads than `num_threads` passing through.
+
+#[cfg(test)]
+mod tests;
+
+use super::{Condvar, Mutex};
+use crate::cell::UnsafeCell;
+use crate::panic::RefUnwindSafe;
+
+pub struct Barrier {
+    lock: Mutex,
+    cvar: Condvar,
+    state: UnsafeCell<State>,
+    num_threads: usize,
+}
+
+/// Protected by `lock`.
+struct State {
+    count: usize,
+    generation: usize,
+}
+
+unsafe impl Send for Barrier {}
+unsafe impl Sync for Barrier {}
+
+// `sync::Barrier` was unwind safe before it used this, and a panic can't leave `state` broken.
+impl RefUnwindSafe for Barrier {}
+
+impl Barrier {
+    pub fn new(num_threads: usize) -> Barrier {
+        let mut barrier = Barrier {
+            lock: Mutex::new(),
+            cvar: Condvar::new(),
+            state: UnsafeCell::new(State { count: 0, generation: 0 }),
+            num_threads,
+        };
+        unsafe {
+            barrier.lock.init();
+            barrier.cvar.init();
+        }
+        barrier
+    }
+
+    /// Blocks until `num_threads` threads are waiting, and returns whether the calling thread
+    /// released the others (is the leader).
+    pub fn wait(&self) -> bool {
+        unsafe {
+            self.lock.lock();
+            let state = &mut *self.state.get();
+            let generation = state.generation;
+            state.count += 1;
+            if state.count < self.num_threads {
+                while self.generation() == generation {
+                    self.cvar.wait(&self.lock);
+                }
+                self.lock.unlock();
+                false
+            } else {
+                state.count = 0;
+                state.generation = generation.wrapping_add(1);
+                self.cvar.notify_all();
+                self.lock.unlock();
+                true
+            }
+        }
+    }
+
+    /// `lock` has to be locked.
+    unsafe fn generation(&self) -> usize {
+        (*self.state.get()).generation
+    }
+}
+
+impl Drop for Barrier {
+    fn drop(&mut self) {
+        unsafe {
+            self.lock.destroy();
+            self.cvar.destroy();
+        }
+    }
+}
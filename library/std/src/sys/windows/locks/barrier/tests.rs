@@ -0,0 +1,66 @@
+use super::Barrier;
+use crate::sync::atomic::{AtomicUsize, Ordering};
+use crate::sync::Arc;
+use crate::thread;
+
+#[test]
+fn no_thread_races_ahead() {
+    const THREADS: usize = 16;
+    const ROUNDS: usize = 1000;
+
+    let barrier = Arc::new(Barrier::new(THREADS));
+    let arrived = Arc::new(AtomicUsize::new(0));
+    let leaders = Arc::new(AtomicUsize::new(0));
+
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let (barrier, arrived, leaders) = (barrier.clone(), arrived.clone(), leaders.clone());
+            thread::spawn(move || {
+                for round in 0..ROUNDS {
+                    arrived.fetch_add(1, Ordering::SeqCst);
+                    if barrier.wait() {
+                        leaders.fetch_add(1, Ordering::SeqCst);
+                    }
+                    let arrived = arrived.load(Ordering::SeqCst);
+                    // everyone arrived for this round, but nobody got past the next one
+                    assert!(arrived >= (round + 1) * THREADS, "woken up early in {}", round);
+                    assert!(arrived <= (round + 2) * THREADS, "raced ahead in {}", round);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    assert_eq!(leaders.load(Ordering::SeqCst), ROUNDS);
+}
+
+// Waiters of one generation may not have left yet when the next one completes with other threads.
+#[test]
+fn more_threads_than_the_barrier_admits() {
+    const SIZE: usize = 4;
+    const THREADS: usize = 3 * SIZE;
+    const ROUNDS: usize = 500;
+
+    let barrier = Arc::new(Barrier::new(SIZE));
+    let leaders = Arc::new(AtomicUsize::new(0));
+
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let (barrier, leaders) = (barrier.clone(), leaders.clone());
+            thread::spawn(move || {
+                for _ in 0..ROUNDS {
+                    if barrier.wait() {
+                        leaders.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    assert_eq!(leaders.load(Ordering::SeqCst), THREADS * ROUNDS / SIZE);
+}
@@ -1,9 +1,7 @@
 use crate::cell::UnsafeCell;
-use crate::io;
 use crate::mem::size_of;
-use crate::ptr;
 use crate::sys::{
-    c, cvt,
+    c,
     locks::{
         mutex::compat::{MutexKind, MUTEX_KIND},
         Mutex,
@@ -11,7 +9,14 @@
     os,
     windows::dur2timeout,
 };
-use crate::time::Duration;
+use crate::time::{Duration, Instant};
+
+mod event_condvar;
+
+#[cfg(test)]
+mod tests;
+
+use event_condvar::EventCondvar;
 
 pub struct Condvar {
     inner: UnsafeCell<usize>,
@@ -26,11 +31,11 @@ impl Condvar {
     pub const fn new() -> Condvar {
         // a `CONDITION_VARIABLE` (modern SRW impl) is `usize`-sized, and the correct
         // `CONDITION_VARIABLE_INIT` value happens to be zeroed. this happens to also be a valid
-        // (zero) init for the fallback `HANDLE`.
+        // (null) init for the pointer to the fallback `EventCondvar`.
 
         const _assertions: () = {
             if size_of::<usize>() != size_of::<c::CONDITION_VARIABLE>()
-                || size_of::<usize>() < size_of::<c::HANDLE>()
+                || size_of::<usize>() < size_of::<*mut EventCondvar>()
             {
                 panic!("fallback implementation invalid")
             }
@@ -44,18 +49,7 @@ pub unsafe fn init(&mut self) {
         match MUTEX_KIND {
             MutexKind::SrwLock => {}
             MutexKind::CriticalSection | MutexKind::Legacy => {
-                let evt_handle = c::CreateEventA(
-                    ptr::null_mut(),
-                    c::TRUE, // manual reset event
-                    c::FALSE,
-                    ptr::null(),
-                );
-
-                if evt_handle.is_null() {
-                    panic!("failed creating event: {}", io::Error::last_os_error());
-                }
-
-                *self.inner.get() = evt_handle as usize;
+                *self.inner.get() = Box::into_raw(EventCondvar::new()) as usize;
             }
         }
     }
@@ -73,13 +67,7 @@ pub unsafe fn wait(&self, mutex: &Mutex) {
                 debug_assert!(r != 0);
             }
             MutexKind::CriticalSection | MutexKind::Legacy => {
-                mutex.unlock();
-                if (c::WaitForSingleObject((*self.inner.get()) as c::HANDLE, c::INFINITE))
-                    != c::WAIT_OBJECT_0
-                {
-                    panic!("event wait failed: {}", io::Error::last_os_error())
-                }
-                mutex.lock();
+                self.fallback().wait(mutex, None);
             }
         }
     }
@@ -101,30 +89,38 @@ pub unsafe fn wait_timeout(&self, mutex: &Mutex, dur: Duration) -> bool {
                 }
             }
             MutexKind::CriticalSection | MutexKind::Legacy => {
-                mutex.unlock();
-                let ret = match c::WaitForSingleObject(
-                    (*self.inner.get()) as c::HANDLE,
-                    dur2timeout(dur),
-                ) {
-                    c::WAIT_OBJECT_0 => true,
-                    c::WAIT_TIMEOUT => false,
-                    _ => panic!("event wait failed: {}", io::Error::last_os_error()),
-                };
-                mutex.lock();
-                ret
+                self.fallback().wait(mutex, Instant::now().checked_add(dur))
+            }
+        }
+    }
+
+    /// Waits while `condition` returns true, but not past `deadline`, checking it again after
+    /// every wakeup, spurious ones included. `mutex` has to be locked, and `condition` is only
+    /// called with it locked. Returns whether `condition` stopped holding in time.
+    ///
+    /// Both backends can wake up spuriously (the fallback one when another waiter took the
+    /// notification meant for it), so each wait only gets the time that is left until `deadline`.
+    pub unsafe fn wait_timeout_while<F: FnMut() -> bool>(
+        &self,
+        mutex: &Mutex,
+        deadline: Instant,
+        mut condition: F,
+    ) -> bool {
+        while condition() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
             }
+            self.wait_timeout(mutex, deadline - now);
         }
+        true
     }
 
     #[inline]
     pub unsafe fn notify_one(&self) {
         match MUTEX_KIND {
             MutexKind::SrwLock => c::WakeConditionVariable(self.inner.get().cast()),
-            MutexKind::CriticalSection | MutexKind::Legacy => {
-                // this currently wakes up all threads, but spurious wakeups are allowed, so this is
-                // "just" reducing perf
-                cvt(c::PulseEvent((*self.inner.get()) as c::HANDLE)).unwrap();
-            }
+            MutexKind::CriticalSection | MutexKind::Legacy => self.fallback().notify_one(),
         }
     }
 
@@ -132,9 +128,7 @@ pub unsafe fn notify_one(&self) {
     pub unsafe fn notify_all(&self) {
         match MUTEX_KIND {
             MutexKind::SrwLock => c::WakeAllConditionVariable(self.inner.get().cast()),
-            MutexKind::CriticalSection | MutexKind::Legacy => {
-                cvt(c::PulseEvent((*self.inner.get()) as c::HANDLE)).unwrap();
-            }
+            MutexKind::CriticalSection | MutexKind::Legacy => self.fallback().notify_all(),
         };
     }
 
@@ -142,8 +136,14 @@ pub unsafe fn destroy(&self) {
         match MUTEX_KIND {
             MutexKind::SrwLock => {}
             MutexKind::CriticalSection | MutexKind::Legacy => {
-                cvt(c::CloseHandle((*self.inner.get()) as c::HANDLE)).unwrap();
+                drop(Box::from_raw((*self.inner.get()) as *mut EventCondvar));
             }
         };
     }
+
+    /// Only valid for the CS and legacy kinds, after `init`.
+    #[inline]
+    unsafe fn fallback(&self) -> &EventCondvar {
+        &*((*self.inner.get()) as *const EventCondvar)
+    }
 }
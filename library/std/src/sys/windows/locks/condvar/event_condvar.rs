@@ -0,0 +1,179 @@
+use crate::cell::UnsafeCell;
+use crate::io;
+use crate::ptr;
+use crate::sys::handle::{wait, WaitOutcome};
+use crate::sys::locks::mutex::critical_section_mutex::CriticalSectionMutex;
+use crate::sys::locks::Mutex;
+use crate::sys::{c, cvt, dur2timeout};
+use crate::time::Instant;
+
+/// Condition variable for the CS and legacy mutex kinds, built from a semaphore and an event.
+///
+/// `PulseEvent` can't be used for this, as it only releases the threads that are in the wait at
+/// that moment, so a waiter that has released the mutex but not started waiting yet misses it.
+/// Instead, waiters wait on a semaphore, which `notify_one` releases once and `notify_all` once
+/// per waiter, so that a notification stays pending until a waiter takes it, and `notify_one`
+/// wakes exactly one thread.
+///
+/// Threads that start waiting after a notification must not take it from the ones that were
+/// already waiting, so they're held at `gate` until all pending notifications were taken, as in
+/// the algorithm 8a of pthreads-win32 (Terekhov, Thomas).
+pub struct EventCondvar {
+    semaphore: c::HANDLE,
+    /// Manual-reset event, set while there are no pending notifications.
+    gate: c::HANDLE,
+    /// Guards `state`, `notify_*` can be called without holding the user's mutex.
+    lock: CriticalSectionMutex,
+    state: UnsafeCell<State>,
+}
+
+struct State {
+    /// Threads in `wait` that weren't notified yet.
+    blocked: usize,
+    /// Notifications released on the semaphore that no waiter has taken yet.
+    to_unblock: usize,
+}
+
+unsafe impl Send for EventCondvar {}
+unsafe impl Sync for EventCondvar {}
+
+impl EventCondvar {
+    /// Boxed, as the critical section can't be moved after initialization.
+    pub fn new() -> Box<Self> {
+        let semaphore =
+            unsafe { c::CreateSemaphoreA(ptr::null_mut(), 0, c::LONG::MAX, ptr::null()) };
+        if semaphore.is_null() {
+            panic!("failed creating semaphore: {}", io::Error::last_os_error());
+        }
+        let gate = unsafe {
+            c::CreateEventA(
+                ptr::null_mut(),
+                c::TRUE, // manual reset event
+                c::TRUE,
+                ptr::null(),
+            )
+        };
+        if gate.is_null() {
+            panic!("failed creating event: {}", io::Error::last_os_error());
+        }
+
+        let condvar = box EventCondvar {
+            semaphore,
+            gate,
+            lock: CriticalSectionMutex::new(),
+            state: UnsafeCell::new(State { blocked: 0, to_unblock: 0 }),
+        };
+        unsafe { condvar.lock.init() };
+        condvar
+    }
+
+    unsafe fn with_state<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        self.lock.lock();
+        let res = f(&mut *self.state.get());
+        self.lock.unlock();
+        res
+    }
+
+    /// Waits until notified, or until `deadline` if there is one. `mutex` has to be locked, and is
+    /// locked again when this returns. Returns `false` on a timeout.
+    pub unsafe fn wait(&self, mutex: &Mutex, deadline: Option<Instant>) -> bool {
+        // Still holding `mutex`, so that no notification can be missed. The waiters holding up
+        // the gate don't need it to take their notifications.
+        loop {
+            let entered = self.with_state(|state| {
+                if state.to_unblock > 0 {
+                    return false;
+                }
+                state.blocked += 1;
+                true
+            });
+            if entered {
+                break;
+            }
+            if let Err(e) = wait(self.gate, c::INFINITE) {
+                panic!("event wait failed: {}", e);
+            }
+        }
+        mutex.unlock();
+
+        let notified = loop {
+            let timeout = match deadline {
+                Some(deadline) => dur2timeout(deadline.saturating_duration_since(Instant::now())),
+                None => c::INFINITE,
+            };
+            match wait(self.semaphore, timeout) {
+                // `dur2timeout` clamps, so this may still be before the deadline
+                Ok(WaitOutcome::TimedOut) if deadline.map_or(true, |d| Instant::now() < d) => {}
+                Ok(WaitOutcome::TimedOut) => break false,
+                Ok(_) => break true,
+                Err(e) => panic!("semaphore wait failed: {}", e),
+            }
+        };
+
+        let notified = self.with_state(|state| {
+            if !notified && state.blocked > 0 {
+                // Either no notification was meant for this thread, or one that is still blocked
+                // takes it instead.
+                state.blocked -= 1;
+                return false;
+            }
+            if !notified {
+                // All remaining waiters were notified, so this one was too, and its notification
+                // is still on the semaphore, as the others each take only one.
+                if let Err(e) = wait(self.semaphore, c::INFINITE) {
+                    panic!("semaphore wait failed: {}", e);
+                }
+            }
+            state.to_unblock -= 1;
+            if state.to_unblock == 0 {
+                cvt(c::SetEvent(self.gate)).unwrap();
+            }
+            true
+        });
+
+        mutex.lock();
+        notified
+    }
+
+    /// Wakes exactly one waiter, if there is one.
+    pub unsafe fn notify_one(&self) {
+        self.with_state(|state| {
+            if state.blocked > 0 {
+                self.release(state, 1);
+            }
+        })
+    }
+
+    pub unsafe fn notify_all(&self) {
+        self.with_state(|state| {
+            if state.blocked > 0 {
+                self.release(state, state.blocked);
+            }
+        })
+    }
+
+    unsafe fn release(&self, state: &mut State, count: usize) {
+        if state.to_unblock == 0 {
+            cvt(c::ResetEvent(self.gate)).unwrap();
+        }
+        state.blocked -= count;
+        state.to_unblock += count;
+        cvt(c::ReleaseSemaphore(self.semaphore, count as c::LONG, ptr::null_mut())).unwrap();
+    }
+
+    /// The threads that are waiting and weren't notified yet.
+    #[cfg(test)]
+    pub fn blocked(&self) -> usize {
+        unsafe { self.with_state(|state| state.blocked) }
+    }
+}
+
+impl Drop for EventCondvar {
+    fn drop(&mut self) {
+        unsafe {
+            self.lock.destroy();
+            cvt(c::CloseHandle(self.semaphore)).unwrap();
+            cvt(c::CloseHandle(self.gate)).unwrap();
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use super::event_condvar::EventCondvar;
+use super::Condvar;
+use crate::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::sync::Arc;
+use crate::sys::locks::Mutex;
+use crate::thread;
+use crate::time::{Duration, Instant};
+
+struct Shared {
+    mutex: Mutex,
+    condvar: Condvar,
+    ready: AtomicBool,
+}
+
+#[test]
+fn wait_timeout_while_survives_spurious_wakeups() {
+    let shared = unsafe {
+        let mut mutex = Mutex::new();
+        mutex.init();
+        let mut condvar = Condvar::new();
+        condvar.init();
+        Arc::new(Shared { mutex, condvar, ready: AtomicBool::new(false) })
+    };
+
+    let notifier = {
+        let shared = shared.clone();
+        thread::spawn(move || unsafe {
+            // a wakeup without a change, then the real one
+            thread::sleep(Duration::from_millis(20));
+            shared.mutex.lock();
+            shared.condvar.notify_all();
+            shared.mutex.unlock();
+            thread::sleep(Duration::from_millis(20));
+            shared.mutex.lock();
+            shared.ready.store(true, Ordering::Relaxed);
+            shared.condvar.notify_all();
+            shared.mutex.unlock();
+        })
+    };
+
+    let checks = AtomicUsize::new(0);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let satisfied = unsafe {
+        shared.mutex.lock();
+        let satisfied = shared.condvar.wait_timeout_while(&shared.mutex, deadline, || {
+            checks.fetch_add(1, Ordering::Relaxed);
+            !shared.ready.load(Ordering::Relaxed)
+        });
+        shared.mutex.unlock();
+        satisfied
+    };
+    notifier.join().unwrap();
+
+    assert!(satisfied);
+    assert!(Instant::now() < deadline);
+    assert!(checks.load(Ordering::Relaxed) >= 2);
+
+    // and it gives up at the deadline
+    let deadline = Instant::now() + Duration::from_millis(30);
+    let satisfied = unsafe {
+        shared.mutex.lock();
+        let satisfied = shared.condvar.wait_timeout_while(&shared.mutex, deadline, || true);
+        shared.mutex.unlock();
+        satisfied
+    };
+    assert!(!satisfied);
+    assert!(Instant::now() >= deadline);
+    unsafe { shared.condvar.destroy() };
+}
+
+#[test]
+fn event_condvar_never_loses_notify_all() {
+    const WAITERS: usize = 4;
+    const ROUNDS: usize = 500;
+
+    struct Rounds {
+        mutex: Mutex,
+        condvar: Box<EventCondvar>,
+        round: AtomicUsize,
+        arrived: AtomicUsize,
+    }
+    let shared = unsafe {
+        let mut mutex = Mutex::new();
+        mutex.init();
+        Arc::new(Rounds {
+            mutex,
+            condvar: EventCondvar::new(),
+            round: AtomicUsize::new(0),
+            arrived: AtomicUsize::new(0),
+        })
+    };
+
+    let waiters: Vec<_> = (0..WAITERS)
+        .map(|_| {
+            let shared = shared.clone();
+            thread::spawn(move || unsafe {
+                for round in 1..=ROUNDS {
+                    shared.mutex.lock();
+                    while shared.round.load(Ordering::Relaxed) < round {
+                        // `PulseEvent` would lose the notification if it came between releasing
+                        // the mutex and waiting on the event
+                        let deadline = Instant::now() + Duration::from_secs(10);
+                        let notified = shared.condvar.wait(&shared.mutex, Some(deadline));
+                        assert!(notified, "lost notify_all in round {}", round);
+                    }
+                    shared.arrived.fetch_add(1, Ordering::Relaxed);
+                    shared.mutex.unlock();
+                }
+            })
+        })
+        .collect();
+
+    for round in 1..=ROUNDS {
+        unsafe {
+            shared.mutex.lock();
+            shared.round.store(round, Ordering::Relaxed);
+            shared.condvar.notify_all();
+            shared.mutex.unlock();
+        }
+        while shared.arrived.load(Ordering::Relaxed) < round * WAITERS {
+            thread::yield_now();
+        }
+    }
+    for waiter in waiters {
+        waiter.join().unwrap();
+    }
+}
+
+#[test]
+fn event_condvar_never_loses_notify_one() {
+    const ROUNDS: usize = 1000;
+
+    struct PingPong {
+        mutex: Mutex,
+        condvar: Box<EventCondvar>,
+        turn: AtomicUsize,
+    }
+    let shared = unsafe {
+        let mut mutex = Mutex::new();
+        mutex.init();
+        Arc::new(PingPong { mutex, condvar: EventCondvar::new(), turn: AtomicUsize::new(0) })
+    };
+
+    // Each side notifies right after the other one released the mutex in `wait`, which is when a
+    // pulsed event would be missed.
+    let play = |shared: Arc<PingPong>, side: usize| unsafe {
+        for round in 0..ROUNDS {
+            shared.mutex.lock();
+            while shared.turn.load(Ordering::Relaxed) % 2 != side {
+                let deadline = Instant::now() + Duration::from_secs(10);
+                let notified = shared.condvar.wait(&shared.mutex, Some(deadline));
+                assert!(notified, "lost notify_one in round {}", round);
+            }
+            shared.turn.fetch_add(1, Ordering::Relaxed);
+            shared.condvar.notify_one();
+            shared.mutex.unlock();
+        }
+    };
+
+    let other = {
+        let shared = shared.clone();
+        thread::spawn(move || play(shared, 1))
+    };
+    play(shared.clone(), 0);
+    other.join().unwrap();
+    assert_eq!(shared.turn.load(Ordering::Relaxed), 2 * ROUNDS);
+}
+
+#[test]
+fn event_condvar_notify_one_wakes_exactly_one() {
+    const WAITERS: usize = 8;
+
+    struct Herd {
+        mutex: Mutex,
+        condvar: Box<EventCondvar>,
+        woken: AtomicUsize,
+    }
+    let shared = unsafe {
+        let mut mutex = Mutex::new();
+        mutex.init();
+        Arc::new(Herd { mutex, condvar: EventCondvar::new(), woken: AtomicUsize::new(0) })
+    };
+
+    let waiters: Vec<_> = (0..WAITERS)
+        .map(|_| {
+            let shared = shared.clone();
+            thread::spawn(move || unsafe {
+                shared.mutex.lock();
+                let deadline = Instant::now() + Duration::from_secs(10);
+                assert!(shared.condvar.wait(&shared.mutex, Some(deadline)));
+                shared.woken.fetch_add(1, Ordering::Relaxed);
+                shared.mutex.unlock();
+            })
+        })
+        .collect();
+    while shared.condvar.blocked() < WAITERS {
+        thread::yield_now();
+    }
+
+    unsafe { shared.condvar.notify_one() };
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while shared.woken.load(Ordering::Relaxed) == 0 && Instant::now() < deadline {
+        thread::yield_now();
+    }
+    // give any other (wrongly) woken thread the time to show up
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(shared.woken.load(Ordering::Relaxed), 1);
+    assert_eq!(shared.condvar.blocked(), WAITERS - 1);
+
+    unsafe { shared.condvar.notify_all() };
+    for waiter in waiters {
+        waiter.join().unwrap();
+    }
+    assert_eq!(shared.woken.load(Ordering::Relaxed), WAITERS);
+}
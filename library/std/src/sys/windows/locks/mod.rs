@@ -1,6 +1,12 @@
+mod backoff;
+mod barrier;
 mod condvar;
 mod mutex;
+mod raw_srwlock;
 mod rwlock;
+pub use barrier::Barrier;
 pub use condvar::{Condvar, MovableCondvar};
+pub use mutex::compat::{capabilities, current_kind, Capabilities, MutexKind};
 pub use mutex::{MovableMutex, Mutex, ReentrantMutex, StaticMutex};
+pub use raw_srwlock::RawSrwLock;
 pub use rwlock::{MovableRWLock, RWLock, StaticRWLock};
@@ -15,9 +15,13 @@
 //!    is that there are no guarantees of fairness.
 
 use crate::cell::UnsafeCell;
+#[cfg(not(feature = "rust9x_srw_only"))]
 use crate::mem::ManuallyDrop;
+#[cfg(not(feature = "rust9x_srw_only"))]
 use crate::ops::{Deref, DerefMut};
+use crate::sync::atomic::{AtomicUsize, Ordering};
 use crate::sys::c;
+use crate::time::Duration;
 use compat::{MutexKind, MUTEX_KIND};
 
 pub mod compat;
@@ -25,15 +29,20 @@
 mod legacy_mutex;
 mod srwlock_mutex;
 
+#[cfg(test)]
+mod tests;
+
 // Windows SRW Locks are movable (while not borrowed).
 pub type MovableMutex = Mutex;
 
+#[cfg(not(feature = "rust9x_srw_only"))]
 pub union InnerMutex {
     srwlock: ManuallyDrop<srwlock_mutex::SrwLockMutex>,
     critical_section: ManuallyDrop<Box<critical_section_mutex::CriticalSectionMutex>>,
     legacy: ManuallyDrop<legacy_mutex::LegacyMutex>,
 }
 
+#[cfg(not(feature = "rust9x_srw_only"))]
 impl Drop for InnerMutex {
     fn drop(&mut self) {
         unsafe {
@@ -46,14 +55,18 @@ fn drop(&mut self) {
     }
 }
 
+#[cfg(not(feature = "rust9x_srw_only"))]
 pub struct Mutex {
     pub inner: InnerMutex,
     pub held: UnsafeCell<bool>,
 }
 
+#[cfg(not(feature = "rust9x_srw_only"))]
 unsafe impl Send for Mutex {}
+#[cfg(not(feature = "rust9x_srw_only"))]
 unsafe impl Sync for Mutex {}
 
+#[cfg(not(feature = "rust9x_srw_only"))]
 impl Mutex {
     pub fn raw(&self) -> c::PSRWLOCK {
         unsafe {
@@ -152,6 +165,27 @@ pub unsafe fn try_lock(&self) -> bool {
         }
     }
 
+    /// Like `try_lock`, but keeps trying until `dur` has passed. Only the legacy mutex can wait
+    /// for the lock to be released, the others back off between `try_lock` calls.
+    #[inline]
+    pub unsafe fn try_lock_for(&self, dur: Duration) -> bool {
+        match MUTEX_KIND {
+            MutexKind::SrwLock | MutexKind::CriticalSection => {
+                super::backoff::try_for(dur, || self.try_lock())
+            }
+            MutexKind::Legacy => {
+                if !self.inner.legacy.deref().try_lock_for(dur) {
+                    false
+                } else if self.flag_locked() {
+                    true
+                } else {
+                    self.unlock();
+                    false
+                }
+            }
+        }
+    }
+
     #[inline]
     pub unsafe fn unlock(&self) {
         match MUTEX_KIND {
@@ -186,6 +220,51 @@ unsafe fn flag_locked(&self) -> bool {
     }
 }
 
+/// With `rust9x_srw_only`, there's only one kind to use, so the mutex is a plain SRW lock without
+/// the union and the recursion flag the other kinds need.
+#[cfg(feature = "rust9x_srw_only")]
+pub struct Mutex {
+    inner: srwlock_mutex::SrwLockMutex,
+}
+
+#[cfg(feature = "rust9x_srw_only")]
+impl Mutex {
+    #[inline]
+    pub fn raw(&self) -> c::PSRWLOCK {
+        self.inner.raw()
+    }
+
+    pub const fn new() -> Mutex {
+        Mutex { inner: srwlock_mutex::SrwLockMutex::new() }
+    }
+
+    #[inline]
+    pub unsafe fn init(&mut self) {}
+
+    #[inline]
+    pub unsafe fn lock(&self) {
+        self.inner.lock()
+    }
+
+    #[inline]
+    pub unsafe fn try_lock(&self) -> bool {
+        self.inner.try_lock()
+    }
+
+    #[inline]
+    pub unsafe fn try_lock_for(&self, dur: Duration) -> bool {
+        super::backoff::try_for(dur, || self.try_lock())
+    }
+
+    #[inline]
+    pub unsafe fn unlock(&self) {
+        self.inner.unlock()
+    }
+
+    #[inline]
+    pub unsafe fn destroy(&self) {}
+}
+
 pub type StaticMutex = super::StaticRWLock;
 
 pub struct ReentrantMutex {
@@ -194,6 +273,12 @@ pub struct ReentrantMutex {
     /// after initialization, but the unsafe API where these internal mutexes are used gives this
     /// guarantee.
     inner: UnsafeCell<critical_section_mutex::CriticalSectionMutex>,
+    /// The id of the thread that holds the legacy mutex, or 0. Critical sections track this
+    /// themselves.
+    owner: AtomicUsize,
+    /// How often the owner locked the legacy mutex. It's only released once this gets back to
+    /// zero, so that the kernel object is acquired and released once per outermost lock.
+    count: UnsafeCell<usize>,
 }
 
 unsafe impl Send for ReentrantMutex {}
@@ -203,11 +288,41 @@ impl ReentrantMutex {
     pub const fn uninitialized() -> ReentrantMutex {
         ReentrantMutex {
             inner: UnsafeCell::new(critical_section_mutex::CriticalSectionMutex::new()),
+            owner: AtomicUsize::new(0),
+            count: UnsafeCell::new(0),
         }
     }
 
+    #[inline]
     pub unsafe fn init(&self) {
-        match MUTEX_KIND {
+        self.init_as(&MUTEX_KIND)
+    }
+
+    #[inline]
+    pub unsafe fn lock(&self) {
+        self.lock_as(&MUTEX_KIND)
+    }
+
+    #[inline]
+    pub unsafe fn try_lock(&self) -> bool {
+        self.try_lock_as(&MUTEX_KIND)
+    }
+
+    #[inline]
+    pub unsafe fn unlock(&self) {
+        self.unlock_as(&MUTEX_KIND)
+    }
+
+    #[inline]
+    pub unsafe fn destroy(&self) {
+        self.destroy_as(&MUTEX_KIND)
+    }
+
+    // The `*_as` variants take the backend to use, so that each one can be tested regardless of
+    // the `MUTEX_KIND` of the system. All calls on a mutex have to pass the same kind.
+
+    pub(super) unsafe fn init_as(&self, kind: &MutexKind) {
+        match kind {
             MutexKind::SrwLock | MutexKind::CriticalSection => {
                 (*self.inner.get().cast::<critical_section_mutex::CriticalSectionMutex>()).init()
             }
@@ -215,40 +330,61 @@ pub unsafe fn init(&self) {
         }
     }
 
-    pub unsafe fn lock(&self) {
-        match MUTEX_KIND {
+    pub(super) unsafe fn lock_as(&self, kind: &MutexKind) {
+        match kind {
             MutexKind::SrwLock | MutexKind::CriticalSection => {
                 (*self.inner.get().cast::<critical_section_mutex::CriticalSectionMutex>()).lock()
             }
 
-            MutexKind::Legacy => (*self.inner.get().cast::<legacy_mutex::LegacyMutex>()).lock(),
+            MutexKind::Legacy => {
+                if !self.lock_again() {
+                    (*self.inner.get().cast::<legacy_mutex::LegacyMutex>()).lock();
+                    self.acquired();
+                }
+            }
         }
     }
 
     #[inline]
-    pub unsafe fn try_lock(&self) -> bool {
-        match MUTEX_KIND {
+    pub(super) unsafe fn try_lock_as(&self, kind: &MutexKind) -> bool {
+        match kind {
             MutexKind::SrwLock | MutexKind::CriticalSection => {
                 (*self.inner.get().cast::<critical_section_mutex::CriticalSectionMutex>())
                     .try_lock()
             }
 
-            MutexKind::Legacy => (*self.inner.get().cast::<legacy_mutex::LegacyMutex>()).try_lock(),
+            MutexKind::Legacy => {
+                if self.lock_again() {
+                    return true;
+                }
+                let locked = (*self.inner.get().cast::<legacy_mutex::LegacyMutex>()).try_lock();
+                if locked {
+                    self.acquired();
+                }
+                locked
+            }
         }
     }
 
-    pub unsafe fn unlock(&self) {
-        match MUTEX_KIND {
+    pub(super) unsafe fn unlock_as(&self, kind: &MutexKind) {
+        match kind {
             MutexKind::SrwLock | MutexKind::CriticalSection => {
                 (*self.inner.get().cast::<critical_section_mutex::CriticalSectionMutex>()).unlock()
             }
 
-            MutexKind::Legacy => (*self.inner.get().cast::<legacy_mutex::LegacyMutex>()).unlock(),
+            MutexKind::Legacy => {
+                let count = &mut *self.count.get();
+                *count -= 1;
+                if *count == 0 {
+                    self.owner.store(0, Ordering::Relaxed);
+                    (*self.inner.get().cast::<legacy_mutex::LegacyMutex>()).unlock();
+                }
+            }
         }
     }
 
-    pub unsafe fn destroy(&self) {
-        match MUTEX_KIND {
+    pub(super) unsafe fn destroy_as(&self, kind: &MutexKind) {
+        match kind {
             MutexKind::SrwLock | MutexKind::CriticalSection => {
                 (*self.inner.get().cast::<critical_section_mutex::CriticalSectionMutex>()).destroy()
             }
@@ -256,4 +392,19 @@ pub unsafe fn destroy(&self) {
             MutexKind::Legacy => (*self.inner.get().cast::<legacy_mutex::LegacyMutex>()).destroy(),
         }
     }
+
+    /// Counts another lock if the calling thread already holds the legacy mutex. The owner can
+    /// only be the calling thread's id if it stored it itself, so relaxed loads are enough.
+    unsafe fn lock_again(&self) -> bool {
+        if self.owner.load(Ordering::Relaxed) != c::GetCurrentThreadId() as usize {
+            return false;
+        }
+        *self.count.get() += 1;
+        true
+    }
+
+    unsafe fn acquired(&self) {
+        self.owner.store(c::GetCurrentThreadId() as usize, Ordering::Relaxed);
+        *self.count.get() = 1;
+    }
 }
@@ -2,6 +2,9 @@
 use crate::sync::atomic::{AtomicUsize, Ordering};
 use crate::sys::c;
 
+#[cfg(test)]
+mod tests;
+
 /// Taken from the [once-removed](https://github.com/rust-lang/rust/pull/81250) Windows XP compatible mutex implementation
 #[inline(always)]
 pub fn atomic_boxed_init<T>(
@@ -34,19 +37,73 @@ pub enum MutexKind {
     Legacy,
 }
 
+/// Which mutex kinds could be used on this system, as opposed to [`current_kind`], which is the
+/// one that was picked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capabilities {
+    /// `TryAcquireSRWLockExclusive` exists (Win 7+)
+    pub srw_available: bool,
+    /// `TryEnterCriticalSection` exists (NT 4+)
+    pub try_cs_available: bool,
+    /// `CreateMutex` works everywhere, this is always `true`
+    pub legacy_always: bool,
+}
+
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        srw_available: c::TryAcquireSRWLockExclusive::available(),
+        try_cs_available: c::TryEnterCriticalSection::available(),
+        legacy_always: true,
+    }
+}
+
+#[cfg(not(feature = "rust9x_srw_only"))]
+pub fn current_kind() -> &'static MutexKind {
+    unsafe { &MUTEX_KIND }
+}
+
+#[cfg(feature = "rust9x_srw_only")]
+pub fn current_kind() -> &'static MutexKind {
+    &MUTEX_KIND
+}
+
+/// The kinds this system supports, for testing each of them. Note that both fallback kinds of
+/// `MovableRWLock` end up using the same `SharedLock`, whose gate uses the best kind available.
+#[cfg(test)]
+pub fn available_kinds() -> Vec<&'static MutexKind> {
+    let capabilities = capabilities();
+    let mut kinds = vec![&MutexKind::Legacy];
+    if capabilities.try_cs_available {
+        kinds.push(&MutexKind::CriticalSection);
+    }
+    if capabilities.srw_available {
+        kinds.push(&MutexKind::SrwLock);
+    }
+    kinds
+}
+
+#[cfg(not(feature = "rust9x_srw_only"))]
 pub static mut MUTEX_KIND: MutexKind = MutexKind::SrwLock;
 
+/// Builds that only target Win 7+ don't need to pick a kind at runtime. Every `match MUTEX_KIND`
+/// then matches on a constant, so the CS and legacy branches are compiled out.
+#[cfg(feature = "rust9x_srw_only")]
+pub const MUTEX_KIND: MutexKind = MutexKind::SrwLock;
+
 /// See the main windows compat.rs on what this is
+#[cfg(not(feature = "rust9x_srw_only"))]
 #[used]
-// Makes sure this initializer runs after regular global/XCU initializers, but before any other MSVCRT
-// initializers. This is needed so that all the compat API info is initialized here.
-#[link_section = ".CRT$XCU_AFTER"]
+// Makes sure this initializer runs after the compat initializers (`.CRT$XCU_RUST_B`), so that all
+// the compat API info is initialized here.
+#[link_section = ".CRT$XCU_RUST_C"]
 static INIT_TABLE_ENTRY: unsafe extern "C" fn() = init;
 
+#[cfg(not(feature = "rust9x_srw_only"))]
 unsafe extern "C" fn init() {
-    MUTEX_KIND = if c::TryAcquireSRWLockExclusive::available() {
+    let capabilities = capabilities();
+    MUTEX_KIND = if capabilities.srw_available {
         MutexKind::SrwLock
-    } else if c::TryEnterCriticalSection::available() {
+    } else if capabilities.try_cs_available {
         MutexKind::CriticalSection
     } else {
         MutexKind::Legacy
@@ -0,0 +1,18 @@
+use super::{capabilities, current_kind, MutexKind};
+use crate::sys::c;
+
+#[test]
+fn capabilities_explain_the_chosen_kind() {
+    let capabilities = capabilities();
+    assert!(capabilities.legacy_always);
+
+    let version = unsafe { c::GetVersion() };
+    let (major, minor) = (version & 0xff, (version >> 8) & 0xff);
+    if version < 0x8000_0000 && (major, minor) >= (6, 1) {
+        assert!(capabilities.srw_available);
+        assert!(capabilities.try_cs_available);
+        assert_eq!(current_kind(), &MutexKind::SrwLock);
+    } else if !capabilities.srw_available {
+        assert_ne!(current_kind(), &MutexKind::SrwLock);
+    }
+}
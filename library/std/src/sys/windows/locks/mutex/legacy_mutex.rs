@@ -1,7 +1,9 @@
 use crate::cell::UnsafeCell;
 use crate::io;
 use crate::ptr;
-use crate::sys::{c, cvt};
+use crate::sys::handle::{wait, WaitOutcome};
+use crate::sys::{c, cvt, dur2timeout};
+use crate::time::{Duration, Instant};
 
 /// Mutex based on `CreateMutex`.
 ///
@@ -33,17 +35,34 @@ pub unsafe fn init(&self) {
 
     #[inline]
     pub unsafe fn lock(&self) {
-        if c::WaitForSingleObject(*self.handle.get(), c::INFINITE) != c::WAIT_OBJECT_0 {
-            panic!("mutex lock failed: {}", io::Error::last_os_error())
+        if let Err(e) = wait(*self.handle.get(), c::INFINITE) {
+            panic!("mutex lock failed: {}", e)
         }
     }
 
     #[inline]
     pub unsafe fn try_lock(&self) -> bool {
-        match c::WaitForSingleObject(*self.handle.get(), 0) {
-            c::WAIT_OBJECT_0 => true,
-            c::WAIT_TIMEOUT => false,
-            _ => panic!("try lock error: {}", io::Error::last_os_error()),
+        match wait(*self.handle.get(), 0) {
+            Ok(outcome) => outcome != WaitOutcome::TimedOut,
+            Err(e) => panic!("try lock error: {}", e),
+        }
+    }
+
+    #[inline]
+    pub unsafe fn try_lock_for(&self, dur: Duration) -> bool {
+        // `None` is as good as waiting forever.
+        let deadline = Instant::now().checked_add(dur);
+        loop {
+            let timeout = match deadline {
+                Some(deadline) => dur2timeout(deadline.saturating_duration_since(Instant::now())),
+                None => c::INFINITE,
+            };
+            match wait(*self.handle.get(), timeout) {
+                // `dur2timeout` clamps, so this may still be before the deadline
+                Ok(WaitOutcome::TimedOut) if deadline.map_or(true, |d| Instant::now() < d) => {}
+                Ok(outcome) => return outcome != WaitOutcome::TimedOut,
+                Err(e) => panic!("timed lock error: {}", e),
+            }
         }
     }
 
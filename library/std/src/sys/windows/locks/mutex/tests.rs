@@ -0,0 +1,98 @@
+use super::Mutex;
+
+#[test]
+fn try_lock_fails_while_locked() {
+    let mut mutex = Mutex::new();
+    unsafe {
+        mutex.init();
+        mutex.lock();
+        assert!(!mutex.try_lock());
+        mutex.unlock();
+        assert!(mutex.try_lock());
+        mutex.unlock();
+        mutex.destroy();
+    }
+}
+
+#[test]
+fn legacy_try_lock_for_waits_for_the_holder() {
+    use super::legacy_mutex::LegacyMutex;
+    use crate::sync::{mpsc, Arc};
+    use crate::thread;
+    use crate::time::{Duration, Instant};
+
+    let mutex = Arc::new(LegacyMutex::new());
+    unsafe { mutex.init() };
+    let (locked_tx, locked_rx) = mpsc::channel();
+    let holder = {
+        let mutex = mutex.clone();
+        thread::spawn(move || unsafe {
+            mutex.lock();
+            locked_tx.send(()).unwrap();
+            thread::sleep(Duration::from_millis(200));
+            mutex.unlock();
+        })
+    };
+    locked_rx.recv().unwrap();
+
+    unsafe {
+        let start = Instant::now();
+        assert!(!mutex.try_lock_for(Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert!(mutex.try_lock_for(Duration::from_secs(10)));
+        mutex.unlock();
+    }
+    holder.join().unwrap();
+    unsafe { mutex.destroy() };
+}
+
+#[test]
+fn reentrant_mutex_needs_balanced_unlocks() {
+    use super::compat::available_kinds;
+    use super::ReentrantMutex;
+    use crate::sync::Arc;
+    use crate::thread;
+
+    for kind in available_kinds() {
+        let mutex = Arc::new(ReentrantMutex::uninitialized());
+        let try_lock_elsewhere = || {
+            let mutex = mutex.clone();
+            thread::spawn(move || unsafe {
+                let locked = mutex.try_lock_as(kind);
+                if locked {
+                    mutex.unlock_as(kind);
+                }
+                locked
+            })
+            .join()
+            .unwrap()
+        };
+        unsafe {
+            mutex.init_as(kind);
+            for _ in 0..4 {
+                mutex.lock_as(kind);
+            }
+            assert!(mutex.try_lock_as(kind), "{:?}", kind);
+            for _ in 0..4 {
+                mutex.unlock_as(kind);
+                assert!(!try_lock_elsewhere(), "{:?}", kind);
+            }
+            mutex.unlock_as(kind);
+            assert!(try_lock_elsewhere(), "{:?}", kind);
+            mutex.destroy_as(kind);
+        }
+    }
+}
+
+// Without the feature, the mutex also carries the recursion flag of the CS and legacy kinds.
+#[cfg(feature = "rust9x_srw_only")]
+#[test]
+fn srw_only_mutex_is_a_plain_srwlock() {
+    use super::compat::{current_kind, MutexKind};
+    use crate::mem::size_of;
+    use crate::sys::c;
+
+    assert_eq!(current_kind(), &MutexKind::SrwLock);
+    assert_eq!(size_of::<Mutex>(), size_of::<c::SRWLOCK>());
+    assert!(size_of::<Mutex>() < size_of::<super::ReentrantMutex>());
+}
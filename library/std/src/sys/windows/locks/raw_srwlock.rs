@@ -0,0 +1,79 @@
+//! A reader-writer lock backed by an SRW lock where available.
+//!
+//! This is the lock behind `MovableRWLock`, with the same fallback: without the SRW lock APIs
+//! (before Windows 7), it's a boxed lock built from the selected mutex kind, which still lets
+//! readers share it.
+
+#[cfg(test)]
+mod tests;
+
+use super::mutex::compat::MUTEX_KIND;
+use super::MovableRWLock;
+
+/// A raw reader-writer lock, using `SRWLOCK` on Windows 7+ and a mutex on older systems.
+///
+/// Like the SRW lock it's based on, this isn't recursive for either kind of access, and every
+/// acquisition has to be paired with the matching unlock on the same thread. It must not be moved
+/// while it's locked, as the `SRWLOCK` is stored inline. The boxed fallback wouldn't mind, as only
+/// the pointer to it moves.
+pub struct RawSrwLock {
+    inner: MovableRWLock,
+}
+
+unsafe impl Send for RawSrwLock {}
+unsafe impl Sync for RawSrwLock {}
+
+impl RawSrwLock {
+    pub const fn new() -> RawSrwLock {
+        RawSrwLock { inner: MovableRWLock::new() }
+    }
+
+    /// Acquires shared access, blocking while there's a writer.
+    #[inline]
+    pub unsafe fn read(&self) {
+        self.inner.read()
+    }
+
+    /// Tries to acquire shared access without blocking.
+    #[inline]
+    pub unsafe fn try_read(&self) -> bool {
+        self.inner.try_read()
+    }
+
+    /// Acquires exclusive access, blocking while there are readers or a writer.
+    #[inline]
+    pub unsafe fn write(&self) {
+        self.inner.write()
+    }
+
+    /// Tries to acquire exclusive access without blocking.
+    #[inline]
+    pub unsafe fn try_write(&self) -> bool {
+        self.inner.try_write()
+    }
+
+    #[inline]
+    pub unsafe fn read_unlock(&self) {
+        self.inner.read_unlock()
+    }
+
+    #[inline]
+    pub unsafe fn write_unlock(&self) {
+        self.inner.write_unlock()
+    }
+
+    /// Turns the exclusive access held by the calling thread into shared access.
+    ///
+    /// SRW locks can't do this atomically, so another writer may get the lock in between. The
+    /// fallback registers the thread as a reader before letting anyone else in.
+    #[inline]
+    pub unsafe fn downgrade(&self) {
+        self.inner.downgrade_as(&MUTEX_KIND)
+    }
+}
+
+impl Drop for RawSrwLock {
+    fn drop(&mut self) {
+        unsafe { self.inner.destroy() };
+    }
+}
@@ -0,0 +1,62 @@
+use super::RawSrwLock;
+use crate::mem;
+use crate::sync::Arc;
+use crate::sys::locks::mutex::compat::{available_kinds, MutexKind};
+use crate::thread;
+
+/// Returns whether another thread could get shared and exclusive access right now.
+fn others_can_acquire(lock: &Arc<RawSrwLock>, kind: &'static MutexKind) -> (bool, bool) {
+    let lock = lock.clone();
+    thread::spawn(move || unsafe {
+        let read = lock.inner.try_read_as(kind);
+        if read {
+            lock.inner.read_unlock_as(kind);
+        }
+        let write = lock.inner.try_write_as(kind);
+        if write {
+            lock.inner.write_unlock_as(kind);
+        }
+        (read, write)
+    })
+    .join()
+    .unwrap()
+}
+
+#[test]
+fn acquire_under_each_kind() {
+    for kind in available_kinds() {
+        let lock = Arc::new(RawSrwLock::new());
+        unsafe {
+            lock.inner.write_as(kind);
+            assert_eq!(others_can_acquire(&lock, kind), (false, false), "{:?}", kind);
+            lock.inner.downgrade_as(kind);
+            assert_eq!(others_can_acquire(&lock, kind), (true, false), "{:?}", kind);
+            lock.inner.read_unlock_as(kind);
+
+            assert!(lock.inner.try_write_as(kind), "{:?}", kind);
+            assert_eq!(others_can_acquire(&lock, kind), (false, false), "{:?}", kind);
+            lock.inner.write_unlock_as(kind);
+
+            lock.inner.read_as(kind);
+            assert_eq!(others_can_acquire(&lock, kind), (true, false), "{:?}", kind);
+            lock.inner.read_unlock_as(kind);
+
+            assert!(lock.inner.try_read_as(kind), "{:?}", kind);
+            lock.inner.read_unlock_as(kind);
+
+            // the reader a downgrade leaves behind is released like any other
+            lock.inner.write_as(kind);
+            lock.inner.downgrade_as(kind);
+            lock.inner.read_as(kind);
+            lock.inner.read_unlock_as(kind);
+            lock.inner.read_unlock_as(kind);
+
+            assert_eq!(others_can_acquire(&lock, kind), (true, true), "{:?}", kind);
+
+            // `Drop` would destroy it as `MUTEX_KIND`
+            let lock = Arc::try_unwrap(lock).ok().unwrap();
+            lock.inner.destroy_as(kind);
+            mem::forget(lock);
+        }
+    }
+}
@@ -1,99 +1,219 @@
 use crate::cell::UnsafeCell;
 use crate::mem;
+#[cfg(debug_assertions)]
+use crate::sync::atomic::AtomicU8;
 use crate::sync::atomic::{AtomicUsize, Ordering};
 use crate::sys::c;
 use crate::sys::locks::{
+    backoff,
     mutex::{
         compat::{atomic_boxed_init, MutexKind, MUTEX_KIND},
         critical_section_mutex::CriticalSectionMutex,
     },
-    Mutex,
 };
+use crate::time::Duration;
 
-/// The fallback implementation is just a mutex, which might be slower, but valid and compatible.
+mod shared_lock;
+
+#[cfg(test)]
+mod tests;
+
+use shared_lock::SharedLock;
+
+/// The fallback implementation is a boxed `SharedLock`, which is slower than an `SRWLOCK`, but lets
+/// readers share the lock just the same.
 pub struct MovableRWLock {
-    // Both the `SRWLOCK` and a boxed mutex are usize-sized
+    // Both the `SRWLOCK` and a pointer to the boxed fallback are usize-sized
     lock: AtomicUsize,
+    /// Which of the two `lock` holds, so that an `SRWLOCK` value is never taken for a pointer (or
+    /// the other way around). Only tracked with debug assertions, as the kind passed to the
+    /// `*_as` functions never changes outside of tests.
+    #[cfg(debug_assertions)]
+    storage: AtomicU8,
 }
 
+#[cfg(debug_assertions)]
+const STORAGE_UNUSED: u8 = 0;
+#[cfg(debug_assertions)]
+const STORAGE_SRWLOCK: u8 = 1;
+#[cfg(debug_assertions)]
+const STORAGE_BOXED: u8 = 2;
+
 unsafe impl Send for MovableRWLock {}
 unsafe impl Sync for MovableRWLock {}
 
 impl MovableRWLock {
     pub const fn new() -> MovableRWLock {
-        MovableRWLock { lock: AtomicUsize::new(0) }
+        MovableRWLock {
+            lock: AtomicUsize::new(0),
+            #[cfg(debug_assertions)]
+            storage: AtomicU8::new(STORAGE_UNUSED),
+        }
     }
     #[inline]
     pub unsafe fn read(&self) {
-        match MUTEX_KIND {
+        self.read_as(&MUTEX_KIND)
+    }
+    #[inline]
+    pub unsafe fn try_read(&self) -> bool {
+        self.try_read_as(&MUTEX_KIND)
+    }
+    #[inline]
+    pub unsafe fn write(&self) {
+        self.write_as(&MUTEX_KIND)
+    }
+    #[inline]
+    pub unsafe fn try_write(&self) -> bool {
+        self.try_write_as(&MUTEX_KIND)
+    }
+    #[inline]
+    pub unsafe fn try_read_for(&self, dur: Duration) -> bool {
+        self.try_read_for_as(&MUTEX_KIND, dur)
+    }
+    #[inline]
+    pub unsafe fn try_write_for(&self, dur: Duration) -> bool {
+        self.try_write_for_as(&MUTEX_KIND, dur)
+    }
+    #[inline]
+    pub unsafe fn read_unlock(&self) {
+        self.read_unlock_as(&MUTEX_KIND)
+    }
+    #[inline]
+    pub unsafe fn write_unlock(&self) {
+        self.write_unlock_as(&MUTEX_KIND)
+    }
+    #[inline]
+    pub unsafe fn destroy(&self) {
+        self.destroy_as(&MUTEX_KIND)
+    }
+
+    // The `*_as` variants take the backend to use, so that each one can be tested regardless of
+    // the `MUTEX_KIND` of the system. All calls on a lock have to pass the same kind.
+
+    #[inline]
+    pub(super) unsafe fn read_as(&self, kind: &MutexKind) {
+        self.check_storage(kind);
+        match kind {
             MutexKind::SrwLock => c::AcquireSRWLockShared(&self.lock as *const _ as *mut _),
-            MutexKind::CriticalSection | MutexKind::Legacy => (*self.remutex()).lock(),
+            MutexKind::CriticalSection | MutexKind::Legacy => (*self.shared()).read(),
         }
     }
     #[inline]
-    pub unsafe fn try_read(&self) -> bool {
-        match MUTEX_KIND {
+    pub(super) unsafe fn try_read_as(&self, kind: &MutexKind) -> bool {
+        self.check_storage(kind);
+        match kind {
             MutexKind::SrwLock => c::TryAcquireSRWLockShared(&self.lock as *const _ as *mut _) != 0,
-            MutexKind::CriticalSection | MutexKind::Legacy => (*self.remutex()).try_lock(),
+            MutexKind::CriticalSection | MutexKind::Legacy => (*self.shared()).try_read(),
         }
     }
     #[inline]
-    pub unsafe fn write(&self) {
-        match MUTEX_KIND {
+    pub(super) unsafe fn write_as(&self, kind: &MutexKind) {
+        self.check_storage(kind);
+        match kind {
             MutexKind::SrwLock => c::AcquireSRWLockExclusive(&self.lock as *const _ as *mut _),
-            MutexKind::CriticalSection | MutexKind::Legacy => (*self.remutex()).lock(),
+            MutexKind::CriticalSection | MutexKind::Legacy => (*self.shared()).write(),
         }
     }
     #[inline]
-    pub unsafe fn try_write(&self) -> bool {
-        match MUTEX_KIND {
+    pub(super) unsafe fn try_write_as(&self, kind: &MutexKind) -> bool {
+        self.check_storage(kind);
+        match kind {
             MutexKind::SrwLock => {
                 c::TryAcquireSRWLockExclusive(&self.lock as *const _ as *mut _) != 0
             }
-            MutexKind::CriticalSection | MutexKind::Legacy => (*self.remutex()).try_lock(),
+            MutexKind::CriticalSection | MutexKind::Legacy => (*self.shared()).try_write(),
         }
     }
     #[inline]
-    pub unsafe fn read_unlock(&self) {
-        match MUTEX_KIND {
+    pub(super) unsafe fn try_read_for_as(&self, kind: &MutexKind, dur: Duration) -> bool {
+        self.check_storage(kind);
+        match kind {
+            MutexKind::SrwLock => backoff::try_for(dur, || {
+                c::TryAcquireSRWLockShared(&self.lock as *const _ as *mut _) != 0
+            }),
+            MutexKind::CriticalSection | MutexKind::Legacy => (*self.shared()).try_read_for(dur),
+        }
+    }
+    #[inline]
+    pub(super) unsafe fn try_write_for_as(&self, kind: &MutexKind, dur: Duration) -> bool {
+        self.check_storage(kind);
+        match kind {
+            MutexKind::SrwLock => backoff::try_for(dur, || {
+                c::TryAcquireSRWLockExclusive(&self.lock as *const _ as *mut _) != 0
+            }),
+            MutexKind::CriticalSection | MutexKind::Legacy => (*self.shared()).try_write_for(dur),
+        }
+    }
+    #[inline]
+    pub(super) unsafe fn read_unlock_as(&self, kind: &MutexKind) {
+        self.check_storage(kind);
+        match kind {
             MutexKind::SrwLock => c::ReleaseSRWLockShared(&self.lock as *const _ as *mut _),
-            MutexKind::CriticalSection | MutexKind::Legacy => (*self.remutex()).unlock(),
+            MutexKind::CriticalSection | MutexKind::Legacy => (*self.shared()).read_unlock(),
         }
     }
     #[inline]
-    pub unsafe fn write_unlock(&self) {
-        match MUTEX_KIND {
+    pub(super) unsafe fn write_unlock_as(&self, kind: &MutexKind) {
+        self.check_storage(kind);
+        match kind {
             MutexKind::SrwLock => c::ReleaseSRWLockExclusive(&self.lock as *const _ as *mut _),
-            MutexKind::CriticalSection | MutexKind::Legacy => (*self.remutex()).unlock(),
+            MutexKind::CriticalSection | MutexKind::Legacy => (*self.shared()).write_unlock(),
+        }
+    }
+    /// Turns the exclusive access held by the calling thread into shared access. Only the
+    /// `SharedLock` does this atomically, another writer may get an SRW lock in between.
+    #[inline]
+    pub(super) unsafe fn downgrade_as(&self, kind: &MutexKind) {
+        self.check_storage(kind);
+        match kind {
+            MutexKind::SrwLock => {
+                c::ReleaseSRWLockExclusive(&self.lock as *const _ as *mut _);
+                c::AcquireSRWLockShared(&self.lock as *const _ as *mut _);
+            }
+            MutexKind::CriticalSection | MutexKind::Legacy => (*self.shared()).downgrade(),
         }
     }
 
     #[inline]
-    pub unsafe fn destroy(&self) {
-        match MUTEX_KIND {
+    pub(super) unsafe fn destroy_as(&self, kind: &MutexKind) {
+        self.check_storage(kind);
+        match kind {
             MutexKind::SrwLock => {}
             MutexKind::CriticalSection | MutexKind::Legacy => {
-                match self.lock.load(Ordering::SeqCst) {
+                // Reset, so that destroying twice doesn't free twice.
+                match self.lock.swap(0, Ordering::SeqCst) {
+                    // Never locked, so the fallback was never allocated.
                     0 => {}
-                    n => {
-                        Box::from_raw(n as *mut Mutex).destroy();
-                    }
+                    n => drop(Box::from_raw(n as *mut SharedLock)),
                 }
             }
         }
     }
 
-    unsafe fn remutex(&self) -> *mut Mutex {
-        unsafe fn init() -> Box<Mutex> {
-            let mut re = box Mutex::new();
-            re.init();
-            re
+    #[cfg(debug_assertions)]
+    fn check_storage(&self, kind: &MutexKind) {
+        let storage = match kind {
+            MutexKind::SrwLock => STORAGE_SRWLOCK,
+            MutexKind::CriticalSection | MutexKind::Legacy => STORAGE_BOXED,
+        };
+        let (unused, relaxed) = (STORAGE_UNUSED, Ordering::Relaxed);
+        if let Err(previous) = self.storage.compare_exchange(unused, storage, relaxed, relaxed) {
+            assert_eq!(previous, storage, "`MovableRWLock` used as an SRW lock and a `SharedLock`");
         }
+    }
 
-        unsafe fn destroy(mutex: &Mutex) {
-            mutex.destroy()
+    #[cfg(not(debug_assertions))]
+    #[inline(always)]
+    fn check_storage(&self, _kind: &MutexKind) {}
+
+    unsafe fn shared(&self) -> *mut SharedLock {
+        unsafe fn init() -> Box<SharedLock> {
+            SharedLock::new()
         }
 
+        // dropping the box cleans up
+        unsafe fn destroy(_lock: &SharedLock) {}
+
         atomic_boxed_init(&self.lock, init, destroy)
     }
 }
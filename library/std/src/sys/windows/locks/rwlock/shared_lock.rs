@@ -0,0 +1,172 @@
+use crate::cell::UnsafeCell;
+use crate::io;
+use crate::ptr;
+use crate::sys::handle::{wait, WaitOutcome};
+use crate::sys::locks::mutex::critical_section_mutex::CriticalSectionMutex;
+use crate::sys::locks::Mutex;
+use crate::sys::{c, cvt, dur2timeout};
+use crate::time::{Duration, Instant};
+
+/// Reader-writer lock for the CS and legacy mutex kinds, where readers actually share the lock.
+///
+/// Every acquisition goes through `gate` first. Readers only hold it while they register
+/// themselves, writers for as long as they hold the lock, so that no new readers come in while a
+/// writer waits for the current ones to leave, and writers can't be starved by a steady stream of
+/// readers. The last reader to leave sets `no_readers`, which is what writers wait on.
+pub struct SharedLock {
+    gate: Mutex,
+    /// Guards `readers`, and orders the `SetEvent`/`ResetEvent` calls on `no_readers`.
+    lock: CriticalSectionMutex,
+    readers: UnsafeCell<usize>,
+    /// Manual-reset event, set while `readers` is zero.
+    no_readers: c::HANDLE,
+}
+
+unsafe impl Send for SharedLock {}
+unsafe impl Sync for SharedLock {}
+
+impl SharedLock {
+    /// Boxed, as neither the gate nor the critical section can be moved after initialization.
+    pub fn new() -> Box<Self> {
+        let no_readers = unsafe {
+            c::CreateEventA(
+                ptr::null_mut(),
+                c::TRUE, // manual reset event
+                c::TRUE,
+                ptr::null(),
+            )
+        };
+        if no_readers.is_null() {
+            panic!("failed creating event: {}", io::Error::last_os_error());
+        }
+
+        let lock = box SharedLock {
+            gate: Mutex::new(),
+            lock: CriticalSectionMutex::new(),
+            readers: UnsafeCell::new(0),
+            no_readers,
+        };
+        unsafe {
+            lock.gate.init();
+            lock.lock.init();
+        }
+        lock
+    }
+
+    pub unsafe fn read(&self) {
+        self.gate.lock();
+        self.enter_reader();
+    }
+
+    pub unsafe fn try_read(&self) -> bool {
+        if !self.gate.try_lock() {
+            return false;
+        }
+        self.enter_reader();
+        true
+    }
+
+    pub unsafe fn try_read_for(&self, dur: Duration) -> bool {
+        if !self.gate.try_lock_for(dur) {
+            return false;
+        }
+        self.enter_reader();
+        true
+    }
+
+    /// Registers a reader, `gate` has to be locked, and is unlocked again.
+    unsafe fn enter_reader(&self) {
+        self.lock.lock();
+        let readers = &mut *self.readers.get();
+        *readers += 1;
+        if *readers == 1 {
+            cvt(c::ResetEvent(self.no_readers)).unwrap();
+        }
+        self.lock.unlock();
+        self.gate.unlock();
+    }
+
+    pub unsafe fn read_unlock(&self) {
+        self.lock.lock();
+        let readers = &mut *self.readers.get();
+        *readers -= 1;
+        if *readers == 0 {
+            cvt(c::SetEvent(self.no_readers)).unwrap();
+        }
+        self.lock.unlock();
+    }
+
+    pub unsafe fn write(&self) {
+        self.gate.lock();
+        self.wait_for_all_readers();
+    }
+
+    pub unsafe fn try_write(&self) -> bool {
+        if !self.gate.try_lock() {
+            return false;
+        }
+        self.wait_for_readers(Instant::now())
+    }
+
+    pub unsafe fn try_write_for(&self, dur: Duration) -> bool {
+        let deadline = Instant::now().checked_add(dur);
+        if !self.gate.try_lock_for(dur) {
+            return false;
+        }
+        match deadline {
+            Some(deadline) => self.wait_for_readers(deadline),
+            None => {
+                self.wait_for_all_readers();
+                true
+            }
+        }
+    }
+
+    /// Waits until the readers left, `gate` has to be locked. No reader can come in while it is,
+    /// so this only waits for the ones that are already there.
+    unsafe fn wait_for_all_readers(&self) {
+        if let Err(e) = wait(self.no_readers, c::INFINITE) {
+            panic!("event wait failed: {}", e);
+        }
+    }
+
+    /// Waits until the readers left, `gate` has to be locked, and is unlocked again if they don't
+    /// leave before `deadline`.
+    unsafe fn wait_for_readers(&self, deadline: Instant) -> bool {
+        loop {
+            let timeout = dur2timeout(deadline.saturating_duration_since(Instant::now()));
+            match wait(self.no_readers, timeout) {
+                Ok(WaitOutcome::TimedOut) => {
+                    // `dur2timeout` clamps, so this may still be before the deadline
+                    if Instant::now() >= deadline {
+                        self.gate.unlock();
+                        return false;
+                    }
+                }
+                Ok(_) => return true,
+                Err(e) => panic!("event wait failed: {}", e),
+            }
+        }
+    }
+
+    pub unsafe fn write_unlock(&self) {
+        self.gate.unlock();
+    }
+
+    /// Turns the exclusive access held by the calling thread into shared access, without letting
+    /// another writer in between.
+    pub unsafe fn downgrade(&self) {
+        // Holding `gate` with no readers left, just as a reader that's about to register.
+        self.enter_reader();
+    }
+}
+
+impl Drop for SharedLock {
+    fn drop(&mut self) {
+        unsafe {
+            self.gate.destroy();
+            self.lock.destroy();
+            cvt(c::CloseHandle(self.no_readers)).unwrap();
+        }
+    }
+}
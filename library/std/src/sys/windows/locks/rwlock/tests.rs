@@ -0,0 +1,169 @@
+use super::MovableRWLock;
+use crate::sync::atomic::Ordering;
+use crate::sync::{mpsc, Arc};
+use crate::sys::locks::mutex::compat::{available_kinds, MutexKind};
+use crate::thread::{self, JoinHandle};
+use crate::time::Duration;
+
+/// Locks `lock` exclusively on another thread, until the returned sender is dropped.
+fn hold_write(
+    lock: &Arc<MovableRWLock>,
+    kind: &'static MutexKind,
+) -> (mpsc::Sender<()>, JoinHandle<()>) {
+    let (locked_tx, locked_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+    let lock = lock.clone();
+    let holder = thread::spawn(move || unsafe {
+        lock.write_as(kind);
+        locked_tx.send(()).unwrap();
+        let _ = release_rx.recv();
+        lock.write_unlock_as(kind);
+    });
+    locked_rx.recv().unwrap();
+    (release_tx, holder)
+}
+
+/// A timed read or write acquisition, releasing the lock again if it succeeded.
+fn try_for(lock: &MovableRWLock, kind: &MutexKind, write: bool, dur: Duration) -> bool {
+    unsafe {
+        if write {
+            let locked = lock.try_write_for_as(kind, dur);
+            if locked {
+                lock.write_unlock_as(kind);
+            }
+            locked
+        } else {
+            let locked = lock.try_read_for_as(kind, dur);
+            if locked {
+                lock.read_unlock_as(kind);
+            }
+            locked
+        }
+    }
+}
+
+#[test]
+fn timed_acquire_times_out() {
+    for kind in available_kinds() {
+        let lock = Arc::new(MovableRWLock::new());
+        let (release, holder) = hold_write(&lock, kind);
+        for write in [false, true] {
+            assert!(!try_for(&lock, kind, write, Duration::ZERO), "{:?}", kind);
+            assert!(!try_for(&lock, kind, write, Duration::from_millis(50)), "{:?}", kind);
+        }
+        drop(release);
+        holder.join().unwrap();
+
+        for write in [false, true] {
+            assert!(try_for(&lock, kind, write, Duration::ZERO), "{:?}", kind);
+        }
+        unsafe { lock.destroy_as(kind) };
+    }
+}
+
+#[test]
+fn timed_acquire_succeeds_once_released() {
+    for kind in available_kinds() {
+        for write in [false, true] {
+            let lock = Arc::new(MovableRWLock::new());
+            let (release, holder) = hold_write(&lock, kind);
+            let waiter = {
+                let lock = lock.clone();
+                thread::spawn(move || try_for(&lock, kind, write, Duration::from_secs(10)))
+            };
+            thread::sleep(Duration::from_millis(50));
+            drop(release);
+            holder.join().unwrap();
+            assert!(waiter.join().unwrap(), "{:?}", kind);
+            unsafe { lock.destroy_as(kind) };
+        }
+    }
+}
+
+#[test]
+fn destroy_boxed_mutex() {
+    for kind in [&MutexKind::CriticalSection, &MutexKind::Legacy] {
+        // never locked, so there's nothing to free
+        let lock = MovableRWLock::new();
+        unsafe { lock.destroy_as(kind) };
+        assert_eq!(lock.lock.load(Ordering::SeqCst), 0);
+
+        let lock = MovableRWLock::new();
+        unsafe {
+            lock.write_as(kind);
+            lock.write_unlock_as(kind);
+            assert_ne!(lock.lock.load(Ordering::SeqCst), 0);
+            lock.destroy_as(kind);
+            assert_eq!(lock.lock.load(Ordering::SeqCst), 0);
+            // so this is a no-op now
+            lock.destroy_as(kind);
+        }
+    }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic = "used as an SRW lock and a `SharedLock`"]
+fn mixed_storage_is_caught() {
+    let lock = MovableRWLock::new();
+    unsafe {
+        lock.write_as(&MutexKind::CriticalSection);
+        lock.write_unlock_as(&MutexKind::CriticalSection);
+        // would treat the pointer to the `SharedLock` as an `SRWLOCK`
+        lock.read_as(&MutexKind::SrwLock);
+    }
+}
+
+#[test]
+fn readers_share_the_lock() {
+    for kind in available_kinds() {
+        let lock = Arc::new(MovableRWLock::new());
+        unsafe {
+            lock.read_as(kind);
+            let other = lock.clone();
+            let shared = thread::spawn(move || {
+                let locked = other.try_read_as(kind);
+                if locked {
+                    other.read_unlock_as(kind);
+                }
+                locked
+            });
+            assert!(shared.join().unwrap(), "{:?}", kind);
+            assert!(!lock.try_write_as(kind), "{:?}", kind);
+            lock.read_unlock_as(kind);
+            assert!(lock.try_write_as(kind), "{:?}", kind);
+            lock.write_unlock_as(kind);
+            lock.destroy_as(kind);
+        }
+    }
+}
+
+#[test]
+fn waiting_writer_holds_off_new_readers() {
+    for kind in available_kinds() {
+        let lock = Arc::new(MovableRWLock::new());
+        unsafe { lock.read_as(kind) };
+
+        let (locked_tx, locked_rx) = mpsc::channel();
+        let writer = {
+            let lock = lock.clone();
+            thread::spawn(move || unsafe {
+                lock.write_as(kind);
+                locked_tx.send(()).unwrap();
+                lock.write_unlock_as(kind);
+            })
+        };
+        // the writer waits for the reader, and new readers wait for the writer
+        thread::sleep(Duration::from_millis(50));
+        assert!(locked_rx.try_recv().is_err(), "{:?}", kind);
+        if *kind != MutexKind::SrwLock {
+            // SRW locks don't promise this
+            assert!(!try_for(&lock, kind, false, Duration::ZERO), "{:?}", kind);
+        }
+
+        unsafe { lock.read_unlock_as(kind) };
+        locked_rx.recv().unwrap();
+        writer.join().unwrap();
+        unsafe { lock.destroy_as(kind) };
+    }
+}
@@ -1,9 +1,11 @@
 #![allow(missing_docs, nonstandard_style)]
 
+use crate::convert::TryInto;
 use crate::ffi::{OsStr, OsString};
 use crate::io::ErrorKind;
 use crate::os::windows::ffi::{OsStrExt, OsStringExt};
 use crate::path::PathBuf;
+use crate::ptr;
 use crate::time::Duration;
 
 pub use self::rand::hashmap_random_keys;
@@ -14,8 +16,10 @@
 
 pub mod alloc;
 pub mod args;
+pub mod atomic64;
 pub mod c;
 pub mod cmath;
+pub mod cpu;
 pub mod env;
 pub mod fs;
 pub mod handle;
@@ -23,21 +27,27 @@
 pub mod locks;
 pub mod memchr;
 pub mod net;
+pub mod once;
 pub mod os;
 pub mod os_str;
 pub mod path;
 pub mod pipe;
 pub mod process;
 pub mod rand;
+pub mod report;
 pub mod thread;
 pub mod thread_local_dtor;
+pub mod thread_local_fallback;
 pub mod thread_local_key;
 pub mod thread_parker;
 pub mod time;
 cfg_if::cfg_if! {
     if #[cfg(not(target_vendor = "uwp"))] {
+        pub mod console;
         pub mod stdio;
         pub mod stack_overflow;
+        #[cfg(target_arch = "x86")]
+        pub mod exception;
     } else {
         pub mod stdio_uwp;
         pub mod stack_overflow_uwp;
@@ -50,8 +60,17 @@
 // NOTE: this is not guaranteed to run, for example when Rust code is called externally.
 pub unsafe fn init(_argc: isize, _argv: *const *const u8) {
     stack_overflow::init();
+    process::init();
+    if SUPPRESS_HARD_ERROR_DIALOGS {
+        os::set_error_mode(c::SEM_FAILCRITICALERRORS | c::SEM_NOOPENFILEERRORBOX);
+    }
 }
 
+/// Whether `init` makes the system fail file and device operations with an error instead of
+/// showing a system modal "drive not ready" (or similar) dialog, which hangs programs without a
+/// user in front of them, especially on 9x/ME.
+const SUPPRESS_HARD_ERROR_DIALOGS: bool = true;
+
 // SAFETY: must be called only once during runtime cleanup.
 // NOTE: this is not guaranteed to run, for example when the program aborts.
 pub unsafe fn cleanup() {
@@ -170,6 +189,103 @@ fn inner(s: &OsStr) -> crate::io::Result<Vec<u16>> {
     inner(s.as_ref())
 }
 
+/// Converts UTF-16 to the ANSI code page, for the `A` APIs 9x/ME offer in place of the `W` ones.
+///
+/// Exactly `wide` is converted, so embedded and trailing NULs are preserved. Characters that the
+/// code page can't represent are replaced by its default character.
+pub fn wide_to_ansi(wide: &[u16]) -> crate::io::Result<Vec<u8>> {
+    wide_to_multi_byte(c::CP_ACP, wide)
+}
+
+/// Like `wide_to_ansi`, but for an arbitrary code page.
+pub fn wide_to_multi_byte(code_page: c::UINT, wide: &[u16]) -> crate::io::Result<Vec<u8>> {
+    if wide.is_empty() {
+        return Ok(Vec::new());
+    }
+    let wide_len = wide
+        .len()
+        .try_into()
+        .map_err(|_| crate::io::const_io_error!(ErrorKind::InvalidInput, "string is too long"))?;
+    unsafe {
+        let len = c::WideCharToMultiByte(
+            code_page,
+            0,
+            wide.as_ptr(),
+            wide_len,
+            ptr::null_mut(),
+            0,
+            ptr::null(),
+            ptr::null_mut(),
+        );
+        if len == 0 {
+            return Err(crate::io::Error::last_os_error());
+        }
+        let mut ansi = vec![0u8; len as usize];
+        let len = c::WideCharToMultiByte(
+            code_page,
+            0,
+            wide.as_ptr(),
+            wide_len,
+            ansi.as_mut_ptr() as c::LPSTR,
+            len,
+            ptr::null(),
+            ptr::null_mut(),
+        );
+        if len == 0 {
+            return Err(crate::io::Error::last_os_error());
+        }
+        ansi.truncate(len as usize);
+        Ok(ansi)
+    }
+}
+
+/// Like `to_u16s`, but produces a NUL-terminated string in the ANSI code page.
+pub fn to_ansi<S: AsRef<OsStr>>(s: S) -> crate::io::Result<Vec<u8>> {
+    wide_to_ansi(&to_u16s(s)?)
+}
+
+/// Converts a string in the ANSI code page, as returned by the `A` APIs, back to an `OsString`.
+///
+/// Should the conversion fail, each byte is taken as the code point of the same value instead, so
+/// that the string comes back garbled rather than empty.
+pub fn from_ansi(ansi: &[u8]) -> OsString {
+    if ansi.is_empty() {
+        return OsString::new();
+    }
+    let ansi_len = match ansi.len().try_into() {
+        Ok(len) => len,
+        Err(_) => return from_bytes_lossy(ansi),
+    };
+    // an ANSI string never has more UTF-16 code units than bytes
+    let mut wide = vec![0u16; ansi.len()];
+    let len = unsafe {
+        c::MultiByteToWideChar(
+            c::CP_ACP,
+            0,
+            ansi.as_ptr() as c::LPCSTR,
+            ansi_len,
+            wide.as_mut_ptr(),
+            ansi_len,
+        )
+    };
+    if len == 0 {
+        return from_bytes_lossy(ansi);
+    }
+    OsString::from_wide(&wide[..len as usize])
+}
+
+fn from_bytes_lossy(bytes: &[u8]) -> OsString {
+    let wide: Vec<u16> = bytes.iter().map(|&b| b as u16).collect();
+    OsString::from_wide(&wide)
+}
+
+/// Like `from_ansi`, for the NUL-terminated strings in fixed size buffers of the `A` structs.
+pub fn from_ansi_buf(buf: &[c::CHAR]) -> OsString {
+    let buf: Vec<u8> = buf.iter().map(|&c| c as u8).collect();
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    from_ansi(&buf[..len])
+}
+
 // Many Windows APIs follow a pattern of where we hand a buffer and then they
 // will report back to us how large the buffer should be or how many bytes
 // currently reside in the buffer. This function is an abstraction over these
@@ -267,20 +383,34 @@ pub fn cvt<I: IsZero>(i: I) -> crate::io::Result<I> {
     if i.is_zero() { Err(crate::io::Error::last_os_error()) } else { Ok(i) }
 }
 
+/// The error of the last failed socket call. Winsock reports it through `WSAGetLastError`, while
+/// `GetLastError` can still hold an unrelated error from some kernel32 call in between, and is
+/// only used if Winsock has no error, like when the call failed before reaching it.
+pub fn last_net_error() -> crate::io::Error {
+    let code = match unsafe { c::WSAGetLastError() } {
+        0 => unsafe { c::GetLastError() as i32 },
+        code => code,
+    };
+    crate::io::Error::from_raw_os_error(code)
+}
+
+/// The longest timeout `dur2timeout` returns, `INFINITE - 1` milliseconds (about 49.7 days).
+pub const MAX_TIMEOUT: c::DWORD = c::INFINITE - 1;
+
 pub fn dur2timeout(dur: Duration) -> c::DWORD {
     // Note that a duration is a (u64, u32) (seconds, nanoseconds) pair, and the
     // timeouts in windows APIs are typically u32 milliseconds. To translate, we
     // have two pieces to take care of:
     //
     // * Nanosecond precision is rounded up
-    // * Greater than u32::MAX milliseconds (50 days) is rounded up to INFINITE
-    //   (never time out).
+    // * Greater than `MAX_TIMEOUT` milliseconds (49.7 days) is clamped to it. A timed wait never
+    //   turns into an infinite one, so callers that need longer waits have to wait again.
     dur.as_secs()
         .checked_mul(1000)
         .and_then(|ms| ms.checked_add((dur.subsec_nanos() as u64) / 1_000_000))
         .and_then(|ms| ms.checked_add(if dur.subsec_nanos() % 1_000_000 > 0 { 1 } else { 0 }))
-        .map(|ms| if ms > <c::DWORD>::MAX as u64 { c::INFINITE } else { ms as c::DWORD })
-        .unwrap_or(c::INFINITE)
+        .map(|ms| if ms > MAX_TIMEOUT as u64 { MAX_TIMEOUT } else { ms as c::DWORD })
+        .unwrap_or(MAX_TIMEOUT)
 }
 
 /// Use `__fastfail` to abort the process
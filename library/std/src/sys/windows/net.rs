@@ -1,21 +1,29 @@
 #![unstable(issue = "none", feature = "windows_net")]
 
+#[cfg(test)]
+mod tests;
+
+pub mod overlapped;
+
 use crate::cmp;
+use crate::ffi::OsString;
+use crate::fmt::Write;
 use crate::io::{self, IoSlice, IoSliceMut, Read};
 use crate::lazy::SyncOnceCell;
 use crate::mem;
-use crate::net::{Shutdown, SocketAddr};
+use crate::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, SocketAddrV4};
 use crate::os::windows::io::{
     AsRawSocket, AsSocket, BorrowedSocket, FromRawSocket, IntoRawSocket, OwnedSocket, RawSocket,
 };
 use crate::ptr;
+use crate::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use crate::sys;
-use crate::sys::c;
+use crate::sys::{c, last_net_error};
 use crate::sys_common::net;
 use crate::sys_common::{AsInner, FromInner, IntoInner};
 use crate::time::Duration;
 
-use libc::{c_int, c_long, c_ulong, c_ushort};
+use libc::{c_int, c_long, c_uint, c_ulong, c_ushort};
 
 pub type wrlen_t = i32;
 
@@ -27,7 +35,72 @@ pub mod netc {
     pub use crate::sys::c::*;
 }
 
-pub struct Socket(OwnedSocket);
+/// An owned socket, closed with `closesocket` when dropped.
+///
+/// Every socket also keeps Winsock started (see `WsaRef`), so the last one to be dropped is what
+/// finally releases Winsock, even if `cleanup` has already run.
+pub struct Socket {
+    inner: OwnedSocket,
+    /// The mode last set through `set_nonblocking`, as Windows has no way to query it. Accepted and
+    /// duplicated sockets take it from the socket they came from, while sockets adopted from a raw
+    /// socket are assumed to be blocking, which is what sockets are created as.
+    nonblocking: AtomicBool,
+    _wsa: WsaRef,
+}
+
+/// Switches a socket to non-blocking mode for as long as it is alive, and restores the previous
+/// mode when dropped, including during unwinding.
+pub struct NonBlockingGuard<'a> {
+    socket: &'a Socket,
+    previous: bool,
+}
+
+impl<'a> NonBlockingGuard<'a> {
+    pub fn new(socket: &'a Socket) -> io::Result<NonBlockingGuard<'a>> {
+        let previous = socket.is_nonblocking();
+        socket.set_nonblocking(true)?;
+        Ok(NonBlockingGuard { socket, previous })
+    }
+}
+
+impl Drop for NonBlockingGuard<'_> {
+    fn drop(&mut self) {
+        let _ = self.socket.set_nonblocking(self.previous);
+    }
+}
+
+/// Keeps Winsock started while alive.
+///
+/// Only `init` calls `WSAStartup`, and the single reference it takes is shared by all sockets:
+/// `cleanup` merely gives up its own share, and `WSACleanup` runs once the last share is gone.
+struct WsaRef(());
+
+/// The live `WsaRef`s, plus one for `init` until `cleanup` runs.
+static WSA_USERS: AtomicUsize = AtomicUsize::new(0);
+
+impl WsaRef {
+    fn new() -> WsaRef {
+        init();
+        WSA_USERS.fetch_add(1, Ordering::Relaxed);
+        WsaRef(())
+    }
+}
+
+impl Drop for WsaRef {
+    fn drop(&mut self) {
+        release_wsa();
+    }
+}
+
+fn release_wsa() {
+    if WSA_USERS.fetch_sub(1, Ordering::AcqRel) == 1 {
+        if let Some(cleanup) = WSA_CLEANUP.get() {
+            unsafe {
+                cleanup();
+            }
+        }
+    }
+}
 
 static WSA_CLEANUP: SyncOnceCell<unsafe extern "system" fn() -> i32> = SyncOnceCell::new();
 
@@ -41,6 +114,8 @@ pub fn init() {
             &mut data,
         );
         assert_eq!(ret, 0);
+        WSA_USERS.fetch_add(1, Ordering::Relaxed);
+        let _ = WINSOCK_VERSION.set((data.wVersion as u8, (data.wVersion >> 8) as u8));
 
         // Only register `WSACleanup` if `WSAStartup` is actually ever called.
         // Workaround to prevent linking to `WS2_32.dll` when no network functionality is used.
@@ -49,18 +124,241 @@ pub fn init() {
     });
 }
 
+static WINSOCK_VERSION: SyncOnceCell<(u8, u8)> = SyncOnceCell::new();
+
+/// The (major, minor) Winsock version `init` negotiated, or `None` if it hasn't run yet. This is
+/// 2.2 where ws2_32 is there, but 95 without the Winsock 2 update only offers 1.1, which lacks
+/// most of the socket options and the overlapped functions.
+pub fn winsock_version() -> Option<(u8, u8)> {
+    WINSOCK_VERSION.get().copied()
+}
+
 pub fn cleanup() {
-    // only perform cleanup if network functionality was actually initialized
-    if let Some(cleanup) = WSA_CLEANUP.get() {
-        unsafe {
-            cleanup();
+    static CLEANED_UP: AtomicBool = AtomicBool::new(false);
+    // only perform cleanup if network functionality was actually initialized, and leave it to the
+    // last socket if some are still open
+    if WSA_CLEANUP.get().is_some() && !CLEANED_UP.swap(true, Ordering::Relaxed) {
+        release_wsa();
+    }
+}
+
+/// Takes ownership of a `SOCKET` from C code as a `TcpStream`, after checking that it is a stream
+/// socket. The stream holds a Winsock reference of its own, so it doesn't matter who started
+/// Winsock, and the socket is closed when the stream is dropped. On an error, the socket is left
+/// open and still belongs to the caller.
+///
+/// # Safety
+///
+/// `sock` must be an open socket that is not owned by anything else.
+pub unsafe fn tcp_stream_from_raw_socket(sock: c::SOCKET) -> io::Result<crate::net::TcpStream> {
+    let socket = Socket::from_raw(sock as RawSocket);
+    match net::getsockopt::<c_int>(&socket, c::SOL_SOCKET, c::SO_TYPE) {
+        Ok(c::SOCK_STREAM) => {
+            Ok(crate::net::TcpStream::from_inner(net::TcpStream::from_inner(socket)))
+        }
+        result => {
+            socket.into_raw();
+            result?;
+            Err(io::const_io_error!(io::ErrorKind::InvalidInput, "not a stream socket"))
+        }
+    }
+}
+
+/// An adapter with IPv4 addresses, as reported by `GetAdaptersInfo`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdapterInfo {
+    pub name: OsString,
+    pub description: OsString,
+    pub index: u32,
+    pub addresses: Vec<Ipv4Addr>,
+    pub gateways: Vec<Ipv4Addr>,
+}
+
+/// Lists the IPv4 adapters of the system. 95 has no iphlpapi, which is reported as no adapters
+/// rather than an error, the same as a system without any network adapters.
+pub fn adapters() -> io::Result<Vec<AdapterInfo>> {
+    if !c::GetAdaptersInfo::available() {
+        return Ok(Vec::new());
+    }
+
+    // `u64`s to keep the entries aligned
+    let mut buf: Vec<u64> = Vec::new();
+    let mut size: c::ULONG = 0;
+    loop {
+        let ptr = if buf.is_empty() { ptr::null_mut() } else { buf.as_mut_ptr().cast() };
+        match unsafe { c::GetAdaptersInfo(ptr, &mut size) } {
+            c::ERROR_SUCCESS => break,
+            c::ERROR_NO_DATA => return Ok(Vec::new()),
+            c::ERROR_BUFFER_OVERFLOW => {
+                buf = vec![0; (size as usize + mem::size_of::<u64>() - 1) / mem::size_of::<u64>()]
+            }
+            err => return Err(io::Error::from_raw_os_error(err as i32)),
+        }
+    }
+
+    // everything is copied out, the list points into `buf`
+    let mut adapters = Vec::new();
+    let mut entry = buf.as_ptr() as *const c::IP_ADAPTER_INFO;
+    while !entry.is_null() {
+        let adapter = unsafe { &*entry };
+        adapters.push(AdapterInfo {
+            name: super::from_ansi_buf(&adapter.AdapterName),
+            description: super::from_ansi_buf(&adapter.Description),
+            index: adapter.Index,
+            addresses: unsafe { ip_addr_list(&adapter.IpAddressList) },
+            gateways: unsafe { ip_addr_list(&adapter.GatewayList) },
+        });
+        entry = adapter.Next;
+    }
+    Ok(adapters)
+}
+
+/// Empty lists have a single "0.0.0.0" entry, which is skipped like anything that can't be parsed.
+unsafe fn ip_addr_list(mut entry: *const c::IP_ADDR_STRING) -> Vec<Ipv4Addr> {
+    let mut addrs = Vec::new();
+    while !entry.is_null() {
+        let chars = &(*entry).IpAddress.String;
+        let len = chars.iter().position(|&c| c == 0).unwrap_or(chars.len());
+        let text: Vec<u8> = chars[..len].iter().map(|&c| c as u8).collect();
+        match crate::str::from_utf8(&text).ok().and_then(|text| text.parse::<Ipv4Addr>().ok()) {
+            Some(addr) if !addr.is_unspecified() => addrs.push(addr),
+            _ => {}
         }
+        entry = (*entry).Next;
+    }
+    addrs
+}
+
+// `inet_pton` and `inet_ntop` are Vista+, and `inet_addr`/`inet_ntoa` take more than dotted
+// decimal and return per-thread buffers. These go through std's own parser and formatter instead,
+// which take and produce the same forms as `inet_pton`/`inet_ntop` without needing Winsock.
+
+/// Dotted decimal with exactly four parts and no leading zeros, which `inet_addr` reads as octal.
+pub fn pton_v4(src: &str) -> Option<Ipv4Addr> {
+    src.parse().ok()
+}
+
+/// Without a scope id, like `inet_pton`. A trailing dotted decimal IPv4 part is allowed.
+pub fn pton_v6(src: &str) -> Option<Ipv6Addr> {
+    src.parse().ok()
+}
+
+pub fn ntop_v4(addr: &Ipv4Addr) -> String {
+    addr.to_string()
+}
+
+/// In the RFC 5952 form, with IPv4-mapped addresses written as `::ffff:a.b.c.d`.
+///
+/// `Display` differs for the deprecated IPv4-compatible addresses, which it writes as `::a.b.c.d`,
+/// while RFC 5952 only allows the dotted form for IPv4-mapped ones.
+pub fn ntop_v6(addr: &Ipv6Addr) -> String {
+    if let Some(ipv4) = addr.to_ipv4_mapped() {
+        return format!("::ffff:{}", ipv4);
+    }
+
+    let segments = addr.segments();
+    // the longest run of at least two zero segments, or the first one of those that are longest
+    let (mut zeros_at, mut zeros) = (0, 0);
+    let mut i = 0;
+    while i < segments.len() {
+        let run = segments[i..].iter().take_while(|&&segment| segment == 0).count();
+        if run > zeros {
+            (zeros_at, zeros) = (i, run);
+        }
+        i += cmp::max(run, 1);
+    }
+
+    let mut out = String::new();
+    let mut i = 0;
+    while i < segments.len() {
+        if i == zeros_at && zeros >= 2 {
+            out.push_str("::");
+            i += zeros;
+            continue;
+        }
+        if i > 0 && !out.ends_with(':') {
+            out.push(':');
+        }
+        write!(out, "{:x}", segments[i]).unwrap();
+        i += 1;
+    }
+    out
+}
+
+/// Binds `sock` to `addr`. IPv4 addresses get a `sockaddr_in` set up like the ones `wspiapi`
+/// returns.
+///
+/// Neither NT nor 9x treat ports below 1024 as privileged. On NT, binding fails with `WSAEACCES`
+/// (`PermissionDenied`) if another socket holds the address with `SO_EXCLUSIVEADDRUSE`, or the
+/// port is reserved, which 9x has neither of. An address in use is `WSAEADDRINUSE` (`AddrInUse`)
+/// on both.
+pub fn bind(sock: &Socket, addr: &SocketAddr) -> io::Result<()> {
+    let storage;
+    let (addrp, len) = match addr {
+        SocketAddr::V4(addr) => {
+            storage = c::sockaddr_in {
+                sin_family: c::AF_INET as c::ADDRESS_FAMILY,
+                sin_port: addr.port().to_be(),
+                sin_addr: c::in_addr { s_addr: u32::from_ne_bytes(addr.ip().octets()) },
+                sin_zero: [0; 8],
+            };
+            let len = mem::size_of::<c::sockaddr_in>() as c::socklen_t;
+            (&storage as *const c::sockaddr_in as *const c::SOCKADDR, len)
+        }
+        SocketAddr::V6(_) => addr.into_inner(),
+    };
+    cvt(unsafe { c::bind(sock.as_raw_socket(), addrp, len) }).map(drop)
+}
+
+/// The largest backlog `listen` passes on. On NT, that's `SOMAXCONN`, which makes the provider
+/// pick its own maximum. 9x only ever queues 5 connections, and some of its stacks fail with
+/// `WSAEINVAL` instead of clamping larger values.
+pub fn max_backlog() -> c_int {
+    if crate::sys::compat::version::is_windows_nt() { c::SOMAXCONN } else { 5 }
+}
+
+/// Starts listening on `sock`, with `backlog` clamped to `max_backlog`.
+pub fn listen(sock: &Socket, backlog: c_int) -> io::Result<()> {
+    listen_capped(sock, backlog, max_backlog())
+}
+
+fn listen_capped(sock: &Socket, backlog: c_int, max: c_int) -> io::Result<()> {
+    cvt(unsafe { c::listen(sock.as_raw_socket(), backlog.clamp(0, max)) }).map(drop)
+}
+
+/// Whether `setsockopt` failed because `IPV6_V6ONLY` doesn't exist, as before Vista.
+fn is_missing_only_v6(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(c::WSAENOPROTOOPT)
+}
+
+/// Maps the `result` of setting `IPV6_V6ONLY` to `only_v6`. Without the option, IPv6 sockets are
+/// always IPv6-only, so only asking for dual-stack sockets is an error.
+fn only_v6_result(only_v6: bool, result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(ref e) if is_missing_only_v6(e) && !only_v6 => Err(io::const_io_error!(
+            io::ErrorKind::Unsupported,
+            "IPv6 sockets can't accept IPv4 connections before Windows Vista",
+        )),
+        Err(ref e) if is_missing_only_v6(e) => Ok(()),
+        result => result,
     }
 }
 
-/// Returns the last error from the Windows socket interface.
-fn last_error() -> io::Error {
-    io::Error::from_raw_os_error(unsafe { c::WSAGetLastError() })
+/// Whether IPv6 sockets can be created. 9x/ME and NT 4 have no IPv6 stack, and 2000/XP only if
+/// one was installed, which is checked once by creating (and closing) a socket.
+pub fn supports_ipv6() -> bool {
+    static SUPPORTS_IPV6: SyncOnceCell<bool> = SyncOnceCell::new();
+    *SUPPORTS_IPV6.get_or_init(|| {
+        init();
+        let socket =
+            unsafe { c::WSASocketA(c::AF_INET6, c::SOCK_STREAM, 0, ptr::null_mut(), 0, 0) };
+        if socket == c::INVALID_SOCKET {
+            // Other errors may be temporary, let the actual socket creation report them.
+            return last_net_error().raw_os_error() != Some(c::WSAEAFNOSUPPORT);
+        }
+        unsafe { c::closesocket(socket) };
+        true
+    })
 }
 
 #[doc(hidden)]
@@ -82,12 +380,12 @@ fn is_minus_one(&self) -> bool {
 /// and if so, returns the last error from the Windows socket interface. This
 /// function must be called before another call to the socket API is made.
 pub fn cvt<T: IsMinusOne>(t: T) -> io::Result<T> {
-    if t.is_minus_one() { Err(last_error()) } else { Ok(t) }
+    if t.is_minus_one() { Err(last_net_error()) } else { Ok(t) }
 }
 
 /// A variant of `cvt` for `getaddrinfo` which return 0 for a success.
 pub fn cvt_gai(err: c_int) -> io::Result<()> {
-    if err == 0 { Ok(()) } else { Err(last_error()) }
+    if err == 0 { Ok(()) } else { Err(last_net_error()) }
 }
 
 /// Just to provide the same interface as sys/unix/net.rs
@@ -99,10 +397,230 @@ pub fn cvt_r<T, F>(mut f: F) -> io::Result<T>
     cvt(f())
 }
 
+/// Retrieves and clears the pending error of a socket via `SO_ERROR`.
+///
+/// This is used to find out how a non-blocking `connect` went after `select` reported the socket
+/// as writable. `Ok(None)` means the connect succeeded. Some 9x Winsock versions report
+/// `WSAEISCONN` for a socket that is already connected, which is treated as success as well there,
+/// but nowhere else.
+pub fn take_socket_error(sock: &Socket) -> io::Result<Option<io::Error>> {
+    let raw: c_int = net::getsockopt(sock, c::SOL_SOCKET, c::SO_ERROR)?;
+    match raw {
+        0 => Ok(None),
+        c::WSAEISCONN if !crate::sys::compat::version::is_windows_nt() => Ok(None),
+        _ => Ok(Some(io::Error::from_raw_os_error(raw))),
+    }
+}
+
+/// Returns how many bytes can be read from a socket without blocking.
+///
+/// Uses `ioctlsocket(FIONREAD)`, which unlike most `WSAIoctl` codes works the same on 9x and NT.
+pub fn peek_len(sock: &Socket) -> io::Result<usize> {
+    let mut len: c_ulong = 0;
+    cvt(unsafe { c::ioctlsocket(sock.as_raw_socket(), c::FIONREAD as c_int, &mut len) })?;
+    Ok(len as usize)
+}
+
+/// Maps the `WSAEMSGSIZE` a peek into a too small buffer produces to a descriptive error.
+fn peek_error(error: io::Error) -> io::Error {
+    if error.raw_os_error() == Some(c::WSAEMSGSIZE) {
+        io::const_io_error!(
+            io::ErrorKind::InvalidInput,
+            "peek buffer is smaller than the pending datagram",
+        )
+    } else {
+        error
+    }
+}
+
+/// Returns the local address a socket is bound to.
+fn local_addr(sock: &Socket) -> io::Result<SocketAddr> {
+    unsafe {
+        let mut storage: c::SOCKADDR_STORAGE_LH = mem::zeroed();
+        let mut len = mem::size_of_val(&storage) as c::socklen_t;
+        cvt(c::getsockname(sock.as_raw_socket(), &mut storage as *mut _ as *mut _, &mut len))?;
+        net::sockaddr_to_addr(&storage, len as usize)
+    }
+}
+
+/// Converts a `select` timeout, rounding zero up so that it doesn't mean "wait forever".
+fn to_timeval(timeout: Duration) -> c::timeval {
+    let mut timeout = c::timeval {
+        tv_sec: cmp::min(timeout.as_secs(), c_long::MAX as u64) as c_long,
+        tv_usec: (timeout.subsec_nanos() / 1000) as c_long,
+    };
+    if timeout.tv_sec == 0 && timeout.tv_usec == 0 {
+        timeout.tv_usec = 1;
+    }
+    timeout
+}
+
+/// A heap allocated `fd_set` with room for any number of sockets, as `c::fd_set` only holds
+/// `FD_SETSIZE` (64).
+///
+/// Winsock's `fd_set` is just a count followed by an array of sockets, and `select` goes by the
+/// count, so the array can be as long as needed.
+pub struct FdSet {
+    /// The first slot holds the `fd_count` (padded to the size of a socket, like in `fd_set`).
+    slots: Vec<c::SOCKET>,
+}
+
+impl FdSet {
+    pub fn with_capacity(capacity: usize) -> FdSet {
+        FdSet { slots: vec![0; capacity + 1] }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        unsafe { *self.slots.as_ptr().cast::<c_uint>() as usize }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn sockets(&self) -> &[c::SOCKET] {
+        &self.slots[1..][..self.len()]
+    }
+
+    pub fn contains(&self, socket: c::SOCKET) -> bool {
+        self.sockets().contains(&socket)
+    }
+
+    /// Adds `socket` unless it's already in the set. Returns `false` if the set is full.
+    pub fn insert(&mut self, socket: c::SOCKET) -> bool {
+        if self.contains(socket) {
+            return true;
+        }
+        let len = self.len();
+        if len == self.capacity() {
+            return false;
+        }
+        self.slots[len + 1] = socket;
+        self.set_len(len + 1);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.set_len(0);
+    }
+
+    fn set_len(&mut self, len: usize) {
+        unsafe { *self.slots.as_mut_ptr().cast::<c_uint>() = len as c_uint };
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut c::fd_set {
+        self.slots.as_mut_ptr().cast()
+    }
+}
+
+/// `select` on sets of any size, which are updated to only contain the ready sockets. Returns the
+/// number of ready sockets, 0 meaning that `timeout` elapsed.
+///
+/// Winsock on 9x/ME may not take sets larger than `FD_SETSIZE`, and fails with `WSAEINVAL` then,
+/// which is reported as an [`io::ErrorKind::InvalidInput`] error.
+pub fn select(
+    read: Option<&mut FdSet>,
+    write: Option<&mut FdSet>,
+    except: Option<&mut FdSet>,
+    timeout: Option<Duration>,
+) -> io::Result<usize> {
+    let too_large = [&read, &write, &except]
+        .iter()
+        .any(|set| set.as_ref().map_or(false, |set| set.len() > c::FD_SETSIZE));
+    let as_ptr = |set: Option<&mut FdSet>| set.map_or(ptr::null_mut(), FdSet::as_mut_ptr);
+    let timeout = timeout.map(to_timeval);
+
+    let count = unsafe {
+        c::select(
+            // ignored by winsock
+            0,
+            as_ptr(read),
+            as_ptr(write),
+            as_ptr(except),
+            timeout.as_ref().map_or(ptr::null(), |t| t as *const _),
+        )
+    };
+    if count == c::SOCKET_ERROR {
+        let error = last_net_error();
+        if error.raw_os_error() == Some(c::WSAEINVAL)
+            && too_large
+            && !crate::sys::compat::version::is_windows_nt()
+        {
+            return Err(io::const_io_error!(
+                io::ErrorKind::InvalidInput,
+                "socket set too large for select on this system",
+            ));
+        }
+        return Err(error);
+    }
+    Ok(count as usize)
+}
+
+/// A loopback UDP socket pair used to interrupt a blocking read from another thread.
+///
+/// 9x has nothing like `eventfd`, so instead of blocking in `recv` directly,
+/// [`Socket::read_interruptible`] `select()`s on both the socket it wants to read from and the
+/// receiving half of this pair. [`WakeupSocket::wake`] sends a single byte to the receiving half,
+/// which makes the `select()` return early.
+pub struct WakeupSocket {
+    recv: Socket,
+    send: Socket,
+}
+
+impl WakeupSocket {
+    pub fn new() -> io::Result<WakeupSocket> {
+        init();
+
+        let loopback = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0));
+
+        let recv = Socket::new(&loopback, c::SOCK_DGRAM)?;
+        bind(&recv, &loopback)?;
+        // draining must never block, no matter how many wakeups are pending
+        recv.set_nonblocking(true)?;
+
+        let send = Socket::new(&loopback, c::SOCK_DGRAM)?;
+        let recv_addr = local_addr(&recv)?;
+        let (addrp, len) = recv_addr.into_inner();
+        cvt(unsafe { c::connect(send.as_raw_socket(), addrp, len) })?;
+
+        Ok(WakeupSocket { recv, send })
+    }
+
+    /// Releases a thread currently blocked in (or about to enter)
+    /// [`Socket::read_interruptible`] with this wakeup socket.
+    pub fn wake(&self) -> io::Result<()> {
+        let byte = [1u8];
+        let result = unsafe {
+            c::send(self.send.as_raw_socket(), byte.as_ptr() as *const _, byte.len() as c_int, 0)
+        };
+        cvt(result).map(drop)
+    }
+
+    /// Discards all pending wakeups.
+    fn drain(&self) {
+        let mut buf = [0u8; 16];
+        while let Ok(n) = self.recv.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+        }
+    }
+}
+
 impl Socket {
     pub fn new(addr: &SocketAddr, ty: c_int) -> io::Result<Socket> {
         let family = match *addr {
             SocketAddr::V4(..) => c::AF_INET,
+            SocketAddr::V6(..) if !supports_ipv6() => {
+                return Err(io::const_io_error!(
+                    io::ErrorKind::Unsupported,
+                    "IPv6 is not supported on this system",
+                ));
+            }
             SocketAddr::V6(..) => c::AF_INET6,
         };
         let socket = unsafe {
@@ -119,35 +637,34 @@ pub fn new(addr: &SocketAddr, ty: c_int) -> io::Result<Socket> {
         if socket != c::INVALID_SOCKET {
             unsafe { Ok(Self::from_raw_socket(socket)) }
         } else {
-            let error = unsafe { c::WSAGetLastError() };
+            let error = last_net_error();
 
-            if error != c::WSAEPROTOTYPE && error != c::WSAEINVAL {
-                return Err(io::Error::from_raw_os_error(error));
+            if !matches!(error.raw_os_error(), Some(c::WSAEPROTOTYPE | c::WSAEINVAL)) {
+                return Err(error);
             }
 
             let socket =
                 unsafe { c::WSASocketA(family, ty, 0, ptr::null_mut(), 0, c::WSA_FLAG_OVERLAPPED) };
 
             if socket == c::INVALID_SOCKET {
-                return Err(last_error());
+                return Err(last_net_error());
             }
 
             unsafe {
                 let socket = Self::from_raw_socket(socket);
-                socket.0.set_no_inherit()?;
+                socket.inner.set_no_inherit()?;
                 Ok(socket)
             }
         }
     }
 
     pub fn connect_timeout(&self, addr: &SocketAddr, timeout: Duration) -> io::Result<()> {
-        self.set_nonblocking(true)?;
         let result = {
+            let _guard = NonBlockingGuard::new(self)?;
             let (addrp, len) = addr.into_inner();
             let result = unsafe { c::connect(self.as_raw_socket(), addrp, len) };
             cvt(result).map(drop)
         };
-        self.set_nonblocking(false)?;
 
         match result {
             Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => {
@@ -188,7 +705,7 @@ pub fn connect_timeout(&self, addr: &SocketAddr, timeout: Duration) -> io::Resul
                     0 => Err(io::const_io_error!(io::ErrorKind::TimedOut, "connection timed out")),
                     _ => {
                         if writefds.fd_count != 1 {
-                            if let Some(e) = self.take_error()? {
+                            if let Some(e) = take_socket_error(self)? {
                                 return Err(e);
                             }
                         }
@@ -205,13 +722,50 @@ pub fn accept(&self, storage: *mut c::SOCKADDR, len: *mut c_int) -> io::Result<S
         let socket = unsafe { c::accept(self.as_raw_socket(), storage, len) };
 
         match socket {
-            c::INVALID_SOCKET => Err(last_error()),
-            _ => unsafe { Ok(Self::from_raw_socket(socket)) },
+            c::INVALID_SOCKET => Err(last_net_error()),
+            _ => {
+                let socket = unsafe { Self::from_raw_socket(socket) };
+                // accepted sockets inherit the blocking mode of the listening socket
+                socket.nonblocking.store(self.is_nonblocking(), Ordering::Relaxed);
+                Ok(socket)
+            }
         }
     }
 
+    /// Accepts an IPv4 connection, returning the new socket together with the peer address.
+    ///
+    /// Some 9x Winsock versions hand back a zeroed address instead of failing if `addrlen` does
+    /// not match what they expect, so the length is set up for exactly a `sockaddr_in` and the
+    /// result is validated. A zeroed address is re-queried with `getpeername`.
+    pub fn accept_v4(&self) -> io::Result<(Socket, SocketAddrV4)> {
+        const LEN: usize = mem::size_of::<c::sockaddr_in>();
+
+        let mut storage: c::sockaddr_in = unsafe { mem::zeroed() };
+        let mut len = LEN as c_int;
+        let socket = self.accept(&mut storage as *mut _ as *mut _, &mut len)?;
+
+        if len == 0 || storage.sin_port == 0 {
+            len = LEN as c_int;
+            cvt(unsafe {
+                c::getpeername(socket.as_raw_socket(), &mut storage as *mut _ as *mut _, &mut len)
+            })?;
+        }
+
+        if len as usize != LEN || storage.sin_family != c::AF_INET as c::ADDRESS_FAMILY {
+            return Err(io::const_io_error!(
+                io::ErrorKind::InvalidData,
+                "accept returned an invalid peer address",
+            ));
+        }
+
+        Ok((socket, SocketAddrV4::from_inner(storage)))
+    }
+
     pub fn duplicate(&self) -> io::Result<Socket> {
-        Ok(Self(self.0.try_clone()?))
+        let socket = Self::from_inner(self.inner.try_clone()?);
+        // the duplicate is the same socket, so it's in the same mode
+        socket.nonblocking.store(self.is_nonblocking(), Ordering::Relaxed);
+        Ok(socket)
     }
 
     fn recv_with_flags(&self, buf: &mut [u8], flags: c_int) -> io::Result<usize> {
@@ -223,13 +777,9 @@ fn recv_with_flags(&self, buf: &mut [u8], flags: c_int) -> io::Result<usize> {
 
         match result {
             c::SOCKET_ERROR => {
-                let error = unsafe { c::WSAGetLastError() };
+                let error = last_net_error();
 
-                if error == c::WSAESHUTDOWN {
-                    Ok(0)
-                } else {
-                    Err(io::Error::from_raw_os_error(error))
-                }
+                if error.raw_os_error() == Some(c::WSAESHUTDOWN) { Ok(0) } else { Err(error) }
             }
             _ => Ok(result as usize),
         }
@@ -239,6 +789,47 @@ pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
         self.recv_with_flags(buf, 0)
     }
 
+    /// Reads from the socket like [`Socket::read`], but returns early with an
+    /// [`io::ErrorKind::Interrupted`] error once `wakeup` is woken, or with
+    /// [`io::ErrorKind::TimedOut`] once `timeout` elapses.
+    pub fn read_interruptible(
+        &self,
+        buf: &mut [u8],
+        wakeup: &WakeupSocket,
+        timeout: Option<Duration>,
+    ) -> io::Result<usize> {
+        let timeout = timeout.map(to_timeval);
+
+        let mut readfds = unsafe { mem::zeroed::<c::fd_set>() };
+        readfds.fd_count = 2;
+        readfds.fd_array[0] = self.as_raw_socket();
+        readfds.fd_array[1] = wakeup.recv.as_raw_socket();
+
+        let count = unsafe {
+            c::select(
+                // ignored by winsock
+                0,
+                &mut readfds,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                timeout.as_ref().map_or(ptr::null(), |t| t as *const _),
+            )
+        };
+
+        match cvt(count)? {
+            0 => Err(io::const_io_error!(io::ErrorKind::TimedOut, "read timed out")),
+            _ => {
+                let ready = &readfds.fd_array[..readfds.fd_count as usize];
+                if ready.contains(&wakeup.recv.as_raw_socket()) {
+                    wakeup.drain();
+                    Err(io::const_io_error!(io::ErrorKind::Interrupted, "read was interrupted"))
+                } else {
+                    self.read(buf)
+                }
+            }
+        }
+    }
+
     pub fn read_vectored(&self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
         // On unix when a socket is shut down all further reads return 0, so we
         // do the same on windows to map a shut down socket to returning EOF.
@@ -260,13 +851,9 @@ pub fn read_vectored(&self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
         match result {
             0 => Ok(nread as usize),
             _ => {
-                let error = unsafe { c::WSAGetLastError() };
+                let error = last_net_error();
 
-                if error == c::WSAESHUTDOWN {
-                    Ok(0)
-                } else {
-                    Err(io::Error::from_raw_os_error(error))
-                }
+                if error.raw_os_error() == Some(c::WSAESHUTDOWN) { Ok(0) } else { Err(error) }
             }
         }
     }
@@ -276,8 +863,13 @@ pub fn is_read_vectored(&self) -> bool {
         true
     }
 
+    /// Reads data from the socket without removing it from the queue.
+    ///
+    /// For datagram sockets, 9x Winsock fails a peek into a buffer smaller than the pending
+    /// datagram with `WSAEMSGSIZE`. This is reported as an [`io::ErrorKind::InvalidInput`] error,
+    /// and the datagram is left in the queue.
     pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
-        self.recv_with_flags(buf, c::MSG_PEEK)
+        self.recv_with_flags(buf, c::MSG_PEEK).map_err(peek_error)
     }
 
     fn recv_from_with_flags(
@@ -304,12 +896,12 @@ fn recv_from_with_flags(
 
         match result {
             c::SOCKET_ERROR => {
-                let error = unsafe { c::WSAGetLastError() };
+                let error = last_net_error();
 
-                if error == c::WSAESHUTDOWN {
+                if error.raw_os_error() == Some(c::WSAESHUTDOWN) {
                     Ok((0, net::sockaddr_to_addr(&storage, addrlen as usize)?))
                 } else {
-                    Err(io::Error::from_raw_os_error(error))
+                    Err(error)
                 }
             }
             _ => Ok((result as usize, net::sockaddr_to_addr(&storage, addrlen as usize)?)),
@@ -320,8 +912,9 @@ pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
         self.recv_from_with_flags(buf, 0)
     }
 
+    /// Like [`Socket::peek`], but also returns the address the data came from.
     pub fn peek_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
-        self.recv_from_with_flags(buf, c::MSG_PEEK)
+        self.recv_from_with_flags(buf, c::MSG_PEEK).map_err(peek_error)
     }
 
     pub fn write_vectored(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
@@ -385,10 +978,17 @@ pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
     }
 
     pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
-        let mut nonblocking = nonblocking as c_ulong;
+        let mut mode = nonblocking as c_ulong;
         let result =
-            unsafe { c::ioctlsocket(self.as_raw_socket(), c::FIONBIO as c_int, &mut nonblocking) };
-        cvt(result).map(drop)
+            unsafe { c::ioctlsocket(self.as_raw_socket(), c::FIONBIO as c_int, &mut mode) };
+        cvt(result)?;
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns the mode last set through [`Socket::set_nonblocking`].
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblocking.load(Ordering::Relaxed)
     }
 
     pub fn set_linger(&self, linger: Option<Duration>) -> io::Result<()> {
@@ -415,15 +1015,47 @@ pub fn nodelay(&self) -> io::Result<bool> {
         Ok(raw != 0)
     }
 
+    /// Sets `IPV6_V6ONLY`.
+    ///
+    /// Before Vista there are separate IPv4 and IPv6 stacks, so IPv6 sockets never accept IPv4
+    /// connections and the option doesn't exist. Asking for that is what they do anyway, while
+    /// asking for a dual-stack socket fails with `Unsupported`.
+    pub fn set_only_v6(&self, only_v6: bool) -> io::Result<()> {
+        let result = net::setsockopt(self, c::IPPROTO_IPV6, c::IPV6_V6ONLY, only_v6 as c_int);
+        only_v6_result(only_v6, result)
+    }
+
+    /// Gets `IPV6_V6ONLY`, which is always set before Vista, see `set_only_v6`.
+    pub fn only_v6(&self) -> io::Result<bool> {
+        match net::getsockopt::<c_int>(self, c::IPPROTO_IPV6, c::IPV6_V6ONLY) {
+            Ok(raw) => Ok(raw != 0),
+            Err(ref e) if is_missing_only_v6(e) => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn take_error(&self) -> io::Result<Option<io::Error>> {
-        let raw: c_int = net::getsockopt(self, c::SOL_SOCKET, c::SO_ERROR)?;
-        if raw == 0 { Ok(None) } else { Ok(Some(io::Error::from_raw_os_error(raw as i32))) }
+        take_socket_error(self)
     }
 
     // This is used by sys_common code to abstract over Windows and Unix.
     pub fn as_raw(&self) -> RawSocket {
         self.as_inner().as_raw_socket()
     }
+
+    /// Releases ownership of the raw `SOCKET` without closing it.
+    pub fn into_raw(self) -> RawSocket {
+        self.into_raw_socket()
+    }
+
+    /// Takes ownership of a raw `SOCKET`, which will be closed when the returned value is dropped.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be an open socket that is not owned by anything else.
+    pub unsafe fn from_raw(raw: RawSocket) -> Socket {
+        Self::from_raw_socket(raw)
+    }
 }
 
 #[unstable(reason = "not public", issue = "none", feature = "fd_read")]
@@ -435,42 +1067,42 @@ fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
 
 impl AsInner<OwnedSocket> for Socket {
     fn as_inner(&self) -> &OwnedSocket {
-        &self.0
+        &self.inner
     }
 }
 
 impl FromInner<OwnedSocket> for Socket {
     fn from_inner(sock: OwnedSocket) -> Socket {
-        Socket(sock)
+        Socket { inner: sock, nonblocking: AtomicBool::new(false), _wsa: WsaRef::new() }
     }
 }
 
 impl IntoInner<OwnedSocket> for Socket {
     fn into_inner(self) -> OwnedSocket {
-        self.0
+        self.inner
     }
 }
 
 impl AsSocket for Socket {
     fn as_socket(&self) -> BorrowedSocket<'_> {
-        self.0.as_socket()
+        self.inner.as_socket()
     }
 }
 
 impl AsRawSocket for Socket {
     fn as_raw_socket(&self) -> RawSocket {
-        self.0.as_raw_socket()
+        self.inner.as_raw_socket()
     }
 }
 
 impl IntoRawSocket for Socket {
     fn into_raw_socket(self) -> RawSocket {
-        self.0.into_raw_socket()
+        self.inner.into_raw_socket()
     }
 }
 
 impl FromRawSocket for Socket {
     unsafe fn from_raw_socket(raw_socket: RawSocket) -> Self {
-        Self(FromRawSocket::from_raw_socket(raw_socket))
+        Self::from_inner(FromRawSocket::from_raw_socket(raw_socket))
     }
 }
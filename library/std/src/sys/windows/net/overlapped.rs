@@ -0,0 +1,150 @@
+//! Overlapped socket IO, as groundwork for asynchronous IO.
+//!
+//! This is only supported on NT. 9x/ME accept overlapped calls on some sockets, but whether they
+//! work depends on the installed protocol providers, and there's no way to cancel them, so they
+//! are reported as unsupported there instead of half working.
+
+#[cfg(test)]
+mod tests;
+
+use super::Socket;
+use crate::cmp;
+use crate::io;
+use crate::mem;
+use crate::os::windows::io::{AsRawHandle, AsRawSocket};
+use crate::ptr;
+use crate::sys::c::{self, ws2_overlapped as ws2};
+use crate::sys::handle::Handle;
+use crate::sys::last_net_error;
+
+/// Whether overlapped socket IO can be used.
+pub fn is_supported() -> bool {
+    check_supported(crate::sys::compat::version::is_windows_nt()).is_ok()
+}
+
+fn check_supported(is_nt: bool) -> io::Result<()> {
+    if is_nt
+        && ws2::WSARecv::available()
+        && ws2::WSASend::available()
+        && ws2::WSAGetOverlappedResult::available()
+    {
+        Ok(())
+    } else {
+        Err(io::const_io_error!(
+            io::ErrorKind::Unsupported,
+            "overlapped socket IO is not supported on this system",
+        ))
+    }
+}
+
+/// Everything Winsock keeps a pointer to until the operation completes.
+struct State {
+    overlapped: c::OVERLAPPED,
+    wsabuf: c::WSABUF,
+    flags: c::DWORD,
+}
+
+/// A send or receive in progress, which owns its buffer until it completes.
+///
+/// Dropping an operation cancels it and waits for it to complete, as the buffer has to stay valid
+/// until then.
+pub struct Operation<'a> {
+    socket: &'a Socket,
+    state: Box<State>,
+    buf: Vec<u8>,
+    _event: Handle,
+    done: bool,
+}
+
+impl<'a> Operation<'a> {
+    /// Starts receiving into `buf`.
+    pub fn recv(socket: &'a Socket, buf: Vec<u8>) -> io::Result<Operation<'a>> {
+        Self::start(socket, buf, |socket, state| unsafe {
+            ws2::WSARecv(
+                socket,
+                &mut state.wsabuf,
+                1,
+                ptr::null_mut(),
+                &mut state.flags,
+                &mut state.overlapped as *mut c::OVERLAPPED as c::LPWSAOVERLAPPED,
+                ptr::null_mut(),
+            )
+        })
+    }
+
+    /// Starts sending `buf`.
+    pub fn send(socket: &'a Socket, buf: Vec<u8>) -> io::Result<Operation<'a>> {
+        Self::start(socket, buf, |socket, state| unsafe {
+            ws2::WSASend(
+                socket,
+                &mut state.wsabuf,
+                1,
+                ptr::null_mut(),
+                0,
+                &mut state.overlapped as *mut c::OVERLAPPED as c::LPWSAOVERLAPPED,
+                ptr::null_mut(),
+            )
+        })
+    }
+
+    fn start(
+        socket: &'a Socket,
+        mut buf: Vec<u8>,
+        op: impl FnOnce(c::SOCKET, &mut State) -> i32,
+    ) -> io::Result<Operation<'a>> {
+        check_supported(crate::sys::compat::version::is_windows_nt())?;
+
+        let event = Handle::new_event(true, false)?;
+        let mut state = Box::new(State {
+            overlapped: unsafe { mem::zeroed() },
+            wsabuf: c::WSABUF {
+                len: cmp::min(buf.len(), c::ULONG::MAX as usize) as c::ULONG,
+                buf: buf.as_mut_ptr() as *mut c::CHAR,
+            },
+            flags: 0,
+        });
+        state.overlapped.hEvent = event.as_raw_handle();
+
+        if op(socket.as_raw_socket(), &mut state) != 0 {
+            let error = last_net_error();
+            if error.raw_os_error() != Some(c::WSA_IO_PENDING) {
+                return Err(error);
+            }
+        }
+        // Completing right away is signaled like completing later, so `wait` handles both.
+        Ok(Operation { socket, state, buf, _event: event, done: false })
+    }
+
+    /// Waits for the operation to complete, returning the number of bytes transferred and the
+    /// buffer.
+    pub fn wait(mut self) -> io::Result<(usize, Vec<u8>)> {
+        let transferred = self.complete()?;
+        Ok((transferred, mem::take(&mut self.buf)))
+    }
+
+    fn complete(&mut self) -> io::Result<usize> {
+        self.done = true;
+        let mut transferred = 0;
+        let mut flags = 0;
+        let completed = unsafe {
+            ws2::WSAGetOverlappedResult(
+                self.socket.as_raw_socket(),
+                &mut self.state.overlapped as *mut c::OVERLAPPED as c::LPWSAOVERLAPPED,
+                &mut transferred,
+                c::TRUE,
+                &mut flags,
+            )
+        };
+        if completed == c::FALSE { Err(last_net_error()) } else { Ok(transferred as usize) }
+    }
+}
+
+impl Drop for Operation<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            // `CancelIo` only cancels the IO started by this thread, the others are waited for.
+            unsafe { c::CancelIo(self.socket.as_raw_socket() as c::HANDLE) };
+            let _ = self.complete();
+        }
+    }
+}
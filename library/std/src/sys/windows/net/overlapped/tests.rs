@@ -0,0 +1,28 @@
+use super::{check_supported, is_supported, Operation};
+use crate::io::ErrorKind;
+use crate::net::{TcpListener, TcpStream};
+use crate::sys_common::AsInner;
+
+#[test]
+fn unsupported_on_9x() {
+    assert_eq!(check_supported(false).unwrap_err().kind(), ErrorKind::Unsupported);
+}
+
+#[test]
+fn send_and_recv() {
+    if !is_supported() {
+        return;
+    }
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+
+    let recv = Operation::recv(server.as_inner().socket(), vec![0; 16]).unwrap();
+    let send = Operation::send(client.as_inner().socket(), b"overlapped".to_vec()).unwrap();
+    assert_eq!(send.wait().unwrap().0, 10);
+    let (received, buf) = recv.wait().unwrap();
+    assert_eq!(&buf[..received], b"overlapped");
+
+    // never completes, so dropping it has to cancel it
+    drop(Operation::recv(server.as_inner().socket(), vec![0; 16]).unwrap());
+}
@@ -0,0 +1,465 @@
+use super::{
+    adapters, bind, init, listen, listen_capped, max_backlog, ntop_v4, ntop_v6, only_v6_result,
+    peek_len, pton_v4, pton_v6, select, supports_ipv6, take_socket_error,
+    tcp_stream_from_raw_socket, winsock_version, FdSet, NonBlockingGuard, Socket, WakeupSocket,
+};
+use crate::io::{self, ErrorKind, Read, Write};
+use crate::net::{
+    Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, TcpListener, TcpStream, UdpSocket,
+};
+use crate::os::windows::io::{AsRawSocket, IntoRawSocket};
+use crate::panic::{self, AssertUnwindSafe};
+use crate::sync::Arc;
+use crate::sys::c;
+use crate::sys_common::{AsInner, IntoInner};
+use crate::thread;
+use crate::time::{Duration, Instant};
+use libc::c_int;
+
+#[test]
+fn wakeup_releases_blocked_read() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let wakeup = Arc::new(WakeupSocket::new().unwrap());
+
+    let waker = {
+        let wakeup = wakeup.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            wakeup.wake().unwrap();
+        })
+    };
+
+    let start = Instant::now();
+    let mut buf = [0u8; 16];
+    let err = socket
+        .as_inner()
+        .socket()
+        .read_interruptible(&mut buf, &wakeup, Some(Duration::from_secs(10)))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Interrupted);
+    assert!(start.elapsed() < Duration::from_secs(10));
+
+    waker.join().unwrap();
+}
+
+#[test]
+fn take_socket_error_connected() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let stream = TcpStream::connect(addr).unwrap();
+    let _accepted = listener.accept().unwrap();
+
+    assert!(take_socket_error(stream.as_inner().socket()).unwrap().is_none());
+    // the error is cleared after being read, and stays clear for a connected socket
+    assert!(take_socket_error(stream.as_inner().socket()).unwrap().is_none());
+}
+
+#[test]
+fn dropped_sockets_are_closed() {
+    init();
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+    // well above the socket limit of a stock 9x Winsock
+    for _ in 0..1000 {
+        let socket = Socket::new(&addr, c::SOCK_DGRAM).unwrap();
+        drop(socket);
+    }
+
+    let socket = Socket::new(&addr, c::SOCK_DGRAM).unwrap();
+    let raw = socket.into_raw();
+    drop(unsafe { Socket::from_raw(raw) });
+}
+
+#[test]
+fn accept_v4_peer_address() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let stream = TcpStream::connect(addr).unwrap();
+
+    let (_socket, peer) = listener.as_inner().socket().accept_v4().unwrap();
+    assert_ne!(peer.port(), 0);
+    assert_eq!(SocketAddr::V4(peer), stream.local_addr().unwrap());
+}
+
+#[test]
+fn peek_len_reports_pending_bytes() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+
+    client.write_all(b"hello").unwrap();
+    let socket = server.as_inner().socket();
+    let start = Instant::now();
+    while peek_len(socket).unwrap() < 5 {
+        assert!(start.elapsed() < Duration::from_secs(10));
+        thread::yield_now();
+    }
+    assert_eq!(peek_len(socket).unwrap(), 5);
+
+    let mut buf = [0u8; 5];
+    (&server).read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    assert_eq!(peek_len(socket).unwrap(), 0);
+}
+
+#[test]
+fn peek_then_read() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+
+    client.write_all(b"peekaboo").unwrap();
+
+    let mut peeked = [0u8; 8];
+    let mut n = 0;
+    while n < peeked.len() {
+        n = server.as_inner().socket().peek(&mut peeked).unwrap();
+    }
+
+    let mut read = [0u8; 8];
+    (&server).read_exact(&mut read).unwrap();
+    assert_eq!(peeked, read);
+    assert_eq!(&read, b"peekaboo");
+}
+
+#[test]
+fn nonblocking_guard_restores_mode_on_panic() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    let inner = socket.as_inner().socket();
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _guard = NonBlockingGuard::new(inner).unwrap();
+        assert!(inner.is_nonblocking());
+        panic!("oops");
+    }));
+    assert!(result.is_err());
+    assert!(!inner.is_nonblocking());
+
+    // a blocking socket times out, a non-blocking one would fail with `WouldBlock`
+    let mut buf = [0u8; 1];
+    assert_eq!(inner.read(&mut buf).unwrap_err().kind(), ErrorKind::TimedOut);
+}
+
+#[test]
+fn duplicates_keep_the_nonblocking_mode() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_nonblocking(true).unwrap();
+    let duplicate = socket.as_inner().socket().duplicate().unwrap();
+    assert!(duplicate.is_nonblocking());
+
+    // the guard must not switch the duplicate to blocking when it's done
+    drop(NonBlockingGuard::new(&duplicate).unwrap());
+    assert!(duplicate.is_nonblocking());
+    let mut buf = [0u8; 1];
+    assert_eq!(duplicate.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+}
+
+#[test]
+fn forced_wspiapi_resolves_localhost_like_the_system() {
+    use crate::net::ToSocketAddrs;
+
+    let system: Vec<_> = ("localhost", 80).to_socket_addrs().unwrap().collect();
+    let shim: Vec<_> = {
+        let _guards = c::mock::force_wspiapi();
+        ("localhost", 80).to_socket_addrs().unwrap().collect()
+    };
+
+    // the shim only knows IPv4
+    let system_v4: Vec<_> = system.into_iter().filter(SocketAddr::is_ipv4).collect();
+    assert!(!shim.is_empty());
+    assert_eq!(shim, system_v4);
+}
+
+#[test]
+fn select_on_more_than_fd_setsize_sockets() {
+    let sockets: Vec<_> = (0..100).map(|_| UdpSocket::bind("127.0.0.1:0").unwrap()).collect();
+    let raw = |socket: &UdpSocket| socket.as_inner().socket().as_raw_socket();
+
+    let mut read = FdSet::with_capacity(sockets.len());
+    for socket in &sockets {
+        assert!(read.insert(raw(socket)));
+    }
+    assert!(read.insert(raw(&sockets[0])), "duplicates are ignored");
+    assert_eq!(read.len(), 100);
+    assert!(!FdSet::with_capacity(0).insert(raw(&sockets[0])));
+
+    let target = &sockets[c::FD_SETSIZE + 10];
+    sockets[0].send_to(b"x", target.local_addr().unwrap()).unwrap();
+
+    match select(Some(&mut read), None, None, Some(Duration::from_secs(10))) {
+        Ok(count) => {
+            assert_eq!(count, 1);
+            assert_eq!(read.sockets(), [raw(target)]);
+        }
+        // 9x/ME may not support sets this large
+        Err(e) if !crate::sys::compat::version::is_windows_nt() => {
+            assert_eq!(e.kind(), ErrorKind::InvalidInput)
+        }
+        Err(e) => panic!("{}", e),
+    }
+
+    read.clear();
+    assert!(read.is_empty());
+    assert!(read.insert(raw(&sockets[1])));
+    let count = select(Some(&mut read), None, None, Some(Duration::from_millis(10))).unwrap();
+    assert_eq!(count, 0);
+}
+
+// `getsockopt`/`setsockopt` are shared with the other platforms, in `sys_common::net`.
+#[test]
+fn socket_option_round_trip() {
+    use crate::sys_common::net::{getsockopt, setsockopt};
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let socket = socket.as_inner().socket();
+
+    for size in [8192, 65536] {
+        setsockopt(socket, c::SOL_SOCKET, c::SO_RCVBUF, size as c_int).unwrap();
+        assert_eq!(getsockopt::<c_int>(socket, c::SOL_SOCKET, c::SO_RCVBUF).unwrap(), size);
+    }
+    setsockopt(socket, c::SOL_SOCKET, c::SO_BROADCAST, 1 as c_int).unwrap();
+    assert_ne!(getsockopt::<c_int>(socket, c::SOL_SOCKET, c::SO_BROADCAST).unwrap(), 0);
+    setsockopt(socket, c::SOL_SOCKET, c::SO_BROADCAST, 0 as c_int).unwrap();
+    assert_eq!(getsockopt::<c_int>(socket, c::SOL_SOCKET, c::SO_BROADCAST).unwrap(), 0);
+}
+
+// `sockaddr_in6` is what `SocketAddrV6` wraps, so this checks the conversions in `net::addr` and
+// `sys_common::net` against the layout winsock expects.
+#[test]
+fn sockaddr_in6_round_trip() {
+    assert_eq!(crate::mem::size_of::<c::sockaddr_in6>(), 28);
+
+    let addr = SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::LOCALHOST, 8080, 0, 3));
+    let (ptr, len) = (&addr).into_inner();
+    assert_eq!(len as usize, crate::mem::size_of::<c::sockaddr_in6>());
+    let raw = unsafe { *(ptr as *const c::sockaddr_in6) };
+    assert_eq!(raw.sin6_family as c_int, c::AF_INET6);
+    assert_eq!(raw.sin6_port.to_ne_bytes(), [0x1f, 0x90]);
+    assert_eq!(raw.sin6_addr.s6_addr, [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    assert_eq!(raw.sin6_scope_id, 3);
+
+    let mut storage: c::SOCKADDR_STORAGE_LH = unsafe { crate::mem::zeroed() };
+    unsafe { *(&mut storage as *mut _ as *mut c::sockaddr_in6) = raw };
+    assert_eq!(crate::sys_common::net::sockaddr_to_addr(&storage, len as usize).unwrap(), addr);
+}
+
+#[test]
+fn ipv6_support_is_stable() {
+    let supported = supports_ipv6();
+    assert_eq!(supports_ipv6(), supported);
+
+    let result = UdpSocket::bind((Ipv6Addr::LOCALHOST, 0));
+    if !supported {
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::Unsupported);
+    }
+}
+
+#[test]
+fn only_v6_before_vista() {
+    // what setsockopt fails with on XP and 2003
+    let missing = || Err(io::Error::from_raw_os_error(c::WSAENOPROTOOPT));
+    assert!(only_v6_result(true, missing()).is_ok());
+    assert_eq!(only_v6_result(false, missing()).unwrap_err().kind(), ErrorKind::Unsupported);
+    let other = Err(io::Error::from_raw_os_error(c::WSAENOTSOCK));
+    assert!(only_v6_result(true, other).is_err());
+
+    if supports_ipv6() {
+        // the option has to be set before binding, which `TcpListener` and `UdpSocket` do first
+        let addr = SocketAddr::from((Ipv6Addr::LOCALHOST, 0));
+        for ty in [c::SOCK_STREAM, c::SOCK_DGRAM] {
+            let socket = Socket::new(&addr, ty).unwrap();
+            socket.set_only_v6(true).unwrap();
+            assert!(socket.only_v6().unwrap());
+            match socket.set_only_v6(false) {
+                Ok(()) => assert!(!socket.only_v6().unwrap()),
+                Err(e) => assert_eq!(e.kind(), ErrorKind::Unsupported),
+            }
+        }
+
+        let listener = TcpListener::bind((Ipv6Addr::LOCALHOST, 0)).unwrap();
+        assert!(listener.only_v6().is_ok());
+    }
+}
+
+#[test]
+fn raw_socket_into_tcp_stream() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+
+    // what a C library would hand over
+    let raw = server.into_raw_socket();
+    let mut stream = unsafe { tcp_stream_from_raw_socket(raw as c::SOCKET) }.unwrap();
+
+    client.write_all(b"legacy").unwrap();
+    let mut buf = [0u8; 6];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"legacy");
+
+    // dropping the stream closes the socket, which the client sees as EOF
+    drop(stream);
+    assert_eq!(client.read(&mut buf).unwrap(), 0);
+
+    // a datagram socket is rejected, and stays open
+    let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let raw = udp.as_inner().socket().as_raw_socket();
+    let err = unsafe { tcp_stream_from_raw_socket(raw as c::SOCKET) }.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    udp.send_to(b"x", udp.local_addr().unwrap()).unwrap();
+}
+
+#[test]
+fn ttl_round_trip() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+    socket.set_ttl(64).unwrap();
+    assert_eq!(socket.ttl().unwrap(), 64);
+    socket.set_multicast_ttl_v4(64).unwrap();
+    assert_eq!(socket.multicast_ttl_v4().unwrap(), 64);
+
+    assert_eq!(socket.set_ttl(256).unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(socket.set_multicast_ttl_v4(256).unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(socket.ttl().unwrap(), 64);
+    assert_eq!(socket.multicast_ttl_v4().unwrap(), 64);
+}
+
+#[test]
+fn adapters_when_iphlpapi_is_present() {
+    let adapters = adapters().unwrap();
+    if !c::GetAdaptersInfo::available() {
+        assert!(adapters.is_empty());
+        return;
+    }
+
+    // `GetAdaptersInfo` leaves out loopback, so this needs a network adapter with an address
+    let local: Vec<_> = adapters.iter().flat_map(|adapter| adapter.addresses.iter()).collect();
+    if !local.is_empty() {
+        assert!(local.iter().all(|addr| !addr.is_unspecified()));
+        let socket = UdpSocket::bind((*local[0], 0)).unwrap();
+        assert_eq!(socket.local_addr().unwrap().ip(), *local[0]);
+    }
+    for adapter in &adapters {
+        assert!(!adapter.name.is_empty());
+    }
+}
+
+#[test]
+fn bind_v4_to_an_ephemeral_port() {
+    init();
+    let any_port = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0);
+    let socket = Socket::new(&SocketAddr::V4(any_port), c::SOCK_DGRAM).unwrap();
+    bind(&socket, &SocketAddr::V4(any_port)).unwrap();
+
+    let bound = super::local_addr(&socket).unwrap();
+    assert_eq!(bound.ip(), Ipv4Addr::LOCALHOST);
+    assert_ne!(bound.port(), 0);
+
+    let second = Socket::new(&bound, c::SOCK_DGRAM).unwrap();
+    let err = bind(&second, &bound).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AddrInUse);
+    assert_eq!(err.raw_os_error(), Some(c::WSAEADDRINUSE));
+
+    // std's sockets go through `bind` as well
+    let err = crate::net::UdpSocket::bind(bound).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(c::WSAEADDRINUSE));
+}
+
+#[test]
+fn large_backlogs_are_capped() {
+    init();
+    let any_port = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0);
+    let listen_on = |listen: &dyn Fn(&Socket) -> io::Result<()>| {
+        let socket = Socket::new(&SocketAddr::V4(any_port), c::SOCK_STREAM).unwrap();
+        bind(&socket, &SocketAddr::V4(any_port)).unwrap();
+        listen(&socket).unwrap();
+        let addr = super::local_addr(&socket).unwrap();
+        let client = TcpStream::connect(addr).unwrap();
+        let (_accepted, peer) = socket.accept_v4().unwrap();
+        assert_eq!(SocketAddr::V4(peer), client.local_addr().unwrap());
+    };
+
+    // what 9x allows at most
+    listen_on(&|socket| listen_capped(socket, 1000, 5));
+    listen_on(&|socket| listen(socket, c_int::MAX));
+    assert!(max_backlog() >= 5);
+}
+
+#[test]
+fn negotiated_winsock_version() {
+    init();
+    let version = winsock_version().unwrap();
+    assert!(version == (1, 1) || (version.0 == 2 && version.1 <= 2), "{:?}", version);
+}
+
+#[test]
+fn pton_and_ntop_without_winsock() {
+    let v4 = [
+        ("1.2.3.4", Some([1, 2, 3, 4])),
+        ("0.0.0.0", Some([0, 0, 0, 0])),
+        ("255.255.255.255", Some([255, 255, 255, 255])),
+        ("256.0.0.1", None),
+        ("010.0.0.1", None),
+        ("0x7f.0.0.1", None),
+        ("127.1", None),
+        ("1.2.3.4.", None),
+        ("1.2.3.4 ", None),
+        ("", None),
+    ];
+    for (src, octets) in v4 {
+        let addr = pton_v4(src);
+        assert_eq!(addr, octets.map(Ipv4Addr::from), "{:?}", src);
+        if let Some(addr) = addr {
+            assert_eq!(ntop_v4(&addr), src);
+        }
+    }
+
+    let v6 = [
+        ("::ffff:1.2.3.4", Some([0, 0, 0, 0, 0, 0xffff, 0x0102, 0x0304])),
+        ("2001:db8::1", Some([0x2001, 0xdb8, 0, 0, 0, 0, 0, 1])),
+        ("::", Some([0; 8])),
+        ("::1", Some([0, 0, 0, 0, 0, 0, 0, 1])),
+        ("fe80::1:0:0:1", Some([0xfe80, 0, 0, 0, 1, 0, 0, 1])),
+        ("2001:db8:0:0:1:0:0:1", Some([0x2001, 0xdb8, 0, 0, 1, 0, 0, 1])),
+        ("2001:db8::1::1", None),
+        ("2001:db8:::1", None),
+        ("12345::1", None),
+        ("fe80::1%1", None),
+        ("::ffff:1.2.3", None),
+        ("1:2:3:4:5:6:7:8:9", None),
+        ("", None),
+    ];
+    for (src, segments) in v6 {
+        let addr = pton_v6(src);
+        assert_eq!(addr, segments.map(Ipv6Addr::from), "{:?}", src);
+    }
+    let canonical = |src| ntop_v6(&pton_v6(src).unwrap());
+    assert_eq!(canonical("::ffff:1.2.3.4"), "::ffff:1.2.3.4");
+    assert_eq!(canonical("2001:DB8:0:0:0:0:0:1"), "2001:db8::1");
+    assert_eq!(canonical("2001:db8:0:0:1:0:0:1"), "2001:db8::1:0:0:1");
+    assert_eq!(canonical("::0.0.0.2"), "::2");
+    assert_eq!(canonical("::1.2.3.4"), "::102:304");
+    assert_eq!(canonical("::"), "::");
+    assert_eq!(canonical("1:0:2:3:4:5:6:7"), "1:0:2:3:4:5:6:7");
+    assert_eq!(canonical("1:2:3:4:5:6:7:0"), "1:2:3:4:5:6:7:0");
+    assert_eq!(canonical("1:0:0:2:3:0:0:0"), "1:0:0:2:3::");
+}
+
+#[test]
+fn failed_socket_calls_report_the_winsock_error() {
+    init();
+    let mut value: c_int = 0;
+    let mut len = crate::mem::size_of::<c_int>() as c_int;
+    let res = unsafe {
+        // a stale kernel32 error, which must not be what's reported
+        c::SetLastError(c::ERROR_FILE_NOT_FOUND);
+        c::getsockopt(
+            c::INVALID_SOCKET,
+            c::SOL_SOCKET,
+            c::SO_TYPE,
+            &mut value as *mut c_int as *mut _,
+            &mut len,
+        )
+    };
+    assert_eq!(res, c::SOCKET_ERROR);
+    assert_eq!(crate::sys::last_net_error().raw_os_error(), Some(c::WSAENOTSOCK));
+}
@@ -0,0 +1,217 @@
+//! A `Once` that doesn't need the thread parker.
+//!
+//! `sync::Once` parks the threads waiting for a running initialization, which needs either
+//! `WaitOnAddress` (Windows 8+) or NT keyed events (XP+). On older systems, `sync::Once` forwards
+//! to this implementation instead, which serializes callers on a lazily allocated `locks::Mutex`
+//! (and thus works with every `MutexKind`). The initializing thread holds the mutex while running
+//! the closure, so the waiting threads simply block on it.
+//!
+//! `sync::Once` only has its state word, and no destructor, so this keeps the mutex in a boxed
+//! `Shared` that lives only while threads are in `call`. The word then points to it, and is
+//! otherwise one of the `sync::Once` states. The pointer is handed out and reference counted under
+//! a global lock, and the last thread to leave stores the final state and frees the box.
+
+#[cfg(test)]
+mod tests;
+
+use crate::cell::UnsafeCell;
+use crate::ptr;
+use crate::sync::atomic::{
+    AtomicPtr, AtomicUsize,
+    Ordering::{Acquire, Relaxed, Release},
+};
+use crate::sys::c;
+use crate::sys::locks::Mutex;
+use crate::sys_common::mutex::StaticMutex;
+
+const INCOMPLETE: usize = 0x0;
+const RUNNING: usize = 0x1;
+const COMPLETE: usize = 0x2;
+const POISONED: usize = 0x3;
+
+// The states of `sync::Once`, which don't match the ones above.
+const STD_INCOMPLETE: usize = 0x0;
+const STD_POISONED: usize = 0x1;
+const STD_COMPLETE: usize = 0x3;
+
+/// Guards handing out and freeing the `Shared` a `sync::Once` points to.
+static SHARED_LOCK: StaticMutex = StaticMutex::new();
+
+/// Returns whether `sync::Once` has to use this implementation, because the thread parker
+/// can't block on this system.
+#[inline]
+pub fn is_needed() -> bool {
+    !c::WaitOnAddress::available() && !c::NtWaitForKeyedEvent::available()
+}
+
+/// Runs `init` if no previous call on the `sync::Once` whose state word is `state` has completed.
+///
+/// `init` is told whether an earlier initialization panicked, and returns whether the
+/// initialization is complete. Returning `false` poisons the `Once`, like panicking does.
+#[cold]
+#[track_caller]
+pub fn call<T>(state: &AtomicPtr<T>, ignore_poisoning: bool, init: &mut dyn FnMut(bool) -> bool) {
+    let shared = {
+        let _guard = unsafe { SHARED_LOCK.lock() };
+        let shared = match state.load(Acquire).addr() {
+            STD_COMPLETE => return,
+            std_state @ (STD_INCOMPLETE | STD_POISONED) => {
+                let sys_state = if std_state == STD_POISONED { POISONED } else { INCOMPLETE };
+                let shared = Box::into_raw(Box::new(Shared {
+                    once: Once::with_state(sys_state),
+                    users: UnsafeCell::new(0),
+                }));
+                state.store(shared.cast(), Relaxed);
+                shared
+            }
+            _ => state.load(Relaxed).cast::<Shared>(),
+        };
+        unsafe { *(*shared).users.get() += 1 };
+        shared
+    };
+    // Declared first, so it's dropped after `Once::call` released the mutex.
+    let _leave = Leave { state, shared };
+    unsafe { (*shared).once.call(ignore_poisoning, init) };
+}
+
+/// Returns whether a call on the `sync::Once` whose state word is `state` has completed.
+pub fn is_completed<T>(state: &AtomicPtr<T>) -> bool {
+    let _guard = unsafe { SHARED_LOCK.lock() };
+    match state.load(Acquire).addr() {
+        STD_COMPLETE => true,
+        STD_INCOMPLETE | STD_POISONED => false,
+        _ => unsafe { (*state.load(Relaxed).cast::<Shared>()).once.is_completed() },
+    }
+}
+
+struct Shared {
+    once: Once,
+    /// The threads in `call`, guarded by `SHARED_LOCK`.
+    users: UnsafeCell<usize>,
+}
+
+struct Leave<'a, T> {
+    state: &'a AtomicPtr<T>,
+    shared: *mut Shared,
+}
+
+impl<T> Drop for Leave<'_, T> {
+    fn drop(&mut self) {
+        let _guard = unsafe { SHARED_LOCK.lock() };
+        unsafe {
+            let users = &mut *(*self.shared).users.get();
+            *users -= 1;
+            if *users == 0 {
+                let std_state = match (*self.shared).once.state.load(Relaxed) {
+                    COMPLETE => STD_COMPLETE,
+                    POISONED => STD_POISONED,
+                    _ => STD_INCOMPLETE,
+                };
+                // Synchronizes with the `Acquire` load in `sync::Once::is_completed`, which doesn't
+                // take the lock.
+                self.state.store(ptr::invalid_mut(std_state), Release);
+                drop(Box::from_raw(self.shared));
+            }
+        }
+    }
+}
+
+struct Once {
+    state: AtomicUsize,
+    lock: AtomicPtr<Mutex>,
+}
+
+impl Once {
+    fn with_state(state: usize) -> Once {
+        Once { state: AtomicUsize::new(state), lock: AtomicPtr::new(ptr::null_mut()) }
+    }
+
+    #[inline]
+    fn is_completed(&self) -> bool {
+        // Synchronizes with the `Release` store at the end of `call`.
+        self.state.load(Acquire) == COMPLETE
+    }
+
+    #[track_caller]
+    fn call(&self, ignore_poisoning: bool, init: &mut dyn FnMut(bool) -> bool) {
+        if self.is_completed() {
+            return;
+        }
+
+        let lock = self.lock();
+        unsafe { lock.lock() };
+        // Declared first, so it's dropped (and the mutex released) after the state was updated.
+        let _unlock = Unlock(lock);
+
+        match self.state.load(Acquire) {
+            COMPLETE => {}
+            POISONED if !ignore_poisoning => {
+                // Panic to propagate the poison.
+                panic!("Once instance has previously been poisoned");
+            }
+            // Only the thread holding the mutex can see this.
+            RUNNING => panic!("Once instance is already being initialized by this thread"),
+            state => {
+                self.state.store(RUNNING, Relaxed);
+                // Poisons the `Once` if `init` panics.
+                let mut finish = Finish { state: &self.state, set_state_to: POISONED };
+                if init(state == POISONED) {
+                    finish.set_state_to = COMPLETE;
+                }
+            }
+        }
+    }
+
+    fn lock(&self) -> &Mutex {
+        let lock = self.lock.load(Acquire);
+        if !lock.is_null() {
+            return unsafe { &*lock };
+        }
+
+        let mut new = Box::new(Mutex::new());
+        unsafe { new.init() };
+        let new = Box::into_raw(new);
+        match self.lock.compare_exchange(ptr::null_mut(), new, Release, Acquire) {
+            Ok(_) => unsafe { &*new },
+            Err(lock) => {
+                // Lost the race to another thread, use theirs instead.
+                unsafe {
+                    (*new).destroy();
+                    drop(Box::from_raw(new));
+                    &*lock
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Once {
+    fn drop(&mut self) {
+        let lock = *self.lock.get_mut();
+        if !lock.is_null() {
+            unsafe {
+                (*lock).destroy();
+                drop(Box::from_raw(lock));
+            }
+        }
+    }
+}
+
+struct Unlock<'a>(&'a Mutex);
+
+impl Drop for Unlock<'_> {
+    fn drop(&mut self) {
+        unsafe { self.0.unlock() };
+    }
+}
+
+struct Finish<'a> {
+    state: &'a AtomicUsize,
+    set_state_to: usize,
+}
+
+impl Drop for Finish<'_> {
+    fn drop(&mut self) {
+        self.state.store(self.set_state_to, Release);
+    }
+}
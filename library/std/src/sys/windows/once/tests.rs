@@ -0,0 +1,80 @@
+use super::{call, is_completed, STD_COMPLETE, STD_INCOMPLETE, STD_POISONED};
+use crate::panic::{self, AssertUnwindSafe};
+use crate::ptr;
+use crate::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use crate::sync::{Arc, Barrier};
+use crate::thread;
+
+fn std_state(state: &AtomicPtr<u8>) -> usize {
+    state.load(Ordering::Acquire).addr()
+}
+
+#[test]
+fn racing_threads_run_init_once() {
+    const THREADS: usize = 32;
+
+    let state = Arc::new(AtomicPtr::new(ptr::invalid_mut(STD_INCOMPLETE)));
+    let runs = Arc::new(AtomicUsize::new(0));
+    let barrier = Arc::new(Barrier::new(THREADS));
+
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let (state, runs, barrier) = (state.clone(), runs.clone(), barrier.clone());
+            thread::spawn(move || {
+                barrier.wait();
+                call(&state, false, &mut |_| {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    // give the other threads time to pile up on the lock
+                    thread::yield_now();
+                    true
+                });
+                assert!(is_completed(&state));
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    // the last thread to leave stored the state `sync::Once` understands
+    assert_eq!(std_state(&state), STD_COMPLETE);
+}
+
+#[test]
+fn panic_poisons() {
+    let state = AtomicPtr::new(ptr::invalid_mut(STD_INCOMPLETE));
+
+    let res = panic::catch_unwind(AssertUnwindSafe(|| call(&state, false, &mut |_| panic!())));
+    assert!(res.is_err());
+    assert!(!is_completed(&state));
+    assert_eq!(std_state(&state), STD_POISONED);
+
+    // poisoning propagates
+    let res = panic::catch_unwind(AssertUnwindSafe(|| call(&state, false, &mut |_| true)));
+    assert!(res.is_err());
+    assert_eq!(std_state(&state), STD_POISONED);
+
+    // but can be ignored, which then reports it to the closure
+    let mut was_poisoned = false;
+    call(&state, true, &mut |poisoned| {
+        was_poisoned = poisoned;
+        true
+    });
+    assert!(was_poisoned);
+    assert!(is_completed(&state));
+    assert_eq!(std_state(&state), STD_COMPLETE);
+}
+
+#[test]
+fn is_completed_while_others_are_still_in_call() {
+    let state = AtomicPtr::new(ptr::invalid_mut(STD_INCOMPLETE));
+
+    call(&state, false, &mut |_| {
+        // the word points to the shared state while this thread is in `call`
+        assert_ne!(std_state(&state), STD_INCOMPLETE);
+        assert!(!is_completed(&state));
+        true
+    });
+    assert!(is_completed(&state));
+}
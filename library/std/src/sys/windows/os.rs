@@ -5,6 +5,8 @@
 #[cfg(test)]
 mod tests;
 
+pub mod toolhelp;
+
 use crate::os::windows::prelude::*;
 
 use crate::error::Error as StdError;
@@ -12,7 +14,7 @@
 use crate::fmt;
 use crate::io;
 use crate::os::windows::ffi::EncodeWide;
-use crate::path::{self, PathBuf};
+use crate::path::{self, Path, PathBuf};
 use crate::ptr;
 use crate::slice;
 use crate::sys::{c, cvt};
@@ -82,6 +84,9 @@ pub fn error_string(mut errnum: i32) -> String {
 pub struct Env {
     base: c::LPWCH,
     cur: c::LPWCH,
+    /// The block converted from `GetEnvironmentStringsA` on 9x/ME without unicows, which `base`
+    /// points into instead of a block from `GetEnvironmentStringsW`.
+    converted: Option<Vec<u16>>,
 }
 
 impl Iterator for Env {
@@ -121,19 +126,46 @@ fn next(&mut self) -> Option<(OsString, OsString)> {
 
 impl Drop for Env {
     fn drop(&mut self) {
-        unsafe {
-            c::FreeEnvironmentStringsW(self.base);
+        if self.converted.is_none() {
+            unsafe {
+                c::FreeEnvironmentStringsW(self.base);
+            }
         }
     }
 }
 
 pub fn env() -> Env {
+    if !crate::sys::compat::wide_apis_available() {
+        return env_ansi();
+    }
     unsafe {
         let ch = c::GetEnvironmentStringsW();
         if ch.is_null() {
             panic!("failure getting env string from OS: {}", io::Error::last_os_error());
         }
-        Env { base: ch, cur: ch }
+        Env { base: ch, cur: ch, converted: None }
+    }
+}
+
+/// `env` for 9x/ME without unicows. The whole block is converted up front, so that `Env` walks it
+/// just like the one from `GetEnvironmentStringsW`.
+fn env_ansi() -> Env {
+    unsafe {
+        let ch = c::GetEnvironmentStringsA();
+        if ch.is_null() {
+            panic!("failure getting env string from OS: {}", io::Error::last_os_error());
+        }
+        // the block ends with an empty string, keep both NULs
+        let mut len = 0;
+        while *ch.add(len) != 0 || *ch.add(len + 1) != 0 {
+            len += 1;
+        }
+        let block = slice::from_raw_parts(ch as *const u8, len + 2);
+        let mut converted: Vec<u16> = super::from_ansi(block).encode_wide().collect();
+        c::FreeEnvironmentStringsA(ch);
+
+        let base = converted.as_mut_ptr();
+        Env { base, cur: base, converted: Some(converted) }
     }
 }
 
@@ -237,6 +269,144 @@ pub fn current_exe() -> io::Result<PathBuf> {
     )
 }
 
+/// Returns the file name of the module (the executable or a DLL) containing `addr`.
+///
+/// This uses `GetModuleHandleExA` on XP+, and searches the loaded modules with ToolHelp32 on
+/// 9x/ME and 2000. On NT 4, which has neither, this always returns `None`.
+pub fn module_for_address(addr: *const ()) -> Option<OsString> {
+    if c::GetModuleHandleExA::available() {
+        let mut module = ptr::null_mut();
+        let found = unsafe {
+            c::GetModuleHandleExA(
+                c::GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS
+                    | c::GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+                addr as c::LPCSTR,
+                &mut module,
+            )
+        };
+        if found == 0 {
+            return None;
+        }
+        let path = module_file_name(module).ok()?;
+        return path.file_name().map(OsStr::to_os_string);
+    }
+
+    module_for_address_toolhelp(addr)
+}
+
+fn module_file_name(module: c::HMODULE) -> io::Result<PathBuf> {
+    if crate::sys::compat::wide_apis_available() {
+        return super::fill_utf16_buf(
+            |buf, sz| unsafe { c::GetModuleFileNameW(module, buf, sz) },
+            super::os2path,
+        );
+    }
+    let mut buf = [0u8; c::MAX_PATH];
+    let len = unsafe {
+        c::GetModuleFileNameA(module, buf.as_mut_ptr() as c::LPSTR, buf.len() as c::DWORD)
+    };
+    if len == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(PathBuf::from(super::from_ansi(&buf[..len as usize])))
+}
+
+fn module_for_address_toolhelp(addr: *const ()) -> Option<OsString> {
+    let modules = toolhelp::modules(0).ok()?;
+    let module = modules.into_iter().find(|module| module.contains(addr as usize))?;
+    Some(module.name().clone())
+}
+
+/// Flushes the instruction cache of the current process for `len` bytes at `base`, which is
+/// needed after writing code at runtime.
+///
+/// x86 mostly keeps its instruction cache coherent by itself, so this is close to a no-op there,
+/// but that's not guaranteed for every processor and configuration.
+pub fn flush_instruction_cache(base: *const (), len: usize) -> io::Result<()> {
+    cvt(unsafe { c::FlushInstructionCache(c::GetCurrentProcess(), base.cast(), len) }).map(drop)
+}
+
+/// Changes the protection of the pages in `len` bytes at `addr` to `flags` (`c::PAGE_*`),
+/// returning their previous protection.
+///
+/// 9x/ME differ from NT here:
+/// * There are no guard pages, so `PAGE_GUARD` is replaced by `PAGE_NOACCESS`. Accessing such a
+///   page raises an access violation instead of a one-time guard page exception, and the
+///   protection stays in place until it's changed back.
+/// * `PAGE_WRITECOMBINE` isn't supported, and is dropped as it's only a caching hint.
+/// * Pages in the shared arena (2-3 GB, which holds system DLLs and memory-mapped files) are
+///   shared by all processes, and changing their protection usually fails.
+pub fn protect(addr: *const (), len: usize, flags: u32) -> io::Result<u32> {
+    let mut flags = flags;
+    if !crate::sys::compat::version::is_windows_nt() {
+        if flags & c::PAGE_GUARD != 0 {
+            flags = flags & !(c::PAGE_GUARD | 0xff) | c::PAGE_NOACCESS;
+        }
+        flags &= !c::PAGE_WRITECOMBINE;
+    }
+
+    let mut old = 0;
+    let res = unsafe { c::VirtualProtect(addr as c::LPVOID, len, flags, &mut old) };
+    if res == 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(c::ERROR_INVALID_ADDRESS as i32) {
+            return Err(io::const_io_error!(
+                io::ErrorKind::InvalidInput,
+                "address range isn't entirely committed memory of this process",
+            ));
+        }
+        return Err(err);
+    }
+    Ok(old)
+}
+
+/// Adds `flags` (`c::SEM_*`) to the error mode of the process, returning the previous mode for
+/// `restore_error_mode`.
+///
+/// `SetErrorMode` can only replace the mode and there's no `GetErrorMode` before Vista, so this
+/// reads the current mode by setting it to 0 and then sets it again with `flags` added, as
+/// documented. The error mode is inherited by child processes.
+pub fn set_error_mode(flags: u32) -> u32 {
+    unsafe {
+        let previous = c::SetErrorMode(0);
+        c::SetErrorMode(previous | flags);
+        previous
+    }
+}
+
+/// Sets the error mode back to what `set_error_mode` returned.
+pub fn restore_error_mode(previous: u32) {
+    unsafe { c::SetErrorMode(previous) };
+}
+
+/// Returns the amount of physical memory available, in bytes.
+///
+/// Systems without `GlobalMemoryStatusEx` (9x/ME and NT 4) can't report more than 2 GB, so this
+/// is a lower bound there.
+pub fn available_memory() -> u64 {
+    unsafe {
+        if c::GlobalMemoryStatusEx::available() {
+            let mut status: c::MEMORYSTATUSEX = crate::mem::zeroed();
+            status.dwLength = crate::mem::size_of::<c::MEMORYSTATUSEX>() as c::DWORD;
+            if c::GlobalMemoryStatusEx(&mut status) != 0 {
+                return status.ullAvailPhys;
+            }
+        }
+
+        let mut status: c::MEMORYSTATUS = crate::mem::zeroed();
+        status.dwLength = crate::mem::size_of::<c::MEMORYSTATUS>() as c::DWORD;
+        c::GlobalMemoryStatus(&mut status);
+        legacy_memory_size(status.dwAvailPhys)
+    }
+}
+
+/// Converts a `GlobalMemoryStatus` size. Sizes of 2 GB and more don't fit the signed 32-bit
+/// values these are calculated with on older systems, and come out wrapped around, so saturate
+/// at 2 GB instead.
+fn legacy_memory_size(size: usize) -> u64 {
+    size.min(i32::MAX as usize) as u64
+}
+
 pub fn getcwd() -> io::Result<PathBuf> {
     super::fill_utf16_buf(|buf, sz| unsafe { c::GetCurrentDirectoryW(sz, buf) }, super::os2path)
 }
@@ -250,6 +420,9 @@ pub fn chdir(p: &path::Path) -> io::Result<()> {
 }
 
 pub fn getenv(k: &OsStr) -> Option<OsString> {
+    if !crate::sys::compat::wide_apis_available() {
+        return getenv_ansi(k);
+    }
     let k = to_u16s(k).ok()?;
     super::fill_utf16_buf(
         |buf, sz| unsafe { c::GetEnvironmentVariableW(k.as_ptr(), buf, sz) },
@@ -258,7 +431,33 @@ pub fn getenv(k: &OsStr) -> Option<OsString> {
     .ok()
 }
 
+/// `getenv` for 9x/ME without unicows.
+fn getenv_ansi(k: &OsStr) -> Option<OsString> {
+    let k = super::to_ansi(k).ok()?;
+    let mut buf = vec![0u8; 512];
+    loop {
+        let n = unsafe {
+            c::SetLastError(0);
+            c::GetEnvironmentVariableA(
+                k.as_ptr() as c::LPCSTR,
+                buf.as_mut_ptr() as c::LPSTR,
+                buf.len() as c::DWORD,
+            )
+        };
+        match n as usize {
+            // either not set, or set to an empty string
+            0 if unsafe { c::GetLastError() } != 0 => return None,
+            // too small, `n` is the required size including the NUL
+            n if n > buf.len() => buf.resize(n, 0),
+            n => return Some(super::from_ansi(&buf[..n])),
+        }
+    }
+}
+
 pub fn setenv(k: &OsStr, v: &OsStr) -> io::Result<()> {
+    if !crate::sys::compat::wide_apis_available() {
+        return setenv_ansi(k, Some(v));
+    }
     let k = to_u16s(k)?;
     let v = to_u16s(v)?;
 
@@ -266,14 +465,75 @@ pub fn setenv(k: &OsStr, v: &OsStr) -> io::Result<()> {
 }
 
 pub fn unsetenv(n: &OsStr) -> io::Result<()> {
+    if !crate::sys::compat::wide_apis_available() {
+        return setenv_ansi(n, None);
+    }
     let v = to_u16s(n)?;
     cvt(unsafe { c::SetEnvironmentVariableW(v.as_ptr(), ptr::null()) }).map(drop)
 }
 
+/// `setenv` and `unsetenv` (with `None`) for 9x/ME without unicows.
+fn setenv_ansi(k: &OsStr, v: Option<&OsStr>) -> io::Result<()> {
+    let k = super::to_ansi(k)?;
+    let v = v.map(super::to_ansi).transpose()?;
+    let v_ptr = v.as_ref().map_or(ptr::null(), |v| v.as_ptr() as c::LPCSTR);
+    cvt(unsafe { c::SetEnvironmentVariableA(k.as_ptr() as c::LPCSTR, v_ptr) }).map(drop)
+}
+
 pub fn temp_dir() -> PathBuf {
+    if !crate::sys::compat::wide_apis_available() {
+        return temp_dir_ansi().unwrap();
+    }
     super::fill_utf16_buf(|buf, sz| unsafe { c::GetTempPath2W(sz, buf) }, super::os2path).unwrap()
 }
 
+/// `temp_dir` for 9x/ME without unicows.
+fn temp_dir_ansi() -> io::Result<PathBuf> {
+    let mut buf = vec![0u8; c::MAX_PATH];
+    loop {
+        let n = unsafe { c::GetTempPathA(buf.len() as c::DWORD, buf.as_mut_ptr() as c::LPSTR) };
+        match n as usize {
+            0 => return Err(io::Error::last_os_error()),
+            // too small, `n` is the required size including the NUL
+            n if n > buf.len() => buf.resize(n, 0),
+            n => return Ok(PathBuf::from(super::from_ansi(&buf[..n]))),
+        }
+    }
+}
+
+/// Creates an empty file with a unique name in `dir` and returns its path.
+///
+/// The file name consists of the first three characters of `prefix`, a hexadecimal number and the
+/// `.tmp` extension. Creating the file reserves the name, so callers can rely on no one else
+/// getting the same path.
+pub fn temp_file(dir: &Path, prefix: &OsStr) -> io::Result<PathBuf> {
+    if !crate::sys::compat::wide_apis_available() {
+        return temp_file_ansi(dir, prefix);
+    }
+    let dir = to_u16s(dir)?;
+    let prefix = to_u16s(prefix)?;
+    let mut buf = [0u16; c::MAX_PATH];
+    cvt(unsafe { c::GetTempFileNameW(dir.as_ptr(), prefix.as_ptr(), 0, buf.as_mut_ptr()) })?;
+    Ok(PathBuf::from(OsString::from_wide(super::truncate_utf16_at_nul(&buf))))
+}
+
+/// `temp_file` for 9x/ME without unicows.
+fn temp_file_ansi(dir: &Path, prefix: &OsStr) -> io::Result<PathBuf> {
+    let dir = super::to_ansi(dir)?;
+    let prefix = super::to_ansi(prefix)?;
+    let mut buf = [0u8; c::MAX_PATH];
+    cvt(unsafe {
+        c::GetTempFileNameA(
+            dir.as_ptr() as c::LPCSTR,
+            prefix.as_ptr() as c::LPCSTR,
+            0,
+            buf.as_mut_ptr() as c::LPSTR,
+        )
+    })?;
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Ok(PathBuf::from(super::from_ansi(&buf[..len])))
+}
+
 #[cfg(not(target_vendor = "uwp"))]
 fn home_dir_crt() -> Option<PathBuf> {
     if !c::GetUserProfileDirectoryW::available() || !c::OpenProcessToken::available() {
@@ -287,7 +547,7 @@ fn home_dir_crt() -> Option<PathBuf> {
         // we can simplify this code. See #90144 for details.
         use crate::sys::handle::Handle;
 
-        let me = c::GetCurrentProcess();
+        let me = super::process::current_process();
         let mut token = ptr::null_mut();
         if c::OpenProcessToken(me, c::TOKEN_READ, &mut token) == 0 {
             return None;
@@ -324,5 +584,5 @@ pub fn exit(code: i32) -> ! {
 }
 
 pub fn getpid() -> u32 {
-    unsafe { c::GetCurrentProcessId() as u32 }
+    super::process::current_pid()
 }
@@ -1,3 +1,5 @@
+use crate::ffi::OsStr;
+use crate::fs;
 use crate::io::Error;
 use crate::sys::c;
 
@@ -11,3 +13,118 @@ fn ntstatus_error() {
             .contains("FormatMessageW() returned error")
     );
 }
+
+#[test]
+fn temp_file_is_created() {
+    let dir = super::temp_dir();
+    let path = super::temp_file(&dir, OsStr::new("rst")).unwrap();
+    assert_eq!(path.parent(), Some(&*dir));
+    assert!(path.file_name().unwrap().to_str().unwrap().starts_with("rst"));
+    assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+    fs::remove_file(&path).unwrap();
+}
+
+// tests the 9x/ME code path, which works on NT as well
+#[test]
+fn temp_file_ansi_round_trips() {
+    let dir = super::temp_dir_ansi().unwrap();
+    assert!(dir.is_dir());
+    let path = super::temp_file_ansi(&dir, OsStr::new("rst")).unwrap();
+    assert!(path.starts_with(&dir));
+    assert!(fs::metadata(&path).unwrap().is_file());
+    fs::remove_file(&path).unwrap();
+}
+
+// the 9x/ME code paths again, which see the same environment on NT
+#[test]
+fn env_ansi_round_trips() {
+    let key = OsStr::new("RUST9X_ENV_ANSI_TEST");
+
+    super::setenv_ansi(key, Some(OsStr::new("ansi value"))).unwrap();
+    assert_eq!(super::getenv(key).as_deref(), Some(OsStr::new("ansi value")));
+    assert_eq!(super::getenv_ansi(key).as_deref(), Some(OsStr::new("ansi value")));
+    assert!(super::env_ansi().any(|(k, v)| k == key && v == "ansi value"));
+
+    super::setenv_ansi(key, Some(OsStr::new(""))).unwrap();
+    assert_eq!(super::getenv_ansi(key).as_deref(), Some(OsStr::new("")));
+
+    super::setenv_ansi(key, None).unwrap();
+    assert_eq!(super::getenv(key), None);
+    assert_eq!(super::getenv_ansi(key), None);
+    assert!(!super::env_ansi().any(|(k, _)| k == key));
+}
+
+#[test]
+fn module_for_address_of_own_function() {
+    let exe = crate::env::current_exe().unwrap();
+    let name = super::module_for_address(module_for_address_of_own_function as *const ()).unwrap();
+    assert!(name.eq_ignore_ascii_case(exe.file_name().unwrap()), "{:?}", name);
+
+    assert_eq!(super::module_for_address(crate::ptr::null()), None);
+}
+
+#[test]
+fn flush_instruction_cache_of_small_region() {
+    let code = [0xc3u8; 16]; // ret
+    super::flush_instruction_cache(code.as_ptr().cast(), code.len()).unwrap();
+    let this = flush_instruction_cache_of_small_region as *const ();
+    super::flush_instruction_cache(this, 1).unwrap();
+}
+
+#[test]
+fn protect_committed_page() {
+    const PAGE: usize = 4096;
+    unsafe {
+        let page = c::VirtualAlloc(
+            crate::ptr::null_mut(),
+            PAGE,
+            c::MEM_RESERVE | c::MEM_COMMIT,
+            c::PAGE_READWRITE,
+        );
+        assert!(!page.is_null());
+        page.cast::<u8>().write(1);
+
+        let old = super::protect(page.cast(), PAGE, c::PAGE_READONLY).unwrap();
+        assert_eq!(old, c::PAGE_READWRITE);
+        assert_eq!(page.cast::<u8>().read(), 1);
+        let old = super::protect(page.cast(), PAGE, c::PAGE_READWRITE).unwrap();
+        assert_eq!(old, c::PAGE_READONLY);
+        page.cast::<u8>().write(2);
+
+        assert!(c::VirtualFree(page, 0, c::MEM_RELEASE) != 0);
+        // not committed anymore, 9x/ME report this differently
+        let err = super::protect(page.cast(), PAGE, c::PAGE_READONLY).unwrap_err();
+        if crate::sys::compat::version::is_windows_nt() {
+            assert_eq!(err.kind(), crate::io::ErrorKind::InvalidInput);
+        }
+    }
+}
+
+#[test]
+fn available_memory_is_reported() {
+    assert!(super::available_memory() > 0);
+}
+
+#[test]
+fn legacy_memory_size_saturates() {
+    use super::legacy_memory_size;
+
+    assert_eq!(legacy_memory_size(0), 0);
+    assert_eq!(legacy_memory_size(64 << 20), 64 << 20);
+    assert_eq!(legacy_memory_size(0x7fff_ffff), 0x7fff_ffff);
+    // 3 GB, or -1 GB as a signed value
+    assert_eq!(legacy_memory_size(0xc000_0000), 0x7fff_ffff);
+    // the overflow marker
+    assert_eq!(legacy_memory_size(usize::MAX), 0x7fff_ffff);
+}
+
+#[test]
+fn error_mode_is_added_and_restored() {
+    use super::{restore_error_mode, set_error_mode};
+
+    let previous = set_error_mode(c::SEM_NOGPFAULTERRORBOX);
+    let current = set_error_mode(0);
+    assert_eq!(current, previous | c::SEM_NOGPFAULTERRORBOX);
+    restore_error_mode(previous);
+    assert_eq!(set_error_mode(0), previous);
+}
@@ -0,0 +1,135 @@
+//! Process and module enumeration with ToolHelp32.
+//!
+//! `CreateToolhelp32Snapshot` is available on 9x/ME and NT 5+, which makes it the one way to do
+//! this on 9x/ME, where psapi (`EnumProcessModules`) doesn't exist. NT 4 only has psapi, so all of
+//! these fail with `Unsupported` there.
+
+#[cfg(test)]
+mod tests;
+
+use crate::ffi::OsString;
+use crate::io;
+use crate::mem;
+use crate::os::windows::io::{AsRawHandle, FromRawHandle};
+use crate::path::PathBuf;
+use crate::sys::c;
+use crate::sys::handle::Handle;
+
+/// A process, as seen when the snapshot was taken.
+pub struct ProcessEntry {
+    pid: u32,
+    parent_pid: u32,
+    threads: u32,
+    exe_file: OsString,
+}
+
+impl ProcessEntry {
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    pub fn parent_pid(&self) -> u32 {
+        self.parent_pid
+    }
+
+    pub fn threads(&self) -> u32 {
+        self.threads
+    }
+
+    /// The executable's file name. On 9x/ME, this is its full path instead.
+    pub fn exe_file(&self) -> &OsString {
+        &self.exe_file
+    }
+}
+
+/// A module loaded into a process.
+pub struct ModuleEntry {
+    base: usize,
+    size: usize,
+    name: OsString,
+    path: PathBuf,
+}
+
+impl ModuleEntry {
+    /// The address the module is loaded at, in the address space of its process.
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether `addr` lies within the module's image.
+    pub fn contains(&self, addr: usize) -> bool {
+        (self.base..self.base + self.size).contains(&addr)
+    }
+
+    /// The module's file name.
+    pub fn name(&self) -> &OsString {
+        &self.name
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+/// Lists all processes in the system.
+pub fn processes() -> io::Result<Vec<ProcessEntry>> {
+    let snapshot = snapshot(c::TH32CS_SNAPPROCESS, 0)?;
+    let mut entry: c::PROCESSENTRY32 = unsafe { mem::zeroed() };
+    entry.dwSize = mem::size_of::<c::PROCESSENTRY32>() as c::DWORD;
+    walk(&snapshot, &mut entry, c::Process32First, c::Process32Next, |entry| ProcessEntry {
+        pid: entry.th32ProcessID,
+        parent_pid: entry.th32ParentProcessID,
+        threads: entry.cntThreads,
+        exe_file: super::super::from_ansi_buf(&entry.szExeFile),
+    })
+}
+
+/// Lists the modules loaded into the process `pid`, or into the current process if it's 0.
+pub fn modules(pid: u32) -> io::Result<Vec<ModuleEntry>> {
+    let snapshot = snapshot(c::TH32CS_SNAPMODULE, pid)?;
+    let mut entry: c::MODULEENTRY32 = unsafe { mem::zeroed() };
+    entry.dwSize = mem::size_of::<c::MODULEENTRY32>() as c::DWORD;
+    walk(&snapshot, &mut entry, c::Module32First, c::Module32Next, |entry| ModuleEntry {
+        base: entry.modBaseAddr as usize,
+        size: entry.modBaseSize as usize,
+        name: super::super::from_ansi_buf(&entry.szModule),
+        path: PathBuf::from(super::super::from_ansi_buf(&entry.szExePath)),
+    })
+}
+
+fn snapshot(flags: c::DWORD, pid: u32) -> io::Result<Handle> {
+    if !c::CreateToolhelp32Snapshot::available() {
+        return Err(io::const_io_error!(
+            io::ErrorKind::Unsupported,
+            "ToolHelp32 is not supported on this version of Windows",
+        ));
+    }
+    let snapshot = unsafe { c::CreateToolhelp32Snapshot(flags, pid) };
+    if snapshot == c::INVALID_HANDLE_VALUE {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { Handle::from_raw_handle(snapshot) })
+}
+
+fn walk<E, T>(
+    snapshot: &Handle,
+    entry: &mut E,
+    first: unsafe fn(c::HANDLE, *mut E) -> c::BOOL,
+    next: unsafe fn(c::HANDLE, *mut E) -> c::BOOL,
+    mut map: impl FnMut(&E) -> T,
+) -> io::Result<Vec<T>> {
+    let mut entries = Vec::new();
+    let mut more = unsafe { first(snapshot.as_raw_handle(), entry) };
+    while more != 0 {
+        entries.push(map(entry));
+        more = unsafe { next(snapshot.as_raw_handle(), entry) };
+    }
+    match unsafe { c::GetLastError() } {
+        c::ERROR_NO_MORE_FILES => Ok(entries),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
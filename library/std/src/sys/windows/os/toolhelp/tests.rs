@@ -0,0 +1,15 @@
+use super::{modules, processes};
+use crate::env;
+use crate::process;
+
+#[test]
+fn current_process_is_listed() {
+    let pid = process::id();
+    let processes = processes().unwrap();
+    let current = processes.iter().find(|p| p.pid() == pid).unwrap();
+    assert!(current.threads() >= 1);
+
+    let exe = env::current_exe().unwrap();
+    let modules = modules(0).unwrap();
+    assert!(modules.iter().any(|m| m.name().eq_ignore_ascii_case(exe.file_name().unwrap())));
+}
@@ -1,5 +1,6 @@
 use crate::os::windows::prelude::*;
 
+use crate::cmp;
 use crate::ffi::OsStr;
 use crate::io::{self, IoSlice, IoSliceMut};
 use crate::mem;
@@ -15,6 +16,9 @@
 use crate::sys::hashmap_random_keys;
 use crate::sys_common::IntoInner;
 
+#[cfg(test)]
+mod tests;
+
 ////////////////////////////////////////////////////////////////////////////////
 // Anonymous pipes
 ////////////////////////////////////////////////////////////////////////////////
@@ -122,7 +126,7 @@ pub fn anon_pipe(ours_readable: bool, their_handle_inheritable: bool) -> io::Res
             tries += 1;
             name = format!(
                 r"\\.\pipe\__rust_anonymous_pipe1__.{}.{}",
-                c::GetCurrentProcessId(),
+                crate::sys::process::current_pid(),
                 random_number()
             );
             let wide_name = OsStr::new(&name).encode_wide().chain(Some(0)).collect::<Vec<_>>();
@@ -258,6 +262,47 @@ pub fn is_write_vectored(&self) -> bool {
     }
 }
 
+/// Returns the number of bytes that can be read from the pipe `handle` without blocking.
+///
+/// Unlike overlapped IO, this works with the anonymous pipes of 9x/ME too. A pipe whose write end
+/// was closed has 0 bytes available.
+pub fn peek(handle: &Handle) -> io::Result<usize> {
+    Ok(peek_available(handle)?.unwrap_or(0))
+}
+
+/// Reads from the pipe `handle` only if data is available, failing with `WouldBlock` otherwise.
+/// Returns `Ok(0)` at the end of the pipe, like `read`.
+pub fn read_nonblocking(handle: &Handle, buf: &mut [u8]) -> io::Result<usize> {
+    match peek_available(handle)? {
+        None => Ok(0),
+        Some(0) => {
+            Err(io::const_io_error!(io::ErrorKind::WouldBlock, "no data available in the pipe"))
+        }
+        // never asks for more than what's there, so this doesn't block
+        Some(available) => handle.read(&mut buf[..cmp::min(buf.len(), available)]),
+    }
+}
+
+/// The number of bytes available in the pipe, or `None` if the write end was closed.
+fn peek_available(handle: &Handle) -> io::Result<Option<usize>> {
+    let mut available = 0;
+    let res = cvt(unsafe {
+        c::PeekNamedPipe(
+            handle.as_raw_handle(),
+            ptr::null_mut(),
+            0,
+            ptr::null_mut(),
+            &mut available,
+            ptr::null_mut(),
+        )
+    });
+    match res {
+        Ok(_) => Ok(Some(available as usize)),
+        Err(e) if e.raw_os_error() == Some(c::ERROR_BROKEN_PIPE as i32) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
 pub fn read2(p1: AnonPipe, v1: &mut Vec<u8>, p2: AnonPipe, v2: &mut Vec<u8>) -> io::Result<()> {
     let p1 = p1.into_handle();
     let p2 = p2.into_handle();
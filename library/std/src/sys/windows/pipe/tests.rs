@@ -0,0 +1,24 @@
+use super::{anon_pipe, peek, read_nonblocking};
+use crate::io::ErrorKind;
+
+#[test]
+fn peek_then_read_without_blocking() {
+    let pipes = anon_pipe(true, false).unwrap();
+    let ours = pipes.ours.handle();
+    assert_eq!(peek(ours).unwrap(), 0);
+    assert_eq!(read_nonblocking(ours, &mut [0; 4]).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+    assert_eq!(pipes.theirs.write(b"hello").unwrap(), 5);
+    assert_eq!(peek(ours).unwrap(), 5);
+    let mut buf = [0; 3];
+    assert_eq!(read_nonblocking(ours, &mut buf).unwrap(), 3);
+    assert_eq!(&buf, b"hel");
+    assert_eq!(peek(ours).unwrap(), 2);
+    let mut buf = [0; 8];
+    assert_eq!(read_nonblocking(ours, &mut buf).unwrap(), 2);
+    assert_eq!(&buf[..2], b"lo");
+
+    drop(pipes.theirs);
+    assert_eq!(peek(ours).unwrap(), 0);
+    assert_eq!(read_nonblocking(ours, &mut buf).unwrap(), 0);
+}
@@ -17,11 +17,12 @@
 use crate::os::windows::io::{AsRawHandle, FromRawHandle, IntoRawHandle};
 use crate::path::{Path, PathBuf};
 use crate::ptr;
+use crate::sync::atomic::{AtomicU32, Ordering};
 use crate::sys::c;
 use crate::sys::c::NonZeroDWORD;
 use crate::sys::cvt;
 use crate::sys::fs::{File, OpenOptions};
-use crate::sys::handle::Handle;
+use crate::sys::handle::{self, Handle, WaitOutcome};
 use crate::sys::path;
 use crate::sys::pipe::{self, AnonPipe};
 use crate::sys::stdio;
@@ -301,7 +302,16 @@ pub fn spawn(
             flags |= c::DETACHED_PROCESS | c::CREATE_NEW_PROCESS_GROUP;
         }
 
-        let (envp, _data) = make_envp(maybe_env)?;
+        let (mut envp, data) = make_envp(maybe_env)?;
+        // Without `CREATE_UNICODE_ENVIRONMENT` (see above), the environment block has to be in
+        // the ANSI code page. The block stays sorted, as `EnvKey`s are uppercased there.
+        let _data = if !envp.is_null() && !crate::sys::compat::version::is_windows_nt() {
+            let ansi = super::wide_to_ansi(&data)?;
+            envp = ansi.as_ptr() as *mut c_void;
+            (data, ansi)
+        } else {
+            (data, Vec::new())
+        };
         let (dirp, _data) = make_dirp(self.cwd.as_ref())?;
         let mut pi = zeroed_process_information();
 
@@ -586,6 +596,31 @@ fn from(file: File) -> Stdio {
 // Processes
 ////////////////////////////////////////////////////////////////////////////////
 
+/// The id of the current process, cached by `init`, or 0 before that.
+static CURRENT_PID: AtomicU32 = AtomicU32::new(0);
+
+pub fn init() {
+    CURRENT_PID.store(unsafe { c::GetCurrentProcessId() }, Ordering::Relaxed);
+}
+
+/// Returns the id of the current process.
+pub fn current_pid() -> u32 {
+    match CURRENT_PID.load(Ordering::Relaxed) {
+        // `init` doesn't run when std isn't linked into the executable
+        0 => unsafe { c::GetCurrentProcessId() },
+        pid => pid,
+    }
+}
+
+/// Returns the pseudo-handle of the current process.
+///
+/// This is not a real handle (NT uses `-1`, 9x/ME use `0x7fffffff`) and must never be passed to
+/// `CloseHandle`, so don't wrap it in a `Handle`. There's nothing to cache either, it's valid for
+/// the whole lifetime of the process.
+pub fn current_process() -> c::HANDLE {
+    unsafe { c::GetCurrentProcess() }
+}
+
 /// A value representing a child process.
 ///
 /// The lifetime of this value is linked to the lifetime of the actual
@@ -626,10 +661,7 @@ pub fn id(&self) -> u32 {
 
     pub fn wait(&mut self) -> io::Result<ExitStatus> {
         unsafe {
-            let res = c::WaitForSingleObject(self.handle.as_raw_handle(), c::INFINITE);
-            if res != c::WAIT_OBJECT_0 {
-                return Err(Error::last_os_error());
-            }
+            handle::wait(self.handle.as_raw_handle(), c::INFINITE)?;
             let mut status = 0;
             cvt(c::GetExitCodeProcess(self.handle.as_raw_handle(), &mut status))?;
             Ok(ExitStatus(status))
@@ -637,13 +669,18 @@ pub fn wait(&mut self) -> io::Result<ExitStatus> {
     }
 
     pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        self.wait_timeout(0)
+    }
+
+    /// Waits up to `timeout` milliseconds for the process to exit, returning `None` if it is
+    /// still running afterwards.
+    pub fn wait_timeout(&mut self, timeout: c::DWORD) -> io::Result<Option<ExitStatus>> {
         unsafe {
-            match c::WaitForSingleObject(self.handle.as_raw_handle(), 0) {
-                c::WAIT_OBJECT_0 => {}
-                c::WAIT_TIMEOUT => {
-                    return Ok(None);
-                }
-                _ => return Err(io::Error::last_os_error()),
+            // Whether the process has exited is decided by the handle being signaled alone.
+            // `GetExitCodeProcess` reports `STILL_ACTIVE` (259) for running processes, which is
+            // indistinguishable from a process that exited with code 259.
+            if handle::wait(self.handle.as_raw_handle(), timeout)? == WaitOutcome::TimedOut {
+                return Ok(None);
             }
             let mut status = 0;
             cvt(c::GetExitCodeProcess(self.handle.as_raw_handle(), &mut status))?;
@@ -673,6 +710,11 @@ pub fn exit_ok(&self) -> Result<(), ExitStatusError> {
     pub fn code(&self) -> Option<i32> {
         Some(self.0 as i32)
     }
+
+    /// The full `DWORD` exit code as returned by `GetExitCodeProcess`.
+    pub fn raw(&self) -> c::DWORD {
+        self.0
+    }
 }
 
 /// Converts a raw `c::DWORD` to a type-safe `ExitStatus` by wrapping it without copying.
@@ -130,6 +130,67 @@ fn windows_env_unicode_case() {
     }
 }
 
+#[test]
+fn child_sees_custom_env() {
+    let output = Command::new("cmd")
+        .args(&["/c", "echo %RUST9X_TEST_VAR%"])
+        .env("RUST9X_TEST_VAR", "hello from the parent")
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "hello from the parent");
+
+    // a cleared environment reaches the child as an empty block, not as the inherited one
+    let output = Command::new("cmd")
+        .args(&["/c", "set"])
+        .env_clear()
+        .env("RUST9X_TEST_VAR", "only")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("RUST9X_TEST_VAR=only"));
+    assert!(!stdout.to_uppercase().contains("PATH="));
+}
+
+#[test]
+fn current_pid_is_stable() {
+    use super::current_pid;
+
+    let pid = current_pid();
+    assert_ne!(pid, 0);
+    assert_eq!(pid, current_pid());
+    assert_eq!(pid, crate::process::id());
+}
+
+#[test]
+fn exit_code_keeps_all_bits() {
+    use crate::os::windows::process::ExitStatusExt;
+
+    let status = Command::new("cmd").args(&["/c", "exit", "1073807364"]).status().unwrap();
+    assert_eq!(status.into_raw(), 0x40010004);
+    assert_eq!(status.code(), Some(0x40010004));
+}
+
+#[test]
+fn try_wait_running_then_exited() {
+    use crate::thread;
+    use crate::time::{Duration, Instant};
+
+    let mut child =
+        Command::new("cmd").args(&["/c", "ping -n 2 127.0.0.1 > nul & exit 259"]).spawn().unwrap();
+    assert!(child.try_wait().unwrap().is_none());
+
+    // an exit code of `STILL_ACTIVE` must not be mistaken for a running process
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        assert!(start.elapsed() < Duration::from_secs(60));
+        thread::sleep(Duration::from_millis(50));
+    };
+    assert_eq!(status.code(), Some(259));
+}
+
 // UWP applications run in a restricted environment which means this test may not work.
 #[cfg(not(target_vendor = "uwp"))]
 #[test]
@@ -1,14 +1,36 @@
+#[cfg(test)]
+mod tests;
+
 use crate::io;
 use crate::mem;
 use crate::sys::c;
 
+/// Where `hashmap_random_keys` gets its randomness from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RngSource {
+    /// Vista+
+    BCryptGenRandom,
+    /// `SystemFunction036` from advapi32, what `BCryptGenRandom` falls back to
+    RtlGenRandom,
+    /// Neither is available, see `weak_keys`
+    Weak,
+}
+
+pub fn rng_source() -> RngSource {
+    if c::BCryptGenRandom::available() {
+        RngSource::BCryptGenRandom
+    } else if c::SystemFunction036::available() {
+        RngSource::RtlGenRandom
+    } else {
+        RngSource::Weak
+    }
+}
+
 pub fn hashmap_random_keys() -> (u64, u64) {
     use crate::ptr;
 
-    let mut v;
-
-    if c::BCryptGenRandom::available() || c::SystemFunction036::available() {
-        v = (0, 0);
+    if rng_source() != RngSource::Weak {
+        let mut v = (0, 0);
 
         let ret = unsafe {
             c::BCryptGenRandom(
@@ -25,17 +47,30 @@ pub fn hashmap_random_keys() -> (u64, u64) {
         return v;
     }
 
+    weak_keys(crate::sys::cpu::read_tsc())
+}
+
+/// Keys for systems without a proper RNG, from the clocks and the thread id.
+///
+/// `tsc` is mixed in if the CPU has a time stamp counter, otherwise `QueryPerformanceCounter` is
+/// used, as `rdtsc` faults on a 486.
+fn weak_keys(tsc: Option<u64>) -> (u64, u64) {
     unsafe {
         let tickCount = c::GetTickCount();
         let id = c::GetCurrentThreadId();
         let mut file_time: c::FILETIME = crate::mem::zeroed();
         c::GetSystemTimeAsFileTime(&mut file_time as *mut _);
 
-        v = (
-            (file_time.dwHighDateTime as u64) << 32 | tickCount as u64,
-            (id as u64) << 32 | file_time.dwLowDateTime as u64,
+        let counter = tsc.unwrap_or_else(|| {
+            let mut qpc: c::LARGE_INTEGER = 0;
+            // leaves it at 0 if there's no performance counter
+            c::QueryPerformanceCounter(&mut qpc);
+            qpc as u64
+        });
+
+        (
+            ((file_time.dwHighDateTime as u64) << 32 | tickCount as u64) ^ counter,
+            ((id as u64) << 32 | file_time.dwLowDateTime as u64) ^ counter.rotate_left(32),
         )
     }
-
-    v
 }
@@ -0,0 +1,19 @@
+use super::weak_keys;
+use crate::thread;
+use crate::time::Duration;
+
+#[test]
+fn weak_keys_without_tsc() {
+    let a = weak_keys(None);
+    thread::sleep(Duration::from_millis(20));
+    let b = weak_keys(None);
+    assert_ne!(a, (0, 0));
+    assert_ne!(a, b);
+}
+
+#[test]
+fn weak_keys_with_tsc() {
+    if let Some(tsc) = crate::sys::cpu::read_tsc() {
+        assert_ne!(weak_keys(Some(tsc)), weak_keys(Some(tsc.wrapping_add(1))));
+    }
+}
@@ -0,0 +1,44 @@
+//! A summary of the implementations std picked at runtime, for triaging reports of std behaving
+//! differently on some system.
+
+use crate::sys::c::{self, Resolver};
+use crate::sys::compat::version;
+use crate::sys::locks::{self, MutexKind};
+use crate::sys::rand::{self, RngSource};
+use crate::sys::time::{self, ClockSource};
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, PartialEq)]
+pub struct RuntimeBackends {
+    pub mutex_kind: &'static MutexKind,
+    pub clock_source: ClockSource,
+    pub rng_source: RngSource,
+    pub resolver: Resolver,
+    pub os_version: OsVersion,
+}
+
+/// The version as reported by `GetVersion`, which is capped at 6.2 for executables that aren't
+/// manifested for newer versions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OsVersion {
+    pub is_nt: bool,
+    pub major: u8,
+    pub minor: u8,
+}
+
+pub fn runtime_backends() -> RuntimeBackends {
+    let version = unsafe { c::GetVersion() };
+    RuntimeBackends {
+        mutex_kind: locks::current_kind(),
+        clock_source: time::clock_source(),
+        rng_source: rand::rng_source(),
+        resolver: c::resolver(),
+        os_version: OsVersion {
+            is_nt: version::is_windows_nt(),
+            major: version as u8,
+            minor: (version >> 8) as u8,
+        },
+    }
+}
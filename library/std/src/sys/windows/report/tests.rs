@@ -0,0 +1,19 @@
+use super::{runtime_backends, OsVersion};
+use crate::sys::c::Resolver;
+use crate::sys::locks::MutexKind;
+use crate::sys::rand::RngSource;
+use crate::sys::time::ClockSource;
+
+#[test]
+fn modern_host_uses_the_native_backends() {
+    let backends = runtime_backends();
+    let OsVersion { is_nt, major, minor } = backends.os_version;
+    if !is_nt || (major, minor) < (6, 1) {
+        return;
+    }
+
+    assert_eq!(backends.mutex_kind, &MutexKind::SrwLock);
+    assert_eq!(backends.clock_source, ClockSource::PerformanceCounter);
+    assert_eq!(backends.rng_source, RngSource::BCryptGenRandom);
+    assert_eq!(backends.resolver, Resolver::Ws2_32);
+}
@@ -3,14 +3,22 @@
 use crate::sys::c;
 use crate::thread;
 
+#[cfg(test)]
+mod tests;
+
 pub struct Handler;
 
 impl Handler {
     pub unsafe fn new() -> Handler {
+        Handler::with_guarantee(0x5000)
+    }
+
+    /// Failing to reserve the space isn't fatal, NT fails with `ERROR_INVALID_PARAMETER` for a
+    /// guarantee that doesn't fit in the thread's stack. The thread then runs without one, so a
+    /// stack overflow may kill it before the message is printed, like before Vista.
+    unsafe fn with_guarantee(mut size: c::ULONG) -> Handler {
         if c::SetThreadStackGuarantee::available() {
-            if c::SetThreadStackGuarantee(&mut 0x5000) == 0 {
-                panic!("failed to reserve stack space for exception handling");
-            }
+            let _ = c::SetThreadStackGuarantee(&mut size);
         }
         Handler
     }
@@ -0,0 +1,13 @@
+use super::Handler;
+use crate::thread;
+
+#[test]
+fn oversized_guarantee_is_not_fatal() {
+    thread::spawn(|| unsafe {
+        let _handler = Handler::with_guarantee(u32::MAX);
+        // and the usual one can still be set afterwards
+        let _handler = Handler::new();
+    })
+    .join()
+    .unwrap();
+}
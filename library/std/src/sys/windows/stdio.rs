@@ -1,11 +1,17 @@
 #![unstable(issue = "none", feature = "windows_stdio")]
 
+#[cfg(test)]
+mod tests;
+
 use crate::char::decode_utf16;
 use crate::cmp;
+use crate::ffi::c_void;
 use crate::io;
+use crate::mem;
 use crate::os::windows::io::{FromRawHandle, IntoRawHandle};
 use crate::ptr;
 use crate::str;
+use crate::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, Ordering};
 use crate::sys::c;
 use crate::sys::cvt;
 use crate::sys::handle::Handle;
@@ -75,14 +81,133 @@ pub fn get_handle(handle_id: c::DWORD) -> io::Result<c::HANDLE> {
     }
 }
 
-fn is_console(handle: c::HANDLE) -> bool {
+fn handle_is_console(handle: c::HANDLE) -> bool {
     // `GetConsoleMode` will return false (0) if this is a pipe (we don't care about the reported
     // mode). This will only detect Windows Console, not other terminals connected to a pipe like
     // MSYS. Which is exactly what we need, as only Windows Console needs a conversion to UTF-16.
+    // `GetFileType` isn't an option, as 9x/ME report consoles as `FILE_TYPE_UNKNOWN`.
     let mut mode = 0;
     unsafe { c::GetConsoleMode(handle, &mut mode) != 0 }
 }
 
+/// One of the standard streams.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StdStream {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+impl StdStream {
+    fn handle_id(self) -> c::DWORD {
+        match self {
+            StdStream::Stdin => c::STD_INPUT_HANDLE,
+            StdStream::Stdout => c::STD_OUTPUT_HANDLE,
+            StdStream::Stderr => c::STD_ERROR_HANDLE,
+        }
+    }
+}
+
+/// Returns whether `stream` is currently connected to a console, as opposed to being redirected
+/// to a file or pipe (or not being there at all).
+///
+/// Like the stdio types, this resolves the handle anew on every call, but only asks the console
+/// for it once per handle value.
+pub fn is_console(stream: StdStream) -> bool {
+    get_handle_cached(stream.handle_id()).map_or(false, |(_, console)| console)
+}
+
+/// Returns the size of the console window stdout is connected to, as `(columns, rows)`.
+///
+/// This is the visible window, not the (usually much taller) screen buffer. Returns `None` if
+/// stdout isn't a console.
+pub fn terminal_size() -> Option<(u16, u16)> {
+    let (handle, console) = get_handle_cached(c::STD_OUTPUT_HANDLE).ok()?;
+    if !console {
+        return None;
+    }
+    console_window_size(handle)
+}
+
+fn console_window_size(handle: c::HANDLE) -> Option<(u16, u16)> {
+    let mut info = unsafe { mem::zeroed() };
+    if unsafe { c::GetConsoleScreenBufferInfo(handle, &mut info) } == 0 {
+        return None;
+    }
+    window_size(&info)
+}
+
+fn window_size(info: &c::CONSOLE_SCREEN_BUFFER_INFO) -> Option<(u16, u16)> {
+    let window = &info.srWindow;
+    let columns = i32::from(window.Right) - i32::from(window.Left) + 1;
+    let rows = i32::from(window.Bottom) - i32::from(window.Top) + 1;
+    if columns > 1 && rows > 1 {
+        return Some((columns as u16, rows as u16));
+    }
+
+    // 9x reports a degenerate window (all corners at 0, so 1x1) for minimized consoles. Use the
+    // size the window would have when restored instead, which can't exceed the buffer.
+    let columns = cmp::min(info.dwMaximumWindowSize.X, info.dwSize.X);
+    let rows = cmp::min(info.dwMaximumWindowSize.Y, info.dwSize.Y);
+    if columns > 0 && rows > 0 { Some((columns as u16, rows as u16)) } else { None }
+}
+
+/// Caches whether the handles last seen for one of the std streams are consoles.
+///
+/// The handles are still resolved via `GetStdHandle` on every access, as the process can swap
+/// them with `SetStdHandle` at any time. Only the `GetConsoleMode` call is skipped while the
+/// stream keeps using the same handle value. Each slot is a single atomic, so concurrent updates
+/// can at worst evict each other, which only costs another `GetConsoleMode` call.
+struct ConsoleCache {
+    console: AtomicPtr<c_void>,
+    not_console: AtomicPtr<c_void>,
+}
+
+impl ConsoleCache {
+    const fn new() -> ConsoleCache {
+        ConsoleCache {
+            console: AtomicPtr::new(ptr::null_mut()),
+            not_console: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    fn is_console(&self, handle: c::HANDLE) -> bool {
+        if self.console.load(Ordering::Relaxed) == handle {
+            return true;
+        }
+        if self.not_console.load(Ordering::Relaxed) == handle {
+            return false;
+        }
+
+        // A new handle, so whatever was cached for the old one is stale now.
+        let console = handle_is_console(handle);
+        let (slot, other) = if console {
+            (&self.console, &self.not_console)
+        } else {
+            (&self.not_console, &self.console)
+        };
+        let _ =
+            other.compare_exchange(handle, ptr::null_mut(), Ordering::Relaxed, Ordering::Relaxed);
+        slot.store(handle, Ordering::Relaxed);
+        console
+    }
+}
+
+static STDIN_CONSOLE: ConsoleCache = ConsoleCache::new();
+static STDOUT_CONSOLE: ConsoleCache = ConsoleCache::new();
+static STDERR_CONSOLE: ConsoleCache = ConsoleCache::new();
+
+/// Resolves the current handle of a std stream, and whether it's a console.
+fn get_handle_cached(handle_id: c::DWORD) -> io::Result<(c::HANDLE, bool)> {
+    let handle = get_handle(handle_id)?;
+    let cache = match handle_id {
+        c::STD_INPUT_HANDLE => &STDIN_CONSOLE,
+        c::STD_OUTPUT_HANDLE => &STDOUT_CONSOLE,
+        _ => &STDERR_CONSOLE,
+    };
+    Ok((handle, cache.is_console(handle)))
+}
+
 fn write(
     handle_id: c::DWORD,
     data: &[u8],
@@ -92,8 +217,17 @@ fn write(
         return Ok(0);
     }
 
-    let handle = get_handle(handle_id)?;
-    if !is_console(handle) {
+    let (handle, console) = get_handle_cached(handle_id)?;
+    write_to_handle(handle, console, data, incomplete_utf8)
+}
+
+fn write_to_handle(
+    handle: c::HANDLE,
+    console: bool,
+    data: &[u8],
+    incomplete_utf8: &mut IncompleteUtf8,
+) -> io::Result<usize> {
+    if !console {
         unsafe {
             let handle = Handle::from_raw_handle(handle);
             let ret = handle.write(data);
@@ -210,7 +344,65 @@ fn write_valid_utf8_to_console(handle: c::HANDLE, utf8: &str) -> io::Result<usiz
     }
 }
 
+/// The code page console output is converted to on 9x/ME, 0 for the console's own.
+static OUTPUT_CODE_PAGE: AtomicU32 = AtomicU32::new(0);
+
+/// Overrides the code page console output is converted to on 9x/ME, e.g. to force CP932 on a
+/// Japanese console. 0 restores the default, the console's `GetConsoleOutputCP`.
+pub fn set_output_codepage(code_page: u32) {
+    OUTPUT_CODE_PAGE.store(code_page, Ordering::Relaxed);
+}
+
+/// Returns the code page console output is converted to on 9x/ME.
+pub fn output_codepage() -> u32 {
+    match OUTPUT_CODE_PAGE.load(Ordering::Relaxed) {
+        0 => unsafe { c::GetConsoleOutputCP() },
+        code_page => code_page,
+    }
+}
+
+/// Whether lone `\n`s are written as `\r\n` to 9x/ME consoles.
+static CRLF_TRANSLATION: AtomicBool = AtomicBool::new(false);
+
+/// Enables writing lone `\n`s as `\r\n` to 9x/ME consoles, for legacy consoles that don't return
+/// the cursor to the start of the line otherwise. Off by default. Redirected output (files and
+/// pipes) always gets the bytes as they were written.
+pub fn set_crlf_translation(enabled: bool) {
+    CRLF_TRANSLATION.store(enabled, Ordering::Relaxed);
+}
+
+pub fn crlf_translation() -> bool {
+    CRLF_TRANSLATION.load(Ordering::Relaxed)
+}
+
+fn encode_for_console(data: &[u16]) -> io::Result<Vec<u8>> {
+    let bytes = super::wide_to_multi_byte(output_codepage(), data)?;
+    Ok(if crlf_translation() { translate_crlf(&bytes) } else { bytes })
+}
+
+/// Replaces every `\n` that isn't preceded by `\r` by `\r\n`. A `\r` ending the previous write
+/// isn't known here, so a `\r\n` split across two writes still gets an extra `\r`, which is
+/// harmless on a console.
+fn translate_crlf(bytes: &[u8]) -> Vec<u8> {
+    let mut translated = Vec::with_capacity(bytes.len());
+    let mut prev = 0;
+    for &b in bytes {
+        if b == b'\n' && prev != b'\r' {
+            translated.push(b'\r');
+        }
+        translated.push(b);
+        prev = b;
+    }
+    translated
+}
+
 fn write_u16s(handle: c::HANDLE, data: &[u16]) -> io::Result<usize> {
+    // 9x/ME consoles are byte-based, so convert to their code page ourselves, unless unicows
+    // does that for us
+    if !crate::sys::compat::wide_apis_available() {
+        return write_u16s_ansi(handle, data);
+    }
+
     let mut written = 0;
     cvt(unsafe {
         c::WriteConsoleW(
@@ -224,6 +416,27 @@ fn write_u16s(handle: c::HANDLE, data: &[u16]) -> io::Result<usize> {
     Ok(written as usize)
 }
 
+fn write_u16s_ansi(handle: c::HANDLE, data: &[u16]) -> io::Result<usize> {
+    let bytes = encode_for_console(data)?;
+    // A single character may take several bytes, so write everything to be able to report the
+    // number of `u16`s written.
+    let mut bytes = &bytes[..];
+    while !bytes.is_empty() {
+        let mut written = 0;
+        cvt(unsafe {
+            c::WriteConsoleA(
+                handle,
+                bytes.as_ptr() as c::LPCVOID,
+                bytes.len() as u32,
+                &mut written,
+                ptr::null_mut(),
+            )
+        })?;
+        bytes = &bytes[written as usize..];
+    }
+    Ok(data.len())
+}
+
 impl Stdin {
     pub const fn new() -> Stdin {
         Stdin { surrogate: 0, incomplete_utf8: IncompleteUtf8::new() }
@@ -232,8 +445,8 @@ pub const fn new() -> Stdin {
 
 impl io::Read for Stdin {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let handle = get_handle(c::STD_INPUT_HANDLE)?;
-        if !is_console(handle) {
+        let (handle, console) = get_handle_cached(c::STD_INPUT_HANDLE)?;
+        if !console {
             unsafe {
                 let handle = Handle::from_raw_handle(handle);
                 let ret = handle.read(buf);
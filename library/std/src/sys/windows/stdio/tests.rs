@@ -0,0 +1,135 @@
+use super::{
+    console_window_size, encode_for_console, get_handle, handle_is_console, is_console,
+    output_codepage, set_crlf_translation, set_output_codepage, terminal_size, translate_crlf,
+    window_size, write_to_handle, ConsoleCache, IncompleteUtf8, StdStream,
+};
+use crate::fs::OpenOptions;
+use crate::os::windows::io::AsRawHandle;
+use crate::sys::c;
+use crate::sys::pipe::anon_pipe;
+
+#[test]
+fn output_codepage_override() {
+    let e_acute: Vec<u16> = "\u{e9}".encode_utf16().collect();
+
+    set_output_codepage(437);
+    assert_eq!(output_codepage(), 437);
+    assert_eq!(encode_for_console(&e_acute).unwrap(), [0x82]);
+
+    set_output_codepage(1252);
+    assert_eq!(output_codepage(), 1252);
+    assert_eq!(encode_for_console(&e_acute).unwrap(), [0xe9]);
+
+    set_output_codepage(0);
+    assert_ne!(output_codepage(), 0);
+}
+
+#[test]
+fn crlf_translation_only_for_consoles() {
+    assert_eq!(translate_crlf(b"a\nb"), b"a\r\nb");
+    assert_eq!(translate_crlf(b"a\r\nb\n"), b"a\r\nb\r\n");
+
+    let a_lf_b: Vec<u16> = "a\nb".encode_utf16().collect();
+    set_crlf_translation(true);
+    assert_eq!(encode_for_console(&a_lf_b).unwrap(), b"a\r\nb");
+    set_crlf_translation(false);
+    assert_eq!(encode_for_console(&a_lf_b).unwrap(), b"a\nb");
+
+    // pipes never take the console path, so they get the raw bytes
+    let pipes = anon_pipe(true, false).unwrap();
+    let handle = pipes.theirs.handle().as_raw_handle();
+    assert!(!handle_is_console(handle));
+    set_crlf_translation(true);
+    let written = write_to_handle(handle, false, b"a\nb", &mut IncompleteUtf8::new());
+    set_crlf_translation(false);
+    assert_eq!(written.unwrap(), 3);
+    let mut buf = [0; 8];
+    let read = pipes.ours.read(&mut buf).unwrap();
+    assert_eq!(&buf[..read], b"a\nb");
+}
+
+#[test]
+fn console_cache_follows_swapped_handles() {
+    // A cache of its own and explicit handles, as swapping the std handles of the process would
+    // affect the tests running in parallel.
+    let cache = ConsoleCache::new();
+    let first = anon_pipe(true, false).unwrap();
+    let second = anon_pipe(true, false).unwrap();
+
+    let mut incomplete_utf8 = IncompleteUtf8::new();
+    for (pipe, data) in [(&first, b"one"), (&second, b"two")] {
+        let handle = pipe.theirs.handle().as_raw_handle();
+        let console = cache.is_console(handle);
+        assert!(!console);
+        assert_eq!(write_to_handle(handle, console, data, &mut incomplete_utf8).unwrap(), 3);
+    }
+
+    let mut buf = [0; 8];
+    let read = first.ours.read(&mut buf).unwrap();
+    assert_eq!(&buf[..read], b"one");
+    let read = second.ours.read(&mut buf).unwrap();
+    assert_eq!(&buf[..read], b"two");
+
+    // Only when the tests run attached to a console.
+    if let Ok(console) = OpenOptions::new().read(true).write(true).open("CONOUT$") {
+        let pipe = first.theirs.handle().as_raw_handle();
+        assert!(cache.is_console(console.as_raw_handle()));
+        assert!(!cache.is_console(pipe));
+        assert!(cache.is_console(console.as_raw_handle()));
+    }
+}
+
+#[test]
+fn is_console_follows_redirection() {
+    // explicit handles, as swapping stdin would affect the tests running in parallel
+    let pipes = anon_pipe(true, false).unwrap();
+    assert!(!handle_is_console(pipes.ours.handle().as_raw_handle()));
+
+    // Only when the tests run attached to a console.
+    if let Ok(console) = OpenOptions::new().read(true).write(true).open("CONIN$") {
+        assert!(handle_is_console(console.as_raw_handle()));
+    }
+    // reading the std handles is fine, whatever the tests' stdin is connected to
+    let stdin = get_handle(c::STD_INPUT_HANDLE).map_or(false, handle_is_console);
+    assert_eq!(is_console(StdStream::Stdin), stdin);
+}
+
+#[test]
+fn terminal_size_of_redirected_stdout() {
+    // explicit handles, as swapping stdout would affect the tests running in parallel
+    let pipes = anon_pipe(true, false).unwrap();
+    assert_eq!(console_window_size(pipes.theirs.handle().as_raw_handle()), None);
+
+    // Only when the tests run attached to a console.
+    if let Ok(console) = OpenOptions::new().read(true).write(true).open("CONOUT$") {
+        assert!(console_window_size(console.as_raw_handle()).is_some());
+    }
+    if !is_console(StdStream::Stdout) {
+        assert_eq!(terminal_size(), None);
+    }
+}
+
+#[test]
+fn window_size_of_minimized_console() {
+    let coord = |x, y| c::COORD { X: x, Y: y };
+    let rect = |left, top, right, bottom| c::SMALL_RECT {
+        Left: left,
+        Top: top,
+        Right: right,
+        Bottom: bottom,
+    };
+    let mut info = c::CONSOLE_SCREEN_BUFFER_INFO {
+        dwSize: coord(80, 300),
+        dwCursorPosition: coord(0, 0),
+        wAttributes: 0,
+        srWindow: rect(0, 100, 79, 124),
+        dwMaximumWindowSize: coord(80, 50),
+    };
+    assert_eq!(window_size(&info), Some((80, 25)));
+
+    info.srWindow = rect(0, 0, 0, 0);
+    assert_eq!(window_size(&info), Some((80, 50)));
+
+    info.dwMaximumWindowSize = coord(0, 0);
+    assert_eq!(window_size(&info), None);
+}
@@ -24,6 +24,24 @@ pub fn get_handle(handle_id: c::DWORD) -> io::Result<c::HANDLE> {
     }
 }
 
+/// One of the standard streams.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StdStream {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+/// Returns whether `stream` is connected to a console, which UWP apps don't have.
+pub fn is_console(_stream: StdStream) -> bool {
+    false
+}
+
+/// Returns the size of the console window stdout is connected to, which UWP apps don't have.
+pub fn terminal_size() -> Option<(u16, u16)> {
+    None
+}
+
 fn write(handle_id: c::DWORD, data: &[u8]) -> io::Result<usize> {
     let handle = get_handle(handle_id)?;
     // SAFETY: The handle returned from `get_handle` must be valid and non-null.
@@ -1,19 +1,23 @@
 use crate::convert::TryInto;
 use crate::ffi::CStr;
 use crate::io;
+use crate::mem;
 use crate::num::NonZeroUsize;
 use crate::os::windows::io::AsRawHandle;
 use crate::ptr;
 use crate::sys::c;
-use crate::sys::handle::Handle;
+use crate::sys::handle::{self, Handle};
 use crate::sys::stack_overflow;
 use crate::sys_common::FromInner;
-use crate::time::Duration;
+use crate::time::{Duration, Instant};
 
-use libc::c_void;
+use libc::{c_int, c_void};
 
 use super::to_u16s;
 
+#[cfg(test)]
+mod tests;
+
 pub const DEFAULT_MIN_STACK_SIZE: usize = 2 * 1024 * 1024;
 
 pub struct Thread {
@@ -58,7 +62,11 @@ extern "system" fn thread_start(main: *mut c_void) -> c::DWORD {
                 // out of stack.
                 let _handler = stack_overflow::Handler::new();
                 // Finally, let's run some code.
-                Box::from_raw(main as *mut Box<dyn FnOnce()>)();
+                let main = Box::from_raw(main as *mut Box<dyn FnOnce()>);
+                #[cfg(all(target_arch = "x86", not(target_vendor = "uwp")))]
+                crate::sys::exception::with_exception_frame(main);
+                #[cfg(not(all(target_arch = "x86", not(target_vendor = "uwp"))))]
+                main();
             }
             0
         }
@@ -75,23 +83,46 @@ pub fn set_name(name: &CStr) {
     }
 
     pub fn join(self) {
-        let rc = unsafe { c::WaitForSingleObject(self.handle.as_raw_handle(), c::INFINITE) };
-        if rc == c::WAIT_FAILED {
-            panic!("failed to join on thread: {}", io::Error::last_os_error());
+        // `GetCurrentThread` returns a pseudo handle that never becomes signaled, so waiting on
+        // it would hang forever.
+        if self.handle.as_raw_handle() == unsafe { c::GetCurrentThread() } {
+            panic!("failed to join on thread: cannot wait on a pseudo handle");
+        }
+        if let Err(e) = handle::wait(self.handle.as_raw_handle(), c::INFINITE) {
+            panic!("failed to join on thread: {}", e);
         }
+        // `self.handle` is closed on drop, both here and when a thread is never joined.
     }
 
     pub fn yield_now() {
-        // This function will return 0 if there are no other threads to execute,
-        // but this also means that the yield was useless so this isn't really a
-        // case that needs to be worried about.
-        unsafe {
-            c::SwitchToThread();
+        if c::SwitchToThread::available() {
+            // This function will return 0 if there are no other threads to execute,
+            // but this also means that the yield was useless so this isn't really a
+            // case that needs to be worried about.
+            unsafe {
+                c::SwitchToThread();
+            }
+        } else {
+            // 9x/ME and NT 3.x don't have `SwitchToThread`. `Sleep(0)` is weaker, as it only
+            // yields to threads of the same or higher priority, but it's the best we have.
+            unsafe {
+                c::Sleep(0);
+            }
         }
     }
 
+    /// Doesn't process window messages, see `sleep_alertable` for that.
     pub fn sleep(dur: Duration) {
-        unsafe { c::Sleep(super::dur2timeout(dur)) }
+        // `dur2timeout` clamps to `MAX_TIMEOUT`, so longer sleeps take several calls.
+        let mut remaining = dur;
+        loop {
+            let timeout = super::dur2timeout(remaining);
+            unsafe { c::Sleep(timeout) };
+            match remaining.checked_sub(Duration::from_millis(timeout as u64)) {
+                Some(rest) if !rest.is_zero() => remaining = rest,
+                _ => return,
+            }
+        }
     }
 
     pub fn handle(&self) -> &Handle {
@@ -103,6 +134,105 @@ pub fn into_handle(self) -> Handle {
     }
 }
 
+/// Sleeps for `dur`, and processes the window messages of the calling thread meanwhile if `pump`
+/// is set.
+///
+/// Pumping matters for threads that created windows: `SendMessage` to one of their windows blocks
+/// the sender until the message is processed, and so do broadcasts (like DDE initiation, or the
+/// shell notifying all top-level windows), which means that a thread with windows blocking without
+/// pumping can hang other programs. On 9x/ME, that can even hang the whole desktop.
+///
+/// A `WM_QUIT` is posted again, so that the thread's own message loop sees it, after which the
+/// rest of the duration is slept without pumping.
+pub fn sleep_alertable(dur: Duration, pump: bool) {
+    // There can't be any windows without user32, so there is nothing to pump if it isn't loaded
+    // yet. It has to be checked first, as resolving the functions below would load it.
+    let user32 = unsafe { c::GetModuleHandleA(b"user32\0".as_ptr() as c::LPCSTR) };
+    if !pump || user32.is_null() || !c::MsgWaitForMultipleObjects::available() {
+        return Thread::sleep(dur);
+    }
+
+    let deadline = Instant::now().checked_add(dur);
+    let remaining = || match deadline {
+        Some(deadline) => super::dur2timeout(deadline.saturating_duration_since(Instant::now())),
+        None => c::INFINITE,
+    };
+    unsafe {
+        loop {
+            let res =
+                c::MsgWaitForMultipleObjects(0, ptr::null(), c::FALSE, remaining(), c::QS_ALLINPUT);
+            if res == c::WAIT_TIMEOUT && remaining() != 0 {
+                // the timeout was clamped, the deadline is still ahead
+                continue;
+            }
+            if res != c::WAIT_OBJECT_0 {
+                return;
+            }
+
+            let mut msg: c::MSG = mem::zeroed();
+            while c::PeekMessageA(&mut msg, ptr::null_mut(), 0, 0, c::PM_REMOVE) != 0 {
+                if msg.message == c::WM_QUIT {
+                    c::PostQuitMessage(msg.wParam as c_int);
+                    return match deadline {
+                        Some(deadline) => {
+                            Thread::sleep(deadline.saturating_duration_since(Instant::now()))
+                        }
+                        None => c::Sleep(c::INFINITE),
+                    };
+                }
+                c::TranslateMessage(&msg);
+                c::DispatchMessageA(&msg);
+            }
+        }
+    }
+}
+
+/// How an alertable wait ended.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WaitResult {
+    /// The handle was signaled, or is a mutex that was abandoned by its owner
+    Signaled,
+    TimedOut,
+    /// IO completion routines or other APCs ran, so the wait ended early and can be retried
+    IoCompletion,
+}
+
+/// Waits for `handle` to be signaled, or just sleeps if there's no handle, for at most `dur`.
+///
+/// Durations longer than `MAX_TIMEOUT` are clamped, so the wait may time out early then.
+///
+/// On NT, the wait is alertable, so it runs the completion routines of IO started by this thread
+/// and reports that as `WaitResult::IoCompletion`. APCs aren't dependable on 9x/ME, where this
+/// is a plain wait that never reports `IoCompletion`.
+pub fn wait_alertable(handle: Option<&Handle>, dur: Duration) -> io::Result<WaitResult> {
+    wait_alertable_as(handle, dur, crate::sys::compat::version::is_windows_nt())
+}
+
+fn wait_alertable_as(
+    handle: Option<&Handle>,
+    dur: Duration,
+    alertable: bool,
+) -> io::Result<WaitResult> {
+    let timeout = super::dur2timeout(dur);
+    let res = unsafe {
+        match handle {
+            Some(handle) => {
+                c::WaitForSingleObjectEx(handle.as_raw_handle(), timeout, alertable as c::BOOL)
+            }
+            None => match c::SleepEx(timeout, alertable as c::BOOL) {
+                0 => c::WAIT_TIMEOUT,
+                res => res,
+            },
+        }
+    };
+    match res {
+        c::WAIT_OBJECT_0 | c::WAIT_ABANDONED => Ok(WaitResult::Signaled),
+        c::WAIT_TIMEOUT => Ok(WaitResult::TimedOut),
+        c::WAIT_IO_COMPLETION => Ok(WaitResult::IoCompletion),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
 pub fn available_parallelism() -> io::Result<NonZeroUsize> {
     let res = unsafe {
         let mut sysinfo: c::SYSTEM_INFO = crate::mem::zeroed();
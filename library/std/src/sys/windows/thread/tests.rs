@@ -0,0 +1,78 @@
+use super::Thread;
+use crate::sync::atomic::{AtomicUsize, Ordering};
+use crate::sync::Arc;
+use crate::time::{Duration, Instant};
+
+#[test]
+fn spawn_and_join_many() {
+    let count = Arc::new(AtomicUsize::new(0));
+
+    for _ in 0..50 {
+        let count = count.clone();
+        let thread = unsafe {
+            Thread::new(
+                64 * 1024,
+                Box::new(move || {
+                    count.fetch_add(1, Ordering::SeqCst);
+                }),
+            )
+        }
+        .unwrap();
+        thread.join();
+    }
+
+    assert_eq!(count.load(Ordering::SeqCst), 50);
+}
+
+#[test]
+fn yield_now_returns() {
+    let start = Instant::now();
+    for _ in 0..100 {
+        Thread::yield_now();
+    }
+    // scheduler dependent, so only guard against something hanging
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn sleep_alertable_waits_for_the_duration() {
+    for pump in [false, true] {
+        let start = Instant::now();
+        super::sleep_alertable(Duration::from_millis(50), pump);
+        assert!(start.elapsed() >= Duration::from_millis(50), "pump: {}", pump);
+    }
+}
+
+#[test]
+fn wait_alertable_results() {
+    use super::{wait_alertable, wait_alertable_as, WaitResult};
+    use crate::os::windows::io::AsRawHandle;
+    use crate::sys::c;
+    use crate::sys::handle::Handle;
+
+    let event = Handle::new_event(true, false).unwrap();
+    let wait = |handle, alertable| {
+        wait_alertable_as(handle, Duration::from_millis(10), alertable).unwrap()
+    };
+    // what 9x/ME do
+    assert_eq!(wait(Some(&event), false), WaitResult::TimedOut);
+    assert_eq!(wait(None, false), WaitResult::TimedOut);
+    // nothing queued an APC, so the alertable waits time out too
+    assert_eq!(wait(Some(&event), true), WaitResult::TimedOut);
+    assert_eq!(wait(None, true), WaitResult::TimedOut);
+
+    assert!(unsafe { c::SetEvent(event.as_raw_handle()) } != 0);
+    let res = wait_alertable(Some(&event), Duration::from_millis(10)).unwrap();
+    assert_eq!(res, WaitResult::Signaled);
+}
+
+#[test]
+fn huge_durations_are_not_infinite() {
+    use crate::sys::{c, dur2timeout, MAX_TIMEOUT};
+
+    let too_long = Duration::from_millis(u32::MAX as u64 + 1);
+    assert_eq!(dur2timeout(too_long), 0xFFFF_FFFE);
+    assert_eq!(dur2timeout(Duration::MAX), MAX_TIMEOUT);
+    assert_ne!(dur2timeout(Duration::from_millis(c::INFINITE as u64)), c::INFINITE);
+    assert_eq!(dur2timeout(Duration::from_micros(1500)), 2);
+}
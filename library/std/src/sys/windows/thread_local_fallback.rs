@@ -0,0 +1,75 @@
+//! Thread-local storage for when `TlsAlloc` runs out of indexes.
+//!
+//! Windows 9x/ME only provide 80 TLS indexes per process (NT 4 only 64), and other DLLs loaded
+//! into the process compete for them. Once `TlsAlloc` is exhausted, `thread_local_key` hands out
+//! keys from this module instead. The values are kept in a global table keyed by the key and the
+//! `GetCurrentThreadId` of the owning thread, and removed again when that thread exits.
+//!
+//! This is a lot slower than real TLS, but keeps std's own thread-local state (like the handle
+//! returned by `thread::current()`) working in crowded processes.
+
+#![unstable(feature = "thread_local_internals", issue = "none")]
+
+#[cfg(test)]
+mod tests;
+
+use crate::ptr;
+use crate::sync::atomic::{AtomicU32, Ordering};
+use crate::sys::c;
+use crate::sys_common::mutex::StaticMutex;
+
+/// Set on all keys handed out by this module. Real TLS indexes never get anywhere close.
+const KEY_FLAG: c::DWORD = 0x8000_0000;
+
+static NEXT_KEY: AtomicU32 = AtomicU32::new(0);
+
+struct Entry {
+    key: c::DWORD,
+    thread: c::DWORD,
+    value: *mut u8,
+}
+
+static LOCK: StaticMutex = StaticMutex::new();
+static mut ENTRIES: Vec<Entry> = Vec::new();
+
+pub fn create() -> c::DWORD {
+    KEY_FLAG | NEXT_KEY.fetch_add(1, Ordering::Relaxed)
+}
+
+#[inline]
+pub fn is_fallback_key(key: c::DWORD) -> bool {
+    key & KEY_FLAG != 0
+}
+
+pub unsafe fn set(key: c::DWORD, value: *mut u8) {
+    let thread = c::GetCurrentThreadId();
+    let _guard = LOCK.lock();
+    let entries = &mut *ptr::addr_of_mut!(ENTRIES);
+
+    match entries.iter().position(|e| e.key == key && e.thread == thread) {
+        Some(i) if value.is_null() => {
+            entries.swap_remove(i);
+        }
+        Some(i) => entries[i].value = value,
+        None if value.is_null() => {}
+        None => entries.push(Entry { key, thread, value }),
+    }
+}
+
+pub unsafe fn get(key: c::DWORD) -> *mut u8 {
+    let thread = c::GetCurrentThreadId();
+    let _guard = LOCK.lock();
+    let entries = &*ptr::addr_of!(ENTRIES);
+
+    entries.iter().find(|e| e.key == key && e.thread == thread).map_or(ptr::null_mut(), |e| e.value)
+}
+
+/// Forgets all values of the current thread. Called on thread exit after the destructors ran,
+/// since thread ids are reused.
+pub unsafe fn clear_current_thread() {
+    let thread = c::GetCurrentThreadId();
+    let _guard = LOCK.lock();
+    let entries = &mut *ptr::addr_of_mut!(ENTRIES);
+
+    entries.retain(|e| e.thread != thread);
+}
@@ -0,0 +1,49 @@
+use super::{create, get, is_fallback_key, set, ENTRIES, LOCK};
+use crate::ptr;
+use crate::thread;
+
+#[test]
+fn values_are_per_thread() {
+    let key = create();
+    assert!(is_fallback_key(key));
+
+    unsafe {
+        assert!(get(key).is_null());
+        set(key, 1 as *mut u8);
+        assert_eq!(get(key), 1 as *mut u8);
+    }
+
+    thread::spawn(move || unsafe {
+        assert!(get(key).is_null());
+        set(key, 2 as *mut u8);
+        assert_eq!(get(key), 2 as *mut u8);
+    })
+    .join()
+    .unwrap();
+
+    unsafe {
+        assert_eq!(get(key), 1 as *mut u8);
+        set(key, crate::ptr::null_mut());
+        assert!(get(key).is_null());
+    }
+}
+
+#[test]
+fn values_are_cleared_at_thread_exit() {
+    // like a key created without a destructor, nothing but the thread exit clears it
+    let key = create();
+
+    thread::spawn(move || unsafe {
+        set(key, 1 as *mut u8);
+        for _ in 0..10 {
+            assert_eq!(get(key), 1 as *mut u8);
+        }
+    })
+    .join()
+    .unwrap();
+
+    // the TLS callback ran before the thread was joined
+    let _guard = unsafe { LOCK.lock() };
+    let entries = unsafe { &*ptr::addr_of!(ENTRIES) };
+    assert!(!entries.iter().any(|e| e.key == key));
+}
@@ -3,6 +3,7 @@
 use crate::sync::atomic::AtomicPtr;
 use crate::sync::atomic::Ordering::SeqCst;
 use crate::sys::c;
+use crate::sys::thread_local_fallback as fallback;
 
 pub type Key = c::DWORD;
 pub type Dtor = unsafe extern "C" fn(*mut u8);
@@ -48,7 +49,8 @@
 #[inline]
 pub unsafe fn create(dtor: Option<Dtor>) -> Key {
     let key = c::TlsAlloc();
-    assert!(key != c::TLS_OUT_OF_INDEXES);
+    // 9x/ME only have 80 TLS indexes, so running out is a real possibility there
+    let key = if key == c::TLS_OUT_OF_INDEXES { fallback::create() } else { key };
     if let Some(f) = dtor {
         register_dtor(key, f);
     }
@@ -57,12 +59,18 @@ pub unsafe fn create(dtor: Option<Dtor>) -> Key {
 
 #[inline]
 pub unsafe fn set(key: Key, value: *mut u8) {
+    if fallback::is_fallback_key(key) {
+        return fallback::set(key, value);
+    }
     let r = c::TlsSetValue(key, value as c::LPVOID);
     debug_assert!(r != 0);
 }
 
 #[inline]
 pub unsafe fn get(key: Key) -> *mut u8 {
+    if fallback::is_fallback_key(key) {
+        return fallback::get(key);
+    }
     c::TlsGetValue(key) as *mut u8
 }
 
@@ -198,6 +206,9 @@ unsafe fn register_dtor(key: Key, dtor: Dtor) {
         run_dtors();
         #[cfg(target_thread_local)]
         super::thread_local_dtor::run_keyless_dtors();
+        // Whether any destructors were registered or not, as the fallback table also holds the
+        // values of keys without one, and thread ids get reused.
+        fallback::clear_current_thread();
     }
 
     // See comments above for what this is doing. Note that we don't need this
@@ -224,11 +235,11 @@ unsafe fn run_dtors() {
         any_run = false;
         let mut cur = DTORS.load(SeqCst);
         while !cur.is_null() {
-            let ptr = c::TlsGetValue((*cur).key);
+            let ptr = get((*cur).key);
 
             if !ptr.is_null() {
-                c::TlsSetValue((*cur).key, ptr::null_mut());
-                ((*cur).dtor)(ptr as *mut _);
+                set((*cur).key, ptr::null_mut());
+                ((*cur).dtor)(ptr);
                 any_run = true;
             }
 
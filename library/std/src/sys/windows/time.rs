@@ -2,7 +2,9 @@
 use crate::convert::TryInto;
 use crate::fmt;
 use crate::mem;
+use crate::sync::atomic::{AtomicU8, Ordering as AtomicOrdering};
 use crate::sys::c;
+use crate::sys_common::IntoInner;
 use crate::time::Duration;
 
 use core::hash::{Hash, Hasher};
@@ -10,10 +12,13 @@
 const NANOS_PER_SEC: u64 = 1_000_000_000;
 const INTERVALS_PER_SEC: u64 = NANOS_PER_SEC / 100;
 
+#[cfg(test)]
+mod tests;
+
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Hash)]
 pub struct Instant {
     // This duration is relative to an arbitrary microsecond epoch
-    // from the winapi QueryPerformanceCounter function.
+    // from the clock source, see `clock_source`.
     t: Duration,
 }
 
@@ -33,19 +38,33 @@ pub struct SystemTime {
 
 impl Instant {
     pub fn now() -> Instant {
-        // High precision timing on windows operates in "Performance Counter"
-        // units, as returned by the WINAPI QueryPerformanceCounter function.
-        // These relate to seconds by a factor of QueryPerformanceFrequency.
-        // In order to keep unit conversions out of normal interval math, we
-        // measure in QPC units and immediately convert to nanoseconds.
-        perf_counter::PerformanceCounterInstant::now().into()
+        match clock_source() {
+            // High precision timing on windows operates in "Performance Counter"
+            // units, as returned by the WINAPI QueryPerformanceCounter function.
+            // These relate to seconds by a factor of QueryPerformanceFrequency.
+            // In order to keep unit conversions out of normal interval math, we
+            // measure in QPC units and immediately convert to nanoseconds.
+            ClockSource::PerformanceCounter => {
+                perf_counter::PerformanceCounterInstant::now().into()
+            }
+            ClockSource::TimeGetTime => {
+                Instant { t: Duration::from_millis(ticks::extend(unsafe { c::timeGetTime() })) }
+            }
+            ClockSource::TickCount => {
+                Instant { t: Duration::from_millis(ticks::extend(unsafe { c::GetTickCount() })) }
+            }
+        }
     }
 
     pub fn checked_sub_instant(&self, other: &Instant) -> Option<Duration> {
         // On windows there's a threshold below which we consider two timestamps
         // equivalent due to measurement error. For more details + doc link,
-        // check the docs on epsilon.
-        let epsilon = perf_counter::PerformanceCounterInstant::epsilon();
+        // check the docs on epsilon. The millisecond counters are kept monotonic
+        // across threads by `ticks::extend`.
+        let epsilon = match clock_source() {
+            ClockSource::PerformanceCounter => perf_counter::PerformanceCounterInstant::epsilon(),
+            ClockSource::TimeGetTime | ClockSource::TickCount => Duration::new(0, 0),
+        };
         if other.t > self.t && other.t - self.t <= epsilon {
             Some(Duration::new(0, 0))
         } else {
@@ -62,6 +81,39 @@ pub fn checked_sub_duration(&self, other: &Duration) -> Option<Instant> {
     }
 }
 
+/// Where `Instant` gets the time from, the most precise of these that is available.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ClockSource {
+    /// `QueryPerformanceCounter`, if the hardware has a performance counter
+    PerformanceCounter,
+    /// `timeGetTime` from winmm, with a resolution of 1ms after `timeBeginPeriod(1)` (5ms or more
+    /// otherwise)
+    TimeGetTime,
+    /// `GetTickCount`, with a resolution of 10-55ms
+    TickCount,
+}
+
+pub fn clock_source() -> ClockSource {
+    // 0 for not yet chosen, otherwise the discriminant plus 1
+    static SOURCE: AtomicU8 = AtomicU8::new(0);
+
+    match SOURCE.load(AtomicOrdering::Relaxed) {
+        1 => return ClockSource::PerformanceCounter,
+        2 => return ClockSource::TimeGetTime,
+        3 => return ClockSource::TickCount,
+        _ => {}
+    }
+    let source = if perf_counter::is_available() {
+        ClockSource::PerformanceCounter
+    } else if c::timeGetTime::available() {
+        ClockSource::TimeGetTime
+    } else {
+        ClockSource::TickCount
+    };
+    SOURCE.store(source as u8 + 1, AtomicOrdering::Relaxed);
+    source
+}
+
 impl SystemTime {
     pub fn now() -> SystemTime {
         unsafe {
@@ -137,6 +189,12 @@ fn from(t: c::FILETIME) -> SystemTime {
     }
 }
 
+impl IntoInner<c::FILETIME> for SystemTime {
+    fn into_inner(self) -> c::FILETIME {
+        self.t
+    }
+}
+
 impl Hash for SystemTime {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.intervals().hash(state)
@@ -188,6 +246,13 @@ fn from(other: PerformanceCounterInstant) -> Self {
         }
     }
 
+    /// Whether there is a performance counter. That's always the case since XP, but older systems
+    /// need hardware support.
+    pub fn is_available() -> bool {
+        let mut frequency = 0;
+        unsafe { c::QueryPerformanceFrequency(&mut frequency) != 0 && frequency != 0 }
+    }
+
     fn frequency() -> c::LARGE_INTEGER {
         // Either the cached result of `QueryPerformanceFrequency` or `0` for
         // uninitialized. Storing this as a single `AtomicU64` allows us to use
@@ -216,3 +281,37 @@ fn query() -> c::LARGE_INTEGER {
         qpc_value
     }
 }
+
+mod ticks {
+    use crate::sync::atomic::{AtomicU64, Ordering};
+
+    /// The last value returned by `extend`, or 0 before the first call.
+    static LAST: AtomicU64 = AtomicU64::new(0);
+
+    /// Extends the wrapping 32-bit millisecond count of `timeGetTime`/`GetTickCount` (which wraps
+    /// after 49.7 days) to 64 bits, as long as it's read at least once every 24.8 days. This also
+    /// makes it monotonic across threads: a reading that is older than the last one returned, from
+    /// a thread that lost a race, returns the last one instead.
+    pub fn extend(now: u32) -> u64 {
+        extend_from(&LAST, now)
+    }
+
+    pub(super) fn extend_from(last: &AtomicU64, now: u32) -> u64 {
+        let mut prev = last.load(Ordering::Relaxed);
+        loop {
+            let next = if prev == 0 {
+                now as u64
+            } else {
+                let elapsed = now.wrapping_sub(prev as u32);
+                if elapsed as i32 <= 0 {
+                    return prev;
+                }
+                prev + elapsed as u64
+            };
+            match last.compare_exchange_weak(prev, next, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => return next,
+                Err(current) => prev = current,
+            }
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use super::{clock_source, ticks, Instant};
+use crate::sync::atomic::AtomicU64;
+use crate::time::Duration;
+
+#[test]
+fn clock_source_advances_monotonically() {
+    let source = clock_source();
+    assert_eq!(clock_source(), source);
+
+    // long enough for even `GetTickCount` to advance a few times
+    let start = Instant::now();
+    let mut prev = start;
+    while prev.checked_sub_instant(&start).unwrap() < Duration::from_millis(200) {
+        let now = Instant::now();
+        assert!(now >= prev, "{:?} went backwards with {:?}", source, now);
+        prev = now;
+    }
+}
+
+#[test]
+fn ticks_wrap_around() {
+    let last = AtomicU64::new(0);
+    let wrap = u32::MAX as u64 + 1;
+    assert_eq!(ticks::extend_from(&last, u32::MAX - 5), wrap - 6);
+    assert_eq!(ticks::extend_from(&last, 10), wrap + 10);
+    // from a thread that read the count before the last call
+    assert_eq!(ticks::extend_from(&last, 5), wrap + 10);
+    assert_eq!(ticks::extend_from(&last, 20), wrap + 20);
+}
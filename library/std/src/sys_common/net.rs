@@ -78,6 +78,8 @@ pub fn setsockopt<T>(
 
 pub fn getsockopt<T: Copy>(sock: &Socket, level: c_int, option_name: c_int) -> io::Result<T> {
     unsafe {
+        // Zeroed, as an implementation may write less than asked for (and report that in
+        // `option_len`), which must not leave the rest of the value uninitialized.
         let mut option_value: T = mem::zeroed();
         let mut option_len = mem::size_of::<T>() as c::socklen_t;
         cvt(c::getsockopt(
@@ -91,6 +93,23 @@ pub fn getsockopt<T: Copy>(sock: &Socket, level: c_int, option_name: c_int) -> i
     }
 }
 
+/// TTLs have to fit in the 8 bit field of the IP header. Some Winsocks truncate larger values
+/// instead of rejecting them.
+#[cfg(windows)]
+fn checked_ttl(ttl: u32) -> io::Result<c_int> {
+    if ttl > u8::MAX as u32 {
+        Err(io::const_io_error!(ErrorKind::InvalidInput, "TTL must be between 0 and 255"))
+    } else {
+        Ok(ttl as c_int)
+    }
+}
+
+/// Elsewhere, the OS gets to reject TTLs it doesn't take.
+#[cfg(not(windows))]
+fn checked_ttl(ttl: u32) -> io::Result<c_int> {
+    Ok(ttl as c_int)
+}
+
 fn sockname<F>(f: F) -> io::Result<SocketAddr>
 where
     F: FnOnce(*mut c::sockaddr, *mut c::socklen_t) -> c_int,
@@ -329,7 +348,7 @@ pub fn nodelay(&self) -> io::Result<bool> {
     }
 
     pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
-        setsockopt(&self.inner, c::IPPROTO_IP, c::IP_TTL, ttl as c_int)
+        setsockopt(&self.inner, c::IPPROTO_IP, c::IP_TTL, checked_ttl(ttl)?)
     }
 
     pub fn ttl(&self) -> io::Result<u32> {
@@ -396,11 +415,21 @@ pub fn bind(addr: io::Result<&SocketAddr>) -> io::Result<TcpListener> {
         setsockopt(&sock, c::SOL_SOCKET, c::SO_REUSEADDR, 1 as c_int)?;
 
         // Bind our new socket
-        let (addrp, len) = addr.into_inner();
-        cvt(unsafe { c::bind(sock.as_raw(), addrp, len as _) })?;
+        #[cfg(not(windows))]
+        {
+            let (addrp, len) = addr.into_inner();
+            cvt(unsafe { c::bind(sock.as_raw(), addrp, len as _) })?;
+        }
+        // See `net::bind` for the errors.
+        #[cfg(windows)]
+        crate::sys::net::bind(&sock, addr)?;
 
         // Start listening
+        #[cfg(not(windows))]
         cvt(unsafe { c::listen(sock.as_raw(), 128) })?;
+        // 9x can't take a backlog of 128, see `net::listen`.
+        #[cfg(windows)]
+        crate::sys::net::listen(&sock, 128)?;
         Ok(TcpListener { inner: sock })
     }
 
@@ -429,7 +458,7 @@ pub fn duplicate(&self) -> io::Result<TcpListener> {
     }
 
     pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
-        setsockopt(&self.inner, c::IPPROTO_IP, c::IP_TTL, ttl as c_int)
+        setsockopt(&self.inner, c::IPPROTO_IP, c::IP_TTL, checked_ttl(ttl)?)
     }
 
     pub fn ttl(&self) -> io::Result<u32> {
@@ -437,15 +466,28 @@ pub fn ttl(&self) -> io::Result<u32> {
         Ok(raw as u32)
     }
 
+    #[cfg(not(windows))]
     pub fn set_only_v6(&self, only_v6: bool) -> io::Result<()> {
         setsockopt(&self.inner, c::IPPROTO_IPV6, c::IPV6_V6ONLY, only_v6 as c_int)
     }
 
+    // Pre-Vista systems don't have the option, see `Socket::set_only_v6`.
+    #[cfg(windows)]
+    pub fn set_only_v6(&self, only_v6: bool) -> io::Result<()> {
+        self.inner.set_only_v6(only_v6)
+    }
+
+    #[cfg(not(windows))]
     pub fn only_v6(&self) -> io::Result<bool> {
         let raw: c_int = getsockopt(&self.inner, c::IPPROTO_IPV6, c::IPV6_V6ONLY)?;
         Ok(raw != 0)
     }
 
+    #[cfg(windows)]
+    pub fn only_v6(&self) -> io::Result<bool> {
+        self.inner.only_v6()
+    }
+
     pub fn take_error(&self) -> io::Result<Option<io::Error>> {
         self.inner.take_error()
     }
@@ -489,8 +531,14 @@ pub fn bind(addr: io::Result<&SocketAddr>) -> io::Result<UdpSocket> {
         init();
 
         let sock = Socket::new(addr, c::SOCK_DGRAM)?;
-        let (addrp, len) = addr.into_inner();
-        cvt(unsafe { c::bind(sock.as_raw(), addrp, len as _) })?;
+        #[cfg(not(windows))]
+        {
+            let (addrp, len) = addr.into_inner();
+            cvt(unsafe { c::bind(sock.as_raw(), addrp, len as _) })?;
+        }
+        // See `net::bind` for the errors.
+        #[cfg(windows)]
+        crate::sys::net::bind(&sock, addr)?;
         Ok(UdpSocket { inner: sock })
     }
 
@@ -582,7 +630,7 @@ pub fn set_multicast_ttl_v4(&self, multicast_ttl_v4: u32) -> io::Result<()> {
             &self.inner,
             c::IPPROTO_IP,
             c::IP_MULTICAST_TTL,
-            multicast_ttl_v4 as IpV4MultiCastType,
+            checked_ttl(multicast_ttl_v4)? as IpV4MultiCastType,
         )
     }
 
@@ -633,7 +681,7 @@ pub fn leave_multicast_v6(&self, multiaddr: &Ipv6Addr, interface: u32) -> io::Re
     }
 
     pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
-        setsockopt(&self.inner, c::IPPROTO_IP, c::IP_TTL, ttl as c_int)
+        setsockopt(&self.inner, c::IPPROTO_IP, c::IP_TTL, checked_ttl(ttl)?)
     }
 
     pub fn ttl(&self) -> io::Result<u32> {